@@ -0,0 +1,252 @@
+//! On-disk cache of exporter output.
+//!
+//! Each entry is keyed by the source file, the arguments it is compiled with,
+//! the clang version and the exporter version. Next to the CBOR blob we record
+//! every file the translation unit pulled in (the source file itself and all
+//! of its headers) together with a hash of its contents, so that an entry is
+//! only reused while none of those files changed. Both are SHA-256 digests,
+//! which stay the same across Rust releases and runs, unlike the hashes of
+//! `std::hash`. Units including a file that cannot be read back are not
+//! cached, since a change to it would go unnoticed.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use sha2::{Digest, Sha256};
+
+use crate::clang_ast::AstContext;
+use crate::{get_ast_cbor, get_clang_version, process_cbor};
+
+/// The files a cache entry was made from, with the digests of their contents
+type Manifest = Vec<(PathBuf, String)>;
+
+/// Directory holding cached exporter output.
+#[derive(Debug, Clone)]
+pub struct AstCache {
+    dir: PathBuf,
+}
+
+impl AstCache {
+    /// Open the cache in `dir`, creating the directory if needed.
+    pub fn new(dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Like [`crate::get_untyped_ast`], but reuses the cached exporter output
+    /// for `file_path` if neither it nor any file it includes changed since
+    /// it was cached. `directory` and `compile_args` are the working
+    /// directory and the arguments from the compilation database entry of
+    /// `file_path`; relative paths of included files are resolved against
+    /// the former, and the latter are only used to key the cache.
+    pub fn get_untyped_ast(
+        &self,
+        file_path: &Path,
+        cc_db: &Path,
+        extra_args: &[&str],
+        directory: &Path,
+        compile_args: &[String],
+        debug: bool,
+    ) -> Result<AstContext, Error> {
+        let key = cache_key(file_path, extra_args, compile_args);
+
+        if let Some(buffer) = self.load(&key) {
+            if let Ok(cxt) = process_cbor(&buffer) {
+                return Ok(cxt);
+            }
+        }
+
+        let buffer = get_ast_cbor(file_path, cc_db, extra_args, debug)?;
+        let cxt = process_cbor(&buffer)?;
+
+        let inputs = cxt.files.iter().filter_map(|file| file.path.as_deref());
+        if let Err(e) = self.store(&key, &buffer, directory, inputs) {
+            warn!(
+                "Could not cache exporter output for {}: {}",
+                file_path.display(),
+                e
+            );
+        }
+
+        Ok(cxt)
+    }
+
+    fn cbor_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.cbor", key))
+    }
+
+    fn inputs_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.inputs.json", key))
+    }
+
+    /// Return the cached CBOR for `key` if every recorded input file still
+    /// has the contents it had when the entry was written.
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        let manifest = fs::read(self.inputs_path(key)).ok()?;
+        let manifest: Manifest = serde_json::from_slice(&manifest).ok()?;
+        for (path, hash) in manifest {
+            if file_hash(&path).ok()? != hash {
+                return None;
+            }
+        }
+        fs::read(self.cbor_path(key)).ok()
+    }
+
+    fn store<'a>(
+        &self,
+        key: &str,
+        buffer: &[u8],
+        directory: &Path,
+        inputs: impl Iterator<Item = &'a Path>,
+    ) -> Result<(), Error> {
+        let mut manifest = Manifest::new();
+        for path in inputs {
+            // Files clang makes up, like `<built-in>`, cannot change
+            if is_builtin(path) {
+                continue;
+            }
+            let path = directory.join(path);
+            let hash = file_hash(&path).map_err(|e| {
+                Error::new(e.kind(), format!("cannot read {}: {}", path.display(), e))
+            })?;
+            manifest.push((path, hash));
+        }
+        let manifest =
+            serde_json::to_vec(&manifest).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        // Write the CBOR before the manifest: an entry without a manifest is
+        // never loaded, so a run that is interrupted here leaves no stale entry.
+        write_atomically(&self.cbor_path(key), buffer)?;
+        write_atomically(&self.inputs_path(key), &manifest)
+    }
+}
+
+fn cache_key(file_path: &Path, extra_args: &[&str], compile_args: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    // Prefix every field with its length, so that no two different keys
+    // hash the same bytes
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    field(env!("CARGO_PKG_VERSION").as_bytes());
+    field(get_clang_version().as_bytes());
+    field(file_path.to_string_lossy().as_bytes());
+    field(&(extra_args.len() as u64).to_le_bytes());
+    for arg in extra_args {
+        field(arg.as_bytes());
+    }
+    field(&(compile_args.len() as u64).to_le_bytes());
+    for arg in compile_args {
+        field(arg.as_bytes());
+    }
+
+    let stem = file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("unknown");
+    format!("{}-{}", stem, hex(&hasher.finalize()))
+}
+
+/// Whether `path` names one of the buffers clang makes up rather than a file,
+/// like `<built-in>` and `<command line>`
+fn is_builtin(path: &Path) -> bool {
+    path.to_str()
+        .map_or(false, |path| path.starts_with('<') && path.ends_with('>'))
+}
+
+fn file_hash(path: &Path) -> Result<String, Error> {
+    let contents = fs::read(path)?;
+    Ok(hex(&Sha256::digest(&contents)))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".tmp{}", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache and a source directory in a fresh temporary directory
+    fn setup(name: &str) -> (AstCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("ast-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("include")).unwrap();
+        fs::write(src.join("main.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(src.join("include/util.h"), "int util(void);\n").unwrap();
+        (AstCache::new(&dir.join("cache")).unwrap(), src)
+    }
+
+    fn inputs() -> Vec<&'static Path> {
+        ["main.c", "include/util.h", "<built-in>"]
+            .iter()
+            .map(Path::new)
+            .collect()
+    }
+
+    #[test]
+    fn hit() {
+        let (cache, src) = setup("hit");
+        assert_eq!(cache.load("key"), None);
+        cache
+            .store("key", b"cbor", &src, inputs().into_iter())
+            .unwrap();
+        assert_eq!(cache.load("key"), Some(b"cbor".to_vec()));
+        assert_eq!(cache.load("other"), None);
+    }
+
+    #[test]
+    fn header_change_invalidates() {
+        let (cache, src) = setup("invalidate");
+        cache
+            .store("key", b"cbor", &src, inputs().into_iter())
+            .unwrap();
+        fs::write(src.join("include/util.h"), "long util(void);\n").unwrap();
+        assert_eq!(cache.load("key"), None);
+    }
+
+    #[test]
+    fn removed_file_invalidates() {
+        let (cache, src) = setup("removed");
+        cache
+            .store("key", b"cbor", &src, inputs().into_iter())
+            .unwrap();
+        fs::remove_file(src.join("include/util.h")).unwrap();
+        assert_eq!(cache.load("key"), None);
+    }
+
+    #[test]
+    fn unreadable_file_is_not_cached() {
+        let (cache, src) = setup("unreadable");
+        let inputs = [Path::new("main.c"), Path::new("missing.h")];
+        assert!(cache
+            .store("key", b"cbor", &src, inputs.into_iter())
+            .is_err());
+        assert_eq!(cache.load("key"), None);
+    }
+
+    #[test]
+    fn paths_with_separators() {
+        let (cache, src) = setup("separators");
+        fs::write(src.join("tab\tand\nnewline.h"), "").unwrap();
+        let inputs = [Path::new("main.c"), Path::new("tab\tand\nnewline.h")];
+        cache
+            .store("key", b"cbor", &src, inputs.into_iter())
+            .unwrap();
+        assert_eq!(cache.load("key"), Some(b"cbor".to_vec()));
+    }
+}
@@ -4,13 +4,17 @@ use std::ffi::{CStr, CString};
 use std::io::{Error, ErrorKind};
 use std::path::Path;
 use std::slice;
+use std::sync::Mutex;
 
+pub mod cache;
 pub mod clang_ast;
 
+/// The exporter parses its command line through global LLVM state, so only
+/// one translation unit can be exported at a time.
+static EXPORTER_LOCK: Mutex<()> = Mutex::new(());
+
 pub fn get_clang_major_version() -> Option<u32> {
-    let s = unsafe { CStr::from_ptr(clang_version()) };
-    s.to_str()
-        .unwrap()
+    get_clang_version()
         .split('.')
         .next()
         .unwrap()
@@ -18,24 +22,39 @@ pub fn get_clang_major_version() -> Option<u32> {
         .ok()
 }
 
+/// Full version string of the clang the exporter is linked against.
+pub fn get_clang_version() -> &'static str {
+    let s = unsafe { CStr::from_ptr(clang_version()) };
+    s.to_str().unwrap()
+}
+
 pub fn get_untyped_ast(
     file_path: &Path,
     cc_db: &Path,
     extra_args: &[&str],
     debug: bool,
 ) -> Result<clang_ast::AstContext, Error> {
-    let cbors = get_ast_cbors(file_path, cc_db, extra_args, debug);
-    let buffer = cbors
-        .values()
-        .next()
-        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Could not parse input file"))?;
+    let buffer = get_ast_cbor(file_path, cc_db, extra_args, debug)?;
+    process_cbor(&buffer)
+}
 
-    // let cbor_path = file_path.with_extension("cbor");
-    // let mut cbor_file = File::create(&cbor_path)?;
-    // cbor_file.write_all(&buffer[..])?;
-    // eprintln!("Dumped CBOR to {}", cbor_path.to_string_lossy());
+/// Run the exporter on `file_path` and return the raw CBOR it produced.
+fn get_ast_cbor(
+    file_path: &Path,
+    cc_db: &Path,
+    extra_args: &[&str],
+    debug: bool,
+) -> Result<Vec<u8>, Error> {
+    get_ast_cbors(file_path, cc_db, extra_args, debug)
+        .into_values()
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Could not parse input file"))
+}
 
-    let items: Value = from_slice(&buffer[..]).unwrap();
+/// Decode exporter CBOR output into an untyped AST.
+fn process_cbor(buffer: &[u8]) -> Result<clang_ast::AstContext, Error> {
+    let items: Value =
+        from_slice(buffer).map_err(|e| Error::new(ErrorKind::InvalidData, format!("{:}", e)))?;
 
     match clang_ast::process(items) {
         Ok(cxt) => Ok(cxt),
@@ -62,6 +81,7 @@ fn get_ast_cbors(
 
     let args_ptrs: Vec<*const libc::c_char> = args_owned.iter().map(|x| x.as_ptr()).collect();
 
+    let _guard = EXPORTER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     let hashmap;
     unsafe {
         let ptr = ast_exporter(
@@ -1,18 +1,21 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
 
+use deps_builder::unresolved::LIBC_LIBRARIES;
 use deps_builder::DependencyGraph;
 use handlebars::Handlebars;
 use pathdiff::diff_paths;
 use serde_derive::Serialize;
 use serde_json::json;
 
-use super::compile_cmds::LinkCmd;
+use super::compile_cmds::{CompileCmd, LinkCmd};
 use super::TranspilerConfig;
 use crate::get_module_name;
+use crate::translator::translate_dispatcher;
 use crate::CrateSet;
 use crate::ExternCrateDetails;
 use crate::PragmaSet;
@@ -78,6 +81,59 @@ pub struct CrateConfig<'lcmd> {
     pub pragmas: PragmaSet,
     pub crates: CrateSet,
     pub link_cmd: &'lcmd LinkCmd,
+    /// C files to compile and link into the crate instead of translating them
+    pub native_inputs: Vec<Rc<CompileCmd>>,
+    /// The binaries' crates and the crate of the modules they share, if the
+    /// binaries don't get the modules they need prepended
+    pub common: Option<CommonCrateConfig>,
+    /// The binary running the applet `argv[0]` names, for a crate of applets
+    pub dispatcher: Option<PathBuf>,
+    /// The crates of other projects the crate's files use symbols of
+    pub extern_crates: Vec<String>,
+}
+
+/// A library crate with the modules that more than one binary of a crate
+/// needs, which the binaries link in instead of compiling the modules again.
+/// Every binary gets a crate of its own for the modules only it needs.
+pub struct CommonCrateConfig {
+    pub crate_name: String,
+    /// Modules more than one binary needs
+    pub modules: Vec<PathBuf>,
+    /// Main modules of the binaries
+    pub binaries: Vec<PathBuf>,
+}
+
+impl CommonCrateConfig {
+    /// The name of the common crate of `crate_name`. The hyphen keeps it
+    /// apart from the names of binaries, which are Rust identifiers.
+    pub fn crate_name(crate_name: &str) -> String {
+        format!("{}-common", crate_name)
+    }
+
+    /// The name to `use` the common crate by
+    pub fn crate_rust_name(crate_name: &str) -> String {
+        Self::crate_name(crate_name).replace('-', "_")
+    }
+
+    fn crate_dir(&self) -> PathBuf {
+        Path::new("crates").join(&self.crate_name)
+    }
+
+    fn binary_crate_dir(binary: &Path) -> PathBuf {
+        Path::new("crates").join(get_module_name(binary, true, false, false).unwrap())
+    }
+
+    /// Directories of the common crate and the binaries' crates, relative to
+    /// the directory of the crate they are split from
+    pub fn crate_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.crate_dir()];
+        dirs.extend(
+            self.binaries
+                .iter()
+                .map(|binary| Self::binary_crate_dir(binary)),
+        );
+        dirs
+    }
 }
 
 /// Emit `Cargo.toml` and `lib.rs` for a library or `main.rs` for a binary.
@@ -104,22 +160,37 @@ pub fn emit_build_files<'lcmd>(
             .unwrap_or_else(|_| panic!("couldn't create build directory: {}", build_dir.display()));
     }
 
+    let native_libs = crate_cfg
+        .as_ref()
+        .map_or_else(Vec::new, |ccfg| native_libraries(ccfg, dependency_graph));
     emit_cargo_toml(
         tcfg,
         &reg,
         build_dir,
         &crate_cfg,
         workspace_members,
+        &native_libs,
         &dependency_graph,
     );
     if tcfg.translate_valist {
         emit_rust_toolchain(tcfg, build_dir);
     }
     crate_cfg.and_then(|ccfg| {
+        if let Some(common) = &ccfg.common {
+            emit_common_crates(
+                tcfg,
+                &reg,
+                build_dir,
+                &ccfg,
+                common,
+                &native_libs,
+                dependency_graph,
+            );
+        }
         if tcfg.emit_no_lib {
-            emit_build_rs(tcfg, &reg, build_dir, ccfg.link_cmd)
+            emit_build_rs(tcfg, &reg, build_dir, &ccfg, &native_libs)
         } else {
-            emit_build_rs(tcfg, &reg, build_dir, ccfg.link_cmd);
+            emit_build_rs(tcfg, &reg, build_dir, &ccfg, &native_libs);
             emit_lib_rs(
                 tcfg,
                 &reg,
@@ -128,6 +199,8 @@ pub fn emit_build_files<'lcmd>(
                 ccfg.pragmas,
                 &ccfg.crates,
                 &dependency_graph,
+                &ccfg.extern_crates,
+                ccfg.common.as_ref(),
             )
         }
     })
@@ -139,42 +212,55 @@ struct Module {
     name: String,
     open: bool,
     close: bool,
+    /// The path of the module in the common crate, which the crate
+    /// re-exports instead of declaring the module again
+    reexport: Option<String>,
 }
 
 #[derive(Debug, Default)]
-struct ModuleTree(BTreeMap<String, ModuleTree>);
+struct ModuleTree {
+    children: BTreeMap<String, ModuleTree>,
+    /// Whether the module belongs to the common crate; set on leaves
+    common: bool,
+}
 
 impl ModuleTree {
+    /// Whether the module and all modules inside it belong to the common
+    /// crate
+    fn is_common(&self) -> bool {
+        if self.children.is_empty() {
+            self.common
+        } else {
+            self.children.values().all(ModuleTree::is_common)
+        }
+    }
+
     /// Convert the tree representation into a linear vector
-    /// and push it into `res`
-    fn linearize(&self, res: &mut Vec<Module>) {
-        for (name, child) in self.0.iter() {
-            child.linearize_internal(name, res);
+    /// and push it into `res`. Modules of the common crate are re-exported
+    /// from the module `common_path` of that crate.
+    fn linearize(&self, res: &mut Vec<Module>, common_path: Option<&str>) {
+        for (name, child) in self.children.iter() {
+            child.linearize_internal(name, res, common_path);
         }
     }
 
-    fn linearize_internal(&self, name: &str, res: &mut Vec<Module>) {
-        if self.0.is_empty() {
-            res.push(Module {
-                name: name.to_string(),
-                path: None,
-                open: false,
-                close: false,
-            });
+    fn linearize_internal(&self, name: &str, res: &mut Vec<Module>, common_path: Option<&str>) {
+        let common_path = common_path.map(|path| format!("{}::{}", path, name));
+        let module = |open, close, reexport| Module {
+            name: name.to_string(),
+            path: None,
+            open,
+            close,
+            reexport,
+        };
+        if common_path.is_some() && self.is_common() {
+            res.push(module(false, false, common_path));
+        } else if self.children.is_empty() {
+            res.push(module(false, false, None));
         } else {
-            res.push(Module {
-                name: name.to_string(),
-                path: None,
-                open: true,
-                close: false,
-            });
-            self.linearize(res);
-            res.push(Module {
-                name: name.to_string(),
-                path: None,
-                open: false,
-                close: true,
-            });
+            res.push(module(true, false, None));
+            self.linearize(res, common_path.as_deref());
+            res.push(module(false, true, None));
         }
     }
 }
@@ -186,12 +272,16 @@ enum ModuleSubset {
     //Both,
 }
 
+/// The module declarations of a crate with `modules`. The ones `common`
+/// holds are re-exported from the common crate instead, so that they are
+/// compiled once.
 fn convert_module_list(
     tcfg: &TranspilerConfig,
     build_dir: &Path,
     mut modules: Vec<PathBuf>,
     module_subset: ModuleSubset,
     dependency_graph: &DependencyGraph,
+    common: Option<&CommonCrateConfig>,
 ) -> Vec<Module> {
     modules.retain(|m| {
         let is_binary = tcfg.is_binary(
@@ -207,8 +297,10 @@ fn convert_module_list(
         is_binary == is_binary_subset
     });
 
+    let common_crate = common.map(|common| common.crate_name.replace('-', "_"));
+    let is_common = |m: &PathBuf| common.map_or(false, |common| common.modules.contains(m));
     let mut res = vec![];
-    let mut module_tree = ModuleTree(BTreeMap::new());
+    let mut module_tree = ModuleTree::default();
     for m in &modules {
         match m.strip_prefix(build_dir) {
             Ok(relpath)
@@ -225,23 +317,31 @@ fn convert_module_list(
                 for sm in relpath.iter() {
                     let path = Path::new(sm);
                     let name = get_module_name(path, true, false, false).unwrap();
-                    cur = cur.0.entry(name).or_default();
+                    cur = cur.children.entry(name).or_default();
                 }
+                cur.common = is_common(m);
             }
             _ => {
                 let relpath = diff_paths(m, build_dir).unwrap();
                 let path = Some(relpath.to_str().unwrap().to_string());
                 let name = get_module_name(m, true, false, false).unwrap();
+                let reexport = match &common_crate {
+                    Some(common_crate) if is_common(m) => {
+                        Some(format!("{}::{}", common_crate, name))
+                    }
+                    _ => None,
+                };
                 res.push(Module {
                     path,
                     name,
                     open: false,
                     close: false,
+                    reexport,
                 });
             }
         }
     }
-    module_tree.linearize(&mut res);
+    module_tree.linearize(&mut res, common_crate.as_deref());
     res
 }
 
@@ -249,6 +349,38 @@ fn convert_dependencies_list(crates: CrateSet) -> Vec<ExternCrateDetails> {
     crates.into_iter().map(|dep| dep.into()).collect()
 }
 
+/// The dependencies of a crate in `dir` on the crates of other projects
+/// named `extern_crates`, next to the ones on the crates in `crates`
+fn crate_dependencies(
+    tcfg: &TranspilerConfig,
+    crates: CrateSet,
+    extern_crates: &[String],
+    dir: &Path,
+) -> serde_json::Value {
+    let mut dependencies = json!(convert_dependencies_list(crates));
+    for name in extern_crates {
+        let project = tcfg
+            .extern_projects
+            .iter()
+            .find(|project| project.crate_name() == *name)
+            .expect("missing project of an extern crate");
+        let path = diff_paths(&project.crate_dir, dir).unwrap_or_else(|| project.crate_dir.clone());
+        dependencies.as_array_mut().unwrap().push(json!({
+            "name": name,
+            "path": path.to_str().unwrap(),
+        }));
+    }
+    dependencies
+}
+
+/// The names to `use` the crates of other projects by
+fn extern_crate_idents(extern_crates: &[String]) -> Vec<String> {
+    extern_crates
+        .iter()
+        .map(|name| name.replace('-', "_"))
+        .collect()
+}
+
 fn get_lib_rs_file_name(tcfg: &TranspilerConfig) -> &str {
     if tcfg.output_dir.is_some() {
         "lib.rs"
@@ -257,16 +389,136 @@ fn get_lib_rs_file_name(tcfg: &TranspilerConfig) -> &str {
     }
 }
 
-/// Emit `build.rs` to make it easier to link in native libraries
+/// A native library the original link command linked in
+#[derive(Debug, Serialize)]
+struct NativeLibrary {
+    name: String,
+    /// `static` if the original link command linked the library statically
+    kind: Option<&'static str>,
+    /// Whether `build.rs` looks the library up with pkg-config before
+    /// falling back to linking it by name
+    pkg_config: bool,
+}
+
+/// The `build.rs` template data for the native sources and libraries of a
+/// crate
+fn build_rs_json(
+    native_sources: Vec<NativeSource>,
+    library_dirs: &[PathBuf],
+    native_libs: &[NativeLibrary],
+) -> serde_json::Value {
+    json!({
+        "native_sources": native_sources,
+        "library_dirs": library_dirs,
+        "libraries": native_libs,
+        "pkg_config": uses_pkg_config(native_libs),
+    })
+}
+
+/// Whether `build.rs` probes pkg-config and needs the `pkg-config` crate
+fn uses_pkg_config(native_libs: &[NativeLibrary]) -> bool {
+    native_libs.iter().any(|lib| lib.pkg_config)
+}
+
+/// The libraries of the crate's link command that its translated units may
+/// need. Libraries outside of the build are dropped if no unresolved symbol
+/// of the crate can come from them, except for libc's own libraries, whose
+/// functions the `libc` crate binds. Hybrid crates keep all of them for their
+/// C files.
+fn native_libraries(
+    crate_cfg: &CrateConfig,
+    dependency_graph: &DependencyGraph,
+) -> Vec<NativeLibrary> {
+    let link_cmd = crate_cfg.link_cmd;
+    let nodes = crate_cfg
+        .modules
+        .iter()
+        .filter_map(|module| {
+            dependency_graph.get_node_index_with_output(&module.to_str().unwrap().to_string())
+        })
+        .collect::<Vec<_>>();
+    let keep_all = nodes.is_empty() || !crate_cfg.native_inputs.is_empty();
+    let needed = dependency_graph.external_dependencies(&nodes).libraries;
+    let is_external = |lib: &String| {
+        nodes
+            .iter()
+            .any(|&i| dependency_graph.nodes[i].link_libs.contains(lib))
+    };
+    link_cmd
+        .libs
+        .iter()
+        .filter(|lib| {
+            keep_all
+                || !is_external(lib)
+                || LIBC_LIBRARIES.contains(&lib.as_str())
+                || needed.contains(lib)
+        })
+        .map(|lib| {
+            let is_static = link_cmd.static_libs.contains(lib);
+            NativeLibrary {
+                name: lib.clone(),
+                kind: if is_static { Some("static") } else { None },
+                pkg_config: !LIBC_LIBRARIES.contains(&lib.as_str()),
+            }
+        })
+        .collect()
+}
+
+/// A C file compiled by `build.rs` with the `cc` crate. All strings are
+/// Rust string literals, quotes included.
+#[derive(Debug, Serialize)]
+struct NativeSource {
+    file: String,
+    flags: Vec<String>,
+    /// Name of the static library the file is compiled into
+    lib_name: String,
+}
+
+fn native_sources(native_inputs: &[Rc<CompileCmd>]) -> Vec<NativeSource> {
+    let literal = |s: &str| format!("{:?}", s);
+    let path_literal = |path: &Path| literal(path.to_str().unwrap());
+    let mut lib_names = HashSet::new();
+    native_inputs
+        .iter()
+        .map(|cmd| {
+            let file = cmd.abs_file();
+
+            // `foo.c` becomes `libfoo_c.a`
+            let name = get_module_name(&file, false, false, false).unwrap();
+            let mut lib_name = name.clone();
+            let mut n = 1;
+            while !lib_names.insert(lib_name.clone()) {
+                n += 1;
+                lib_name = format!("{}{}", name, n);
+            }
+
+            NativeSource {
+                file: path_literal(&file),
+                flags: cmd
+                    .native_flags()
+                    .iter()
+                    .map(|flag| literal(flag))
+                    .collect(),
+                lib_name: literal(&lib_name),
+            }
+        })
+        .collect()
+}
+
+/// Emit `build.rs` to make it easier to link in native libraries, and to
+/// compile the C files of hybrid crates
 fn emit_build_rs(
     tcfg: &TranspilerConfig,
     reg: &Handlebars,
     build_dir: &Path,
-    link_cmd: &LinkCmd,
+    crate_cfg: &CrateConfig,
+    native_libs: &[NativeLibrary],
 ) -> Option<PathBuf> {
-    let json = json!({
-        "libraries": link_cmd.libs,
-    });
+    let json = build_rs_json(
+        native_sources(&crate_cfg.native_inputs),
+        &crate_cfg.link_cmd.lib_dirs,
+        native_libs,
+    );
     let output = reg.render("build.rs", &json).unwrap();
     let output_path = build_dir.join("build.rs");
     maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
@@ -282,6 +534,8 @@ fn emit_lib_rs(
     pragmas: PragmaSet,
     crates: &CrateSet,
     dependency_graph: &DependencyGraph,
+    extern_crates: &[String],
+    common: Option<&CommonCrateConfig>,
 ) -> Option<PathBuf> {
     let modules = convert_module_list(
         tcfg,
@@ -289,6 +543,7 @@ fn emit_lib_rs(
         modules,
         ModuleSubset::Libraries,
         &dependency_graph,
+        common,
     );
     let crates = convert_dependencies_list(crates.clone());
     let file_name = get_lib_rs_file_name(tcfg);
@@ -299,6 +554,7 @@ fn emit_lib_rs(
         "modules": modules,
         "pragmas": pragmas,
         "crates": crates,
+        "extern_crates": extern_crate_idents(extern_crates),
     });
 
     let output_path = build_dir.join(file_name);
@@ -307,6 +563,113 @@ fn emit_lib_rs(
     maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
 }
 
+/// Emit the common crate of `common` and a crate for each of its binaries,
+/// in the directories `CommonCrateConfig::crate_dirs` names. The root file of
+/// the common crate goes next to `lib.rs`, so that it declares the same
+/// module tree.
+fn emit_common_crates(
+    tcfg: &TranspilerConfig,
+    reg: &Handlebars,
+    build_dir: &Path,
+    crate_cfg: &CrateConfig,
+    common: &CommonCrateConfig,
+    native_libs: &[NativeLibrary],
+    dependency_graph: &DependencyGraph,
+) {
+    let crates = convert_dependencies_list(crate_cfg.crates.clone());
+    let relative_path =
+        |path: &Path, dir: &Path| diff_paths(path, dir).unwrap().to_str().unwrap().to_string();
+    let create_dir = |dir: &Path| {
+        fs::create_dir_all(dir)
+            .unwrap_or_else(|_| panic!("couldn't create crate directory: {}", dir.display()));
+    };
+
+    let common_dir = build_dir.join(common.crate_dir());
+    create_dir(&common_dir);
+    let root_file = build_dir.join(format!("{}.rs", common.crate_name));
+    let modules = convert_module_list(
+        tcfg,
+        build_dir,
+        common.modules.clone(),
+        ModuleSubset::Libraries,
+        dependency_graph,
+        None,
+    );
+    let json = json!({
+        "reorganize_definitions": tcfg.reorganize_definitions,
+        "translate_valist": tcfg.translate_valist,
+        "modules": modules,
+        "pragmas": crate_cfg.pragmas,
+        "crates": crates,
+        "extern_crates": extern_crate_idents(&crate_cfg.extern_crates),
+    });
+    let output = reg.render("lib.rs", &json).unwrap();
+    maybe_write_to_file(&root_file, output, tcfg.overwrite_existing);
+
+    // The common crate links in the native libraries for the binaries
+    let mut build_dependencies = vec![];
+    if uses_pkg_config(native_libs) {
+        build_dependencies.push(json!({"name": "pkg-config", "version": "0.3"}));
+    }
+    let json = json!({
+        "is_crate": true,
+        "crate_name": common.crate_name,
+        "crate_rust_name": common.crate_name.replace('-', "_"),
+        "crate_types": "\"rlib\"",
+        "is_library": true,
+        "lib_rs_file": relative_path(&root_file, &common_dir),
+        "dependencies": crate_dependencies(
+            tcfg,
+            crate_cfg.crates.clone(),
+            &crate_cfg.extern_crates,
+            &common_dir,
+        ),
+        "build_dependencies": build_dependencies,
+    });
+    let output = reg.render("Cargo.toml", &json).unwrap();
+    maybe_write_to_file(
+        &common_dir.join("Cargo.toml"),
+        output,
+        tcfg.overwrite_existing,
+    );
+    let json = build_rs_json(vec![], &crate_cfg.link_cmd.lib_dirs, native_libs);
+    let output = reg.render("build.rs", &json).unwrap();
+    maybe_write_to_file(
+        &common_dir.join("build.rs"),
+        output,
+        tcfg.overwrite_existing,
+    );
+
+    for binary in &common.binaries {
+        let binary_dir = build_dir.join(CommonCrateConfig::binary_crate_dir(binary));
+        create_dir(&binary_dir);
+        let mut binary_dependencies = crate_dependencies(
+            tcfg,
+            crate_cfg.crates.clone(),
+            &crate_cfg.extern_crates,
+            &binary_dir,
+        );
+        binary_dependencies.as_array_mut().unwrap().push(json!({
+            "name": common.crate_name,
+            "path": relative_path(&common_dir, &binary_dir),
+        }));
+        let name = get_module_name(binary, true, false, false).unwrap();
+        let json = json!({
+            "is_crate": true,
+            "crate_name": format!("{}-{}", crate_cfg.crate_name, name),
+            "is_library": false,
+            "binaries": [{"path": relative_path(binary, &binary_dir), "name": name}],
+            "dependencies": binary_dependencies,
+        });
+        let output = reg.render("Cargo.toml", &json).unwrap();
+        maybe_write_to_file(
+            &binary_dir.join("Cargo.toml"),
+            output,
+            tcfg.overwrite_existing,
+        );
+    }
+}
+
 /// Get lib for binary. Returns `String`
 /// to the generated file or `None` if the output file exists.
 pub fn get_lib(
@@ -316,6 +679,8 @@ pub fn get_lib(
     pragmas: PragmaSet,
     crates: &CrateSet,
     dependency_graph: &DependencyGraph,
+    common_crate: Option<&str>,
+    extern_crates: &[String],
 ) -> String {
     let mut reg = Handlebars::new();
     reg.register_template_string("lib.rs", include_str!("lib.rs.hbs"))
@@ -327,6 +692,7 @@ pub fn get_lib(
         modules,
         ModuleSubset::Libraries,
         &dependency_graph,
+        None,
     );
     let crates = convert_dependencies_list(crates.clone());
     let file_name = get_lib_rs_file_name(tcfg);
@@ -337,11 +703,59 @@ pub fn get_lib(
         "modules": modules,
         "pragmas": pragmas,
         "crates": crates,
+        "common_crate": common_crate,
+        "extern_crates": extern_crate_idents(extern_crates),
     });
 
     reg.render("lib.rs", &json).unwrap()
 }
 
+/// Emit the binary of a multi-call crate, which runs the applet `argv[0]`
+/// names. Every applet is given as its name and the module with the `main`
+/// of its entry function, `modules` are the modules to prepend and
+/// `extern_crates` the crates of other projects to link. Returns
+/// the path to the generated file or `None` if the output file exists.
+pub fn emit_dispatcher(
+    tcfg: &TranspilerConfig,
+    build_dir: &Path,
+    crate_name: &str,
+    applets: &[(String, PathBuf)],
+    modules: Vec<PathBuf>,
+    pragmas: PragmaSet,
+    crates: &CrateSet,
+    dependency_graph: &DependencyGraph,
+    extern_crates: &[String],
+) -> Option<PathBuf> {
+    let applets = applets
+        .iter()
+        .map(|(name, module)| (name.clone(), module_path(build_dir, module)))
+        .collect::<Vec<_>>();
+    let output = get_lib(
+        tcfg,
+        build_dir,
+        modules,
+        pragmas,
+        crates,
+        dependency_graph,
+        None,
+        extern_crates,
+    ) + &translate_dispatcher(&applets);
+    let output_path = build_dir.join(format!("{}.rs", crate_name.replace('-', "_")));
+    maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
+}
+
+/// The path of a library module in a crate declaring its modules like
+/// `convert_module_list`.
+fn module_path(build_dir: &Path, module: &Path) -> Vec<String> {
+    match module.strip_prefix(build_dir) {
+        Ok(relpath) => relpath
+            .iter()
+            .map(|sm| get_module_name(Path::new(sm), true, false, false).unwrap())
+            .collect(),
+        Err(_) => vec![get_module_name(module, true, false, false).unwrap()],
+    }
+}
+
 /// If we translate variadic functions, the output will only compile
 /// on a nightly toolchain until the `c_variadics` feature is stable.
 fn emit_rust_toolchain(tcfg: &TranspilerConfig, build_dir: &Path) {
@@ -356,6 +770,7 @@ fn emit_cargo_toml<'lcmd>(
     build_dir: &Path,
     crate_cfg: &Option<CrateConfig<'lcmd>>,
     workspace_members: Option<Vec<String>>,
+    native_libs: &[NativeLibrary],
     dependency_graph: &DependencyGraph,
 ) {
     // rust_checks_path is gone because we don't want to refer to the source
@@ -366,14 +781,50 @@ fn emit_cargo_toml<'lcmd>(
         "workspace_members": workspace_members.unwrap_or_default(),
     });
     if let Some(ccfg) = crate_cfg {
-        let binaries = convert_module_list(
-            tcfg,
-            build_dir,
-            ccfg.modules.to_owned(),
-            ModuleSubset::Binaries,
-            dependency_graph,
-        );
-        let dependencies = convert_dependencies_list(ccfg.crates.clone());
+        // Split off binaries are built by their own crates
+        let mut binaries = if ccfg.common.is_some() {
+            vec![]
+        } else {
+            convert_module_list(
+                tcfg,
+                build_dir,
+                ccfg.modules.to_owned(),
+                ModuleSubset::Binaries,
+                dependency_graph,
+                None,
+            )
+        };
+        if let Some(dispatcher) = &ccfg.dispatcher {
+            binaries.push(Module {
+                path: Some(
+                    diff_paths(dispatcher, build_dir)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                ),
+                name: ccfg.crate_name.clone(),
+                open: false,
+                close: false,
+                reexport: None,
+            });
+        }
+        let mut dependencies =
+            crate_dependencies(tcfg, ccfg.crates.clone(), &ccfg.extern_crates, build_dir);
+        // The library re-exports the modules of the common crate
+        if let Some(common) = ccfg.common.as_ref().filter(|_| !tcfg.emit_no_lib) {
+            dependencies.as_array_mut().unwrap().push(json!({
+                "name": common.crate_name,
+                "path": common.crate_dir().to_str().unwrap(),
+            }));
+        }
+        let mut build_dependencies = vec![];
+        if !ccfg.native_inputs.is_empty() {
+            build_dependencies.push(json!({"name": "cc", "version": "1.0"}));
+        }
+        if uses_pkg_config(native_libs) {
+            build_dependencies.push(json!({"name": "pkg-config", "version": "0.3"}));
+        }
         let crate_json = if tcfg.emit_no_lib {
             json!({
                 "crate_name": ccfg.crate_name,
@@ -382,6 +833,7 @@ fn emit_cargo_toml<'lcmd>(
                 "is_library": false,
                 "binaries": binaries,
                 "dependencies": dependencies,
+                "build_dependencies": build_dependencies,
             })
         } else {
             json!({
@@ -392,6 +844,7 @@ fn emit_cargo_toml<'lcmd>(
                 "lib_rs_file": get_lib_rs_file_name(tcfg),
                 "binaries": binaries,
                 "dependencies": dependencies,
+                "build_dependencies": build_dependencies,
             })
         };
         json.as_object_mut().unwrap().extend(
@@ -426,3 +879,45 @@ fn maybe_write_to_file(output_path: &Path, output: String, overwrite: bool) -> O
 
     Some(PathBuf::from(output_path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_build_rs(native_libs: &[NativeLibrary]) -> String {
+        let mut reg = Handlebars::new();
+        reg.register_template_string("build.rs", include_str!("build.rs.hbs"))
+            .unwrap();
+        let json = build_rs_json(vec![], &[PathBuf::from("/opt/lib")], native_libs);
+        reg.render("build.rs", &json).unwrap()
+    }
+
+    fn library(name: &str, is_static: bool, pkg_config: bool) -> NativeLibrary {
+        NativeLibrary {
+            name: name.to_owned(),
+            kind: if is_static { Some("static") } else { None },
+            pkg_config,
+        }
+    }
+
+    #[test]
+    fn pkg_config_probes_in_build_rs() {
+        let build_rs = render_build_rs(&[library("acl", true, true), library("c", false, false)]);
+        assert!(build_rs.contains("fn link_pkg_config(lib: &str, statik: bool) -> bool {"));
+        assert!(build_rs.contains("    if !link_pkg_config(\"acl\", true) {\n        println!(\"cargo:rustc-link-lib=static=acl\");\n    }\n"));
+        assert!(build_rs.contains("    println!(\"cargo:rustc-link-lib=c\");\n"));
+        assert!(build_rs.contains("    println!(\"cargo:rustc-link-search=native=/opt/lib\");\n"));
+    }
+
+    #[test]
+    fn no_pkg_config_without_probes() {
+        let native_libs = [library("m", false, false)];
+        assert!(!uses_pkg_config(&native_libs));
+        let build_rs = render_build_rs(&native_libs);
+        assert!(!build_rs.contains("pkg_config"));
+        assert!(
+            build_rs.starts_with("#[cfg(all(unix, not(target_os = \"macos\")))]\nfn main() {\n")
+        );
+        assert!(build_rs.contains("    println!(\"cargo:rustc-link-lib=m\");\n"));
+    }
+}
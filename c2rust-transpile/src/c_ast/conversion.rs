@@ -4,7 +4,7 @@ use c2rust_ast_exporter::clang_ast::*;
 use failure::err_msg;
 use serde_bytes::ByteBuf;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 use std::vec::Vec;
 
 use super::Located;
@@ -1009,7 +1009,7 @@ impl ConversionContext {
                     let substmt_old = node.children[0].expect("Label sub-statement not found");
                     let substmt = self.visit_stmt(substmt_old);
 
-                    let label_name = from_value::<Rc<str>>(node.extras[0].clone())
+                    let label_name = from_value::<Arc<str>>(node.extras[0].clone())
                         .expect("unnamed label in C source code");
                     if let Some(old_label_name) = self
                         .typed_context
@@ -7,7 +7,7 @@ use std::fmt::{self, Debug, Display};
 use std::mem;
 use std::ops::Index;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub use c2rust_ast_exporter::clang_ast::{BuiltinVaListKind, SrcFile, SrcLoc, SrcSpan};
 
@@ -67,8 +67,12 @@ pub struct TypedAstContext {
     // that location.
     include_map: Vec<Vec<SrcLoc>>,
 
+    // The `#include` edges between files, as pairs of the including and the
+    // included FileId.
+    include_edges: Vec<(FileId, FileId)>,
+
     // Names of the labels defined in the C source code.
-    pub label_names: IndexMap<CLabelId, Rc<str>>,
+    pub label_names: IndexMap<CLabelId, Arc<str>>,
 
     // map expressions to the stack of macros they were expanded from
     pub macro_invocations: HashMap<CExprId, Vec<CDeclId>>,
@@ -166,6 +170,17 @@ impl TypedAstContext {
             include_map.push(include_path);
         }
 
+        // A file included more than once has a `SrcFile` for every inclusion
+        let mut include_edges = vec![];
+        for (clang_id, file) in clang_files.iter().enumerate() {
+            if let Some(include_loc) = &file.include_loc {
+                let edge = (file_map[include_loc.fileid as usize], file_map[clang_id]);
+                if !include_edges.contains(&edge) {
+                    include_edges.push(edge);
+                }
+            }
+        }
+
         TypedAstContext {
             c_types: HashMap::new(),
             c_exprs: HashMap::new(),
@@ -177,6 +192,7 @@ impl TypedAstContext {
             files,
             file_map,
             include_map,
+            include_edges,
             parents: HashMap::new(),
             macro_invocations: HashMap::new(),
             macro_expansions: HashMap::new(),
@@ -238,6 +254,12 @@ impl TypedAstContext {
         self.include_map[file].first().map(|loc| loc.line)
     }
 
+    /// The `#include` edges between files, as pairs of the including and the
+    /// included file.
+    pub fn include_edges(&self) -> &[(FileId, FileId)] {
+        &self.include_edges
+    }
+
     pub fn find_file_id(&self, path: &Path) -> Option<FileId> {
         self.files
             .iter()
@@ -61,7 +61,7 @@ use crate::cfg::multiples::*;
 pub enum Label {
     /// Some labels come directly from the C side (namely those created from labels, cases, and
     /// defaults). For those, we just re-use the `CLabelId` of the C AST node.
-    FromC(CLabelId, Option<Rc<str>>),
+    FromC(CLabelId, Option<Arc<str>>),
 
     /// Most labels are synthetically created while unwrapping control-flow constructs (like loops)
     /// into basic blocks.
@@ -654,7 +654,7 @@ impl Cfg<Label, StmtOrDecl> {
 }
 
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// The polymorphism here is only to make it clear exactly how little these functions need to know
 /// about the actual contents of the CFG - we only actual call these on one monomorphic CFG type.
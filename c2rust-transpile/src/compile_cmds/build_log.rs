@@ -0,0 +1,478 @@
+//! Recover link steps from a captured build log (`make V=1` or `ninja -v`
+//! output) or from a JSON link database with the same layout as
+//! `compile_commands.json`, for builds whose compilation database only
+//! describes the compile steps.
+
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+use failure::{format_err, Error};
+use log::warn;
+use regex::Regex;
+use serde_derive::Deserialize;
+
+use super::{LinkCmd, LinkType};
+
+/// One entry of a JSON link database.
+#[derive(Deserialize, Debug)]
+struct LoggedCommand {
+    directory: PathBuf,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    arguments: Vec<String>,
+}
+
+/// Read the link commands recorded in `path`. Files ending in `.json` are
+/// read as a link database, anything else as a build log.
+pub fn read_link_commands(path: &Path) -> Result<Vec<LinkCmd>, Error> {
+    let base_dir = path
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let parser = LinkCommandParser::new();
+
+    let lcmds = if path.extension().map_or(false, |ext| ext == "json") {
+        let f = std::io::BufReader::new(File::open(path)?);
+        let cmds: Vec<LoggedCommand> = serde_json::from_reader(f)?;
+        cmds.into_iter()
+            .filter_map(|cmd| {
+                let args = if !cmd.arguments.is_empty() {
+                    cmd.arguments
+                } else {
+                    split_command(cmd.command.as_deref().unwrap_or_default())
+                };
+                let directory = base_dir.join(&cmd.directory);
+                parser.parse(&args, &directory)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        let log = fs::read_to_string(path)?;
+        parse_build_log(&parser, &log, &base_dir)
+    };
+
+    if lcmds.is_empty() {
+        return Err(format_err!(
+            "no link commands found in {}; was the build run with `make V=1` or `ninja -v`?",
+            path.display()
+        ));
+    }
+    Ok(lcmds)
+}
+
+/// Extract the link commands from the text of a build log. Relative paths are
+/// resolved against the directory `make` reports it is in, starting from
+/// `base_dir`.
+fn parse_build_log(parser: &LinkCommandParser, log: &str, base_dir: &Path) -> Vec<LinkCmd> {
+    let make_dir =
+        Regex::new(r"^g?make(\[\d+\])?: (Entering|Leaving) directory [`'](.*)'$").unwrap();
+    let ninja_progress = Regex::new(r"^\[\d+/\d+\]\s*").unwrap();
+
+    let mut dir_stack = vec![base_dir.to_path_buf()];
+    let mut lcmds = vec![];
+    for line in log.lines() {
+        let line = line.trim();
+        if let Some(caps) = make_dir.captures(line) {
+            if &caps[2] == "Entering" {
+                dir_stack.push(PathBuf::from(&caps[3]));
+            } else if dir_stack.len() > 1 {
+                dir_stack.pop();
+            }
+            continue;
+        }
+
+        // libtool echoes the commands it runs behind a `libtool: link:` prefix
+        let line = ninja_progress.replace(line, "");
+        let line = line.strip_prefix("libtool: link: ").unwrap_or(&line);
+
+        // A line may chain several commands, e.g. `cd src && cc -o prog ...`
+        let mut directory = dir_stack.last().unwrap().clone();
+        let args = split_command(line);
+        for cmd in args.split(|arg| arg == "&&" || arg == ";" || arg == "||") {
+            match cmd {
+                [cd, dir] if cd == "cd" => directory = normalize(&directory.join(dir)),
+                _ => lcmds.extend(parser.parse(cmd, &directory)),
+            }
+        }
+    }
+    lcmds
+}
+
+fn split_command(command: &str) -> Vec<String> {
+    shlex::split(command).unwrap_or_else(|| {
+        warn!("Could not split build command: {}", command);
+        command.split_whitespace().map(String::from).collect()
+    })
+}
+
+struct LinkCommandParser {
+    linker: Regex,
+    archiver: Regex,
+}
+
+impl LinkCommandParser {
+    fn new() -> Self {
+        Self {
+            linker: Regex::new(
+                r"^(.*-)?(cc|gcc|clang|c\+\+|g\+\+|clang\+\+|ld(\.\w+)?)(-[\d.]+)?$",
+            )
+            .unwrap(),
+            archiver: Regex::new(r"^(.*-)?ar$").unwrap(),
+        }
+    }
+
+    /// Interpret `args` as a link step run in `directory`: either a compiler
+    /// driver or linker invocation that produces an executable or shared
+    /// object, or an `ar` invocation that produces a static library.
+    fn parse(&self, args: &[String], directory: &Path) -> Option<LinkCmd> {
+        // Skip compiler wrappers
+        let args = match args {
+            [wrapper, rest @ ..] if matches!(program_name(wrapper), "ccache" | "distcc") => rest,
+            _ => args,
+        };
+        let program = program_name(args.first()?);
+        let resolve = |path: &str| normalize(&directory.join(path));
+
+        if self.archiver.is_match(program) {
+            // ar <operation> <archive> <members...>
+            let mut operands = args[1..].iter();
+            let operation = operands.next()?.trim_start_matches('-');
+            if !operation.contains(['r', 'q']) {
+                return None;
+            }
+            let mut operands = operands.filter(|arg| !arg.starts_with('-'));
+            let output = resolve(operands.next()?);
+            return Some(link_cmd(
+                output,
+                operands.map(|input| resolve(input)).collect(),
+                Libraries::default(),
+                vec![],
+                LinkType::Static,
+            ));
+        }
+
+        if !self.linker.is_match(program) {
+            return None;
+        }
+
+        let mut output = None;
+        let mut inputs = vec![];
+        let mut libs = Libraries::default();
+        let mut lib_dirs = vec![];
+        let mut flags = vec![];
+        let mut link_type = LinkType::Exe;
+        let mut all_static = false;
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // Not a link step
+                "-c" | "-S" | "-E" | "-r" | "-Wl,-r" | "-M" | "-MM" => return None,
+                "-shared" => link_type = LinkType::Shared,
+                "-static" => {
+                    all_static = true;
+                    flags.push(arg.clone());
+                }
+                "-Bstatic" | "-Wl,-Bstatic" | "-dn" => libs.link_static = true,
+                "-Bdynamic" | "-Wl,-Bdynamic" | "-dy" => libs.link_static = false,
+                "-o" => output = args.next().map(|output| resolve(output)),
+                "-l" => {
+                    if let Some(lib) = args.next() {
+                        libs.push(lib);
+                    }
+                }
+                "-L" => lib_dirs.extend(args.next().map(|dir| resolve(dir))),
+                // Flags whose value is a separate argument
+                "-MF" | "-MT" | "-MQ" | "-x" | "-include" | "-isystem" | "-I" | "-D" | "-U" => {
+                    args.next();
+                }
+                // Linker options whose value is a separate argument
+                "-Xlinker" | "-T" | "-z" | "-u" | "-e" => {
+                    flags.push(arg.clone());
+                    flags.extend(args.next().cloned());
+                }
+                _ => {
+                    if let Some(lib) = arg.strip_prefix("-l") {
+                        libs.push(lib);
+                    } else if let Some(dir) = arg.strip_prefix("-L") {
+                        lib_dirs.push(resolve(dir));
+                    } else if let Some(out) = arg.strip_prefix("-o") {
+                        output = Some(resolve(out));
+                    } else if !arg.starts_with('-') {
+                        if is_link_input(arg) {
+                            inputs.push(resolve(arg));
+                        }
+                    } else if is_link_flag(arg) {
+                        flags.push(arg.clone());
+                    }
+                }
+            }
+        }
+
+        // Compiling and linking C sources in one go isn't something we can map
+        // onto compile commands, so we only accept links of object files.
+        if inputs.is_empty() {
+            return None;
+        }
+        if all_static {
+            libs.static_libs = libs.libs.clone();
+        }
+        let output = output.unwrap_or_else(|| directory.join("a.out"));
+        Some(LinkCmd {
+            flags,
+            ..link_cmd(output, inputs, libs, lib_dirs, link_type)
+        })
+    }
+}
+
+/// The `-l` libraries of a link command, tracking which of them the linker
+/// was told to link statically.
+#[derive(Default)]
+struct Libraries {
+    libs: Vec<String>,
+    static_libs: Vec<String>,
+    /// Whether we are between `-Bstatic` and `-Bdynamic`
+    link_static: bool,
+}
+
+impl Libraries {
+    /// Add the library named by the `-l` argument `lib`, which is either a
+    /// library name or, with a leading `:`, a file name like `libfoo.a`.
+    fn push(&mut self, lib: &str) {
+        let (name, is_static) = match lib.strip_prefix(':') {
+            Some(file_name) => {
+                let path = Path::new(file_name);
+                let stem = path.file_stem().and_then(|stem| stem.to_str());
+                let name = stem.unwrap_or(file_name).split('.').next().unwrap();
+                let name = name.strip_prefix("lib").unwrap_or(name);
+                let is_static = path.extension().map_or(false, |ext| ext == "a");
+                (name, is_static)
+            }
+            None => (lib, self.link_static),
+        };
+        self.libs.push(name.to_owned());
+        if is_static {
+            self.static_libs.push(name.to_owned());
+        }
+    }
+}
+
+fn link_cmd(
+    output: PathBuf,
+    inputs: Vec<PathBuf>,
+    libs: Libraries,
+    lib_dirs: Vec<PathBuf>,
+    r#type: LinkType,
+) -> LinkCmd {
+    let to_string = |path: PathBuf| path.to_str().map(String::from);
+    LinkCmd {
+        inputs: inputs.into_iter().filter_map(to_string).collect(),
+        output: to_string(output),
+        libs: libs.libs,
+        static_libs: libs.static_libs,
+        lib_dirs,
+        flags: vec![],
+        r#type,
+        cmd_inputs: vec![],
+        native_inputs: vec![],
+        top_level: false,
+    }
+}
+
+fn program_name(arg: &str) -> &str {
+    Path::new(arg)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(arg)
+}
+
+/// Whether the option `arg` of a compiler driver affects linking. Options
+/// that only matter for compiling, like `-O2` or `-Wall`, are left out.
+fn is_link_flag(arg: &str) -> bool {
+    arg.starts_with("-Wl,")
+        || arg.starts_with("-fuse-ld=")
+        || arg.starts_with("-fsanitize")
+        || matches!(
+            arg,
+            "-pthread"
+                | "-rdynamic"
+                | "-pie"
+                | "-no-pie"
+                | "-static-pie"
+                | "-static-libgcc"
+                | "-nostdlib"
+                | "-nodefaultlibs"
+                | "-nostartfiles"
+                | "-fopenmp"
+                | "-fprofile-arcs"
+                | "--coverage"
+                | "-m32"
+                | "-m64"
+        )
+}
+
+fn is_link_input(arg: &str) -> bool {
+    let ext = Path::new(arg).extension().and_then(|ext| ext.to_str());
+    matches!(ext, Some("o" | "obj" | "a" | "lo" | "la" | "so")) || arg.contains(".so.")
+}
+
+/// Lexically normalize `path` by removing `.` components and resolving `..`
+/// against the preceding component, without touching the file system.
+pub(super) fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match res.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    res.pop();
+                }
+                // There is nothing above the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => res.push(component),
+            },
+            _ => res.push(component),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str, directory: &str) -> Option<LinkCmd> {
+        LinkCommandParser::new().parse(&split_command(command), Path::new(directory))
+    }
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn executable() {
+        let lcmd = parse(
+            "cc -O2 -o prog main.o ../util/util.o -L../lib -lm -Wl,-Bstatic -lfoo -Wl,-Bdynamic \
+             -l:libbar.a -l z",
+            "/build/src",
+        )
+        .unwrap();
+        assert_eq!(lcmd.r#type, LinkType::Exe);
+        assert_eq!(lcmd.output.as_deref(), Some("/build/src/prog"));
+        assert_eq!(
+            lcmd.inputs,
+            strings(&["/build/src/main.o", "/build/util/util.o"])
+        );
+        assert_eq!(lcmd.libs, strings(&["m", "foo", "bar", "z"]));
+        assert_eq!(lcmd.static_libs, strings(&["foo", "bar"]));
+        assert_eq!(lcmd.lib_dirs, vec![PathBuf::from("/build/lib")]);
+    }
+
+    #[test]
+    fn linker_flag_spellings() {
+        let lcmd = parse("ld -Bstatic -lfoo -Bdynamic -lbar -l:libbaz.so.1 a.o", "/b").unwrap();
+        assert_eq!(lcmd.libs, strings(&["foo", "bar", "baz"]));
+        assert_eq!(lcmd.static_libs, strings(&["foo"]));
+        assert_eq!(lcmd.output.as_deref(), Some("/b/a.out"));
+    }
+
+    #[test]
+    fn fully_static() {
+        let lcmd = parse("gcc -static -o prog prog.o -lm -lz", "/b").unwrap();
+        assert_eq!(lcmd.static_libs, strings(&["m", "z"]));
+        assert_eq!(lcmd.flags, strings(&["-static"]));
+    }
+
+    #[test]
+    fn link_flags() {
+        let lcmd = parse(
+            "gcc -O2 -g -Wall -pthread -o prog prog.o -Wl,--as-needed -Xlinker -rpath \
+             -Xlinker /opt/lib -z now -lm",
+            "/b",
+        )
+        .unwrap();
+        assert_eq!(
+            lcmd.flags,
+            strings(&[
+                "-pthread",
+                "-Wl,--as-needed",
+                "-Xlinker",
+                "-rpath",
+                "-Xlinker",
+                "/opt/lib",
+                "-z",
+                "now",
+            ])
+        );
+        assert_eq!(lcmd.inputs, strings(&["/b/prog.o"]));
+    }
+
+    #[test]
+    fn shared_library() {
+        let lcmd = parse(
+            "ccache x86_64-linux-gnu-gcc-12 -shared -olibx.so a.o b.lo",
+            "/b",
+        )
+        .unwrap();
+        assert_eq!(lcmd.r#type, LinkType::Shared);
+        assert_eq!(lcmd.output.as_deref(), Some("/b/libx.so"));
+        assert_eq!(lcmd.inputs, strings(&["/b/a.o", "/b/b.lo"]));
+    }
+
+    #[test]
+    fn archive() {
+        let lcmd = parse("ar rcs lib/libutil.a x.o ./y.o", "/b").unwrap();
+        assert_eq!(lcmd.r#type, LinkType::Static);
+        assert_eq!(lcmd.output.as_deref(), Some("/b/lib/libutil.a"));
+        assert_eq!(lcmd.inputs, strings(&["/b/x.o", "/b/y.o"]));
+        assert!(parse("ar t lib/libutil.a", "/b").is_none());
+    }
+
+    #[test]
+    fn not_link_steps() {
+        assert!(parse("cc -c a.c -o a.o", "/b").is_none());
+        assert!(parse("cc -r -o all.o a.o b.o", "/b").is_none());
+        assert!(parse("cc -o prog prog.c", "/b").is_none());
+        assert!(parse("install -m 755 prog.o /usr/bin", "/b").is_none());
+    }
+
+    #[test]
+    fn build_log() {
+        let log = "\
+make[1]: Entering directory '/build/lib'
+ar rc libutil.a util.o
+make[1]: Leaving directory '/build/lib'
+make[1]: Entering directory '/build/src'
+cc -c -o main.o main.c
+cd tools && cc -o ../bin/tool tool.o -L../../lib -lutil
+libtool: link: gcc -shared -o .libs/libx.so x.o
+make[1]: Leaving directory '/build/src'
+[3/4] clang -o prog prog.o
+";
+        let lcmds = parse_build_log(&LinkCommandParser::new(), log, Path::new("/build"));
+        let outputs = lcmds
+            .iter()
+            .map(|lcmd| lcmd.output.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            vec![
+                "/build/lib/libutil.a",
+                "/build/src/bin/tool",
+                "/build/src/.libs/libx.so",
+                "/build/prog",
+            ]
+        );
+        assert_eq!(lcmds[1].inputs, strings(&["/build/src/tools/tool.o"]));
+        assert_eq!(lcmds[1].lib_dirs, vec![PathBuf::from("/build/lib")]);
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("../a")), PathBuf::from("../a"));
+        assert_eq!(normalize(Path::new("../../a")), PathBuf::from("../../a"));
+        assert_eq!(normalize(Path::new("a/../../b")), PathBuf::from("../b"));
+        assert_eq!(normalize(Path::new("/../a")), PathBuf::from("/a"));
+    }
+}
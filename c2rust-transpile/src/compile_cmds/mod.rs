@@ -8,6 +8,8 @@ use log::warn;
 use regex::Regex;
 use serde_derive::Deserialize;
 
+mod build_log;
+
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct CompileCmd {
     /// The working directory of the compilation. All paths specified in the command
@@ -22,14 +24,18 @@ pub struct CompileCmd {
     /// to rerun the exact compilation step for the translation unit in the environment
     /// the build system uses. Parameters use shell quoting and shell escaping of quotes,
     /// with ‘"’ and ‘\’ being the only special characters. Shell expansion is not supported.
-    #[serde(skip_deserializing)]
-    _command: Option<String>,
+    #[serde(default)]
+    command: Option<String>,
     /// The compile command executed as list of strings. Either arguments or command is required.
-    #[serde(default, skip_deserializing)]
-    _arguments: Vec<String>,
+    #[serde(default)]
+    arguments: Vec<String>,
     /// The name of the output created by this compilation step. This field is optional. It can
     /// be used to distinguish different processing modes of the same input file.
     output: Option<String>,
+    /// The object file the command produces, for configurations of a file
+    /// whose `output` was changed to tell them apart
+    #[serde(skip)]
+    object: Option<PathBuf>,
 }
 
 impl CompileCmd {
@@ -44,6 +50,120 @@ impl CompileCmd {
         }
     }
 
+    /// The working directory of the compilation
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The compile command as a list of arguments, starting with the compiler.
+    /// Taken from `arguments` if present, otherwise `command` is split using
+    /// shell quoting rules.
+    pub fn args(&self) -> Vec<String> {
+        if !self.arguments.is_empty() {
+            return self.arguments.clone();
+        }
+        let command = match self.command {
+            Some(ref command) => command,
+            None => return vec![],
+        };
+        shlex::split(command).unwrap_or_else(|| {
+            warn!(
+                "Could not split compile command for {}: {}",
+                self.file.display(),
+                command
+            );
+            command.split_whitespace().map(String::from).collect()
+        })
+    }
+
+    /// The preprocessor and language flags of this compile command.
+    pub fn flags(&self) -> CompileFlags {
+        CompileFlags::parse(&self.args(), &self.directory)
+    }
+
+    /// The arguments to compile this command's file with another driver:
+    /// everything but the compiler, the input file, the output and the flags
+    /// selecting what the compiler produces. Relative paths of include
+    /// options are made relative to the working directory.
+    pub fn native_flags(&self) -> Vec<String> {
+        let input = build_log::normalize(&self.directory.join(&self.file));
+        let absolute = |path: &str| self.directory.join(path).to_str().unwrap().to_owned();
+        let mut flags = vec![];
+        let args = self.args();
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "-S" | "-E" => {}
+                "-o" | "-MF" | "-MT" | "-MQ" => {
+                    args.next();
+                }
+                "-I" | "-isystem" | "-iquote" | "-idirafter" | "-include" | "-imacros" => {
+                    flags.push(arg.clone());
+                    flags.extend(args.next().map(|path| absolute(path)));
+                }
+                _ => {
+                    if arg.starts_with("-o") || arg.starts_with("-M") {
+                        continue;
+                    }
+                    if let Some(dir) = arg.strip_prefix("-I") {
+                        flags.push(format!("-I{}", absolute(dir)));
+                    } else if arg.starts_with('-')
+                        || build_log::normalize(&self.directory.join(arg)) != input
+                    {
+                        flags.push(arg.clone());
+                    }
+                }
+            }
+        }
+        flags
+    }
+
+    /// A copy of this command whose output is named after the object file it
+    /// produces, used to tell apart configurations of a file that share an
+    /// output. With `n` above 1, the object's stem gets `-n` appended for
+    /// configurations that share the object file as well. `object_path`
+    /// stays the real object file, so that link commands still find it.
+    fn with_configuration_output(&self, n: usize) -> Self {
+        let object = self.object_path();
+        let output = if n > 1 {
+            let stem = object.file_stem().unwrap().to_str().unwrap();
+            object.with_file_name(format!("{}-{}.o", stem, n))
+        } else {
+            object.clone()
+        };
+        Self {
+            output: Some(output.to_str().unwrap().to_owned()),
+            object: Some(object),
+            ..self.clone()
+        }
+    }
+
+    /// The object file this command produces, taken from `output` or the `-o`
+    /// argument and defaulting to the compiler's `<stem>.o`. Unlike
+    /// `abs_output_file`, this does not require the file to exist.
+    fn object_path(&self) -> PathBuf {
+        if let Some(object) = &self.object {
+            return object.clone();
+        }
+        let output = self.output.clone().or_else(|| {
+            let args = self.args();
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                match arg.strip_prefix("-o") {
+                    Some("") => return args.next().cloned(),
+                    Some(output) => return Some(output.to_owned()),
+                    None => {}
+                }
+            }
+            None
+        });
+        let output = output.unwrap_or_else(|| {
+            let stem = self.file.file_stem().unwrap().to_str().unwrap();
+            format!("{}.o", stem)
+        });
+        build_log::normalize(&self.directory.join(output))
+    }
+
     pub fn abs_output_file(&self) -> Option<PathBuf> {
         match self.output {
             Some(ref output) => {
@@ -62,6 +182,59 @@ impl CompileCmd {
     }
 }
 
+/// Preprocessor and language flags of a compile command, i.e. the ones that
+/// change what clang sees when it parses the translation unit.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompileFlags {
+    /// Macros defined with `-D`, as `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    /// Macros undefined with `-U`
+    pub undefines: Vec<String>,
+    /// Include directories from `-I`, `-iquote`, `-isystem` and `-idirafter`
+    pub include_dirs: Vec<PathBuf>,
+    /// Files included ahead of the source file with `-include`
+    pub includes: Vec<PathBuf>,
+    /// Language standard selected with `-std=`
+    pub std: Option<String>,
+}
+
+impl CompileFlags {
+    /// Extract the flags from `args` (which start with the compiler), making
+    /// relative paths relative to `directory`.
+    fn parse(args: &[String], directory: &Path) -> Self {
+        let mut flags = Self::default();
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            // Options are matched by their whole name first, so that longer
+            // ones like `-include-pch` are not taken for a shorter one with
+            // an attached value
+            let mut value = || args.next().cloned();
+            match arg.as_str() {
+                "-D" => flags.defines.extend(value()),
+                "-U" => flags.undefines.extend(value()),
+                "-include" => flags
+                    .includes
+                    .extend(value().map(|include| directory.join(include))),
+                "-isystem" | "-iquote" | "-idirafter" | "-I" => flags
+                    .include_dirs
+                    .extend(value().map(|dir| directory.join(dir))),
+                _ => {
+                    if let Some(std) = arg.strip_prefix("-std=") {
+                        flags.std = Some(std.to_owned());
+                    } else if let Some(define) = arg.strip_prefix("-D") {
+                        flags.defines.push(define.to_owned());
+                    } else if let Some(undefine) = arg.strip_prefix("-U") {
+                        flags.undefines.push(undefine.to_owned());
+                    } else if let Some(dir) = arg.strip_prefix("-I") {
+                        flags.include_dirs.push(directory.join(dir));
+                    }
+                }
+            }
+        }
+        flags
+    }
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum LinkType {
@@ -97,19 +270,62 @@ pub struct LinkCmd {
     pub output: Option<String>,
     /// List of libraries to link in (without `-l` prefix)
     pub libs: Vec<String>,
+    /// The libraries in `libs` that the original command linked statically
+    #[serde(default)]
+    pub static_libs: Vec<String>,
     /// List of library directories
     pub lib_dirs: Vec<PathBuf>,
+    /// The other options of the original command, like `-pthread`, in order
+    #[serde(default)]
+    pub flags: Vec<String>,
     /// What type of binary we're building
     pub r#type: LinkType,
     /// Input files in `CompileCmd` form
     #[serde(default)]
     pub cmd_inputs: Vec<Rc<CompileCmd>>,
+    /// Input files that are not translated, in `CompileCmd` form; in hybrid
+    /// builds these are compiled as C and linked into the crate
+    #[serde(default)]
+    pub native_inputs: Vec<Rc<CompileCmd>>,
     #[serde(default)]
     pub top_level: bool,
 }
 
-/// Convert a linear vector of `CompileCmd`s into a DAG of `LinkCmd`s and `CompileCmd`s
-fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error> {
+impl LinkCmd {
+    /// The arguments that make a compiler driver link objects the way this
+    /// command did: its options, library directories and libraries, with the
+    /// ones it linked statically between `-Wl,-Bstatic` and `-Wl,-Bdynamic`.
+    pub fn link_args(&self) -> Vec<String> {
+        let mut args = self.flags.clone();
+        args.extend(
+            self.lib_dirs
+                .iter()
+                .map(|dir| format!("-L{}", dir.display())),
+        );
+        let mut link_static = false;
+        for lib in &self.libs {
+            let is_static = self.static_libs.contains(lib);
+            if is_static != link_static {
+                let mode = if is_static { "-Bstatic" } else { "-Bdynamic" };
+                args.push(format!("-Wl,{}", mode));
+                link_static = is_static;
+            }
+            args.push(format!("-l{}", lib));
+        }
+        if link_static {
+            args.push("-Wl,-Bdynamic".to_owned());
+        }
+        args
+    }
+}
+
+/// Convert a linear vector of `CompileCmd`s into a DAG of `LinkCmd`s and `CompileCmd`s.
+/// Link steps come from `/c2rust/link/` entries in `v` and from `logged`, the
+/// link commands recovered from a build log.
+fn build_link_commands(
+    mut v: Vec<Rc<CompileCmd>>,
+    logged: Vec<LinkCmd>,
+) -> Result<Vec<LinkCmd>, Error> {
     let mut output_map = HashMap::new();
     for (idx, ccmd) in v.iter().enumerate() {
         if let Some(ref output) = ccmd.output {
@@ -139,7 +355,9 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
         seen_ccmds.insert(idx);
     }
 
-    // TODO: add binaries
+    if !logged.is_empty() {
+        res.extend(attach_logged_link_commands(&v, logged, &mut seen_ccmds));
+    }
 
     // Check if we have left-over compile commands; if we do,
     // bind them to the crate itself (which becomes a `staticlib` or `rlib`)
@@ -154,9 +372,12 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
             inputs: v.iter().filter_map(|ccmd| ccmd.output.clone()).collect(),
             output: None,
             libs: vec![],
+            static_libs: vec![],
             lib_dirs: vec![],
+            flags: vec![],
             r#type: LinkType::Static,
             cmd_inputs: v,
+            native_inputs: vec![],
             top_level: true,
         };
         res.push(lcmd);
@@ -165,16 +386,284 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
     Ok(res)
 }
 
+/// Fill in `cmd_inputs` of the link commands recovered from a build log by
+/// matching their inputs against the object files the compile commands in `v`
+/// produce. Static archives from the log that are linked into exactly one other
+/// target are folded into it, so its crate holds everything the target links;
+/// other archives become library crates of their own. An object that several
+/// targets link directly goes into the link command of each of them, so that
+/// each crate has all the modules it needs.
+fn attach_logged_link_commands(
+    v: &[Rc<CompileCmd>],
+    logged: Vec<LinkCmd>,
+    seen_ccmds: &mut HashSet<usize>,
+) -> Vec<LinkCmd> {
+    let links = LoggedLinks::new(v, &logged);
+    let inputs: Vec<Option<(Vec<usize>, HashSet<String>)>> = (0..logged.len())
+        .map(|idx| {
+            if links.folded.contains(&idx) {
+                return None;
+            }
+            let mut inputs = vec![];
+            links.collect_inputs(idx, &mut HashSet::new(), &mut inputs);
+            let folded_libs = links.folded_libs(idx).map(String::from).collect();
+            Some((inputs, folded_libs))
+        })
+        .collect();
+
+    let mut res = vec![];
+    for (mut lcmd, inputs) in logged.into_iter().zip(inputs) {
+        let (inputs, folded_libs) = match inputs {
+            Some(inputs) => inputs,
+            None => continue,
+        };
+        lcmd.libs.retain(|lib| !folded_libs.contains(lib));
+        lcmd.static_libs.retain(|lib| !folded_libs.contains(lib));
+        for ccmd_idx in inputs {
+            seen_ccmds.insert(ccmd_idx);
+            lcmd.cmd_inputs.push(Rc::clone(&v[ccmd_idx]));
+        }
+        // Targets built only from objects we don't translate get no crate
+        if !lcmd.cmd_inputs.is_empty() {
+            res.push(lcmd);
+        }
+    }
+    res
+}
+
+/// Lookup tables relating the link commands of a build log to each other and
+/// to the compile commands producing their inputs.
+struct LoggedLinks<'a> {
+    logged: &'a [LinkCmd],
+    /// Index of the compile command producing each object file
+    objects: HashMap<PathBuf, usize>,
+    /// For each link command, the archives from the log it links, along with
+    /// the `-l` name if it was linked as a library rather than by path
+    linked_archives: Vec<Vec<(usize, Option<&'a str>)>>,
+    /// Archives that are linked into exactly one other target
+    folded: HashSet<usize>,
+}
+
+impl<'a> LoggedLinks<'a> {
+    fn new(v: &[Rc<CompileCmd>], logged: &'a [LinkCmd]) -> Self {
+        let objects = v
+            .iter()
+            .enumerate()
+            .map(|(idx, ccmd)| (ccmd.object_path(), idx))
+            .collect();
+        let archives: HashMap<PathBuf, usize> = logged
+            .iter()
+            .enumerate()
+            .filter(|(_, lcmd)| lcmd.r#type == LinkType::Static)
+            .filter_map(|(idx, lcmd)| Some((PathBuf::from(lcmd.output.as_ref()?), idx)))
+            .collect();
+
+        let linked_archives: Vec<Vec<_>> = logged
+            .iter()
+            .enumerate()
+            .map(|(idx, lcmd)| {
+                let by_path = lcmd
+                    .inputs
+                    .iter()
+                    .filter_map(|inp| Some((*archives.get(Path::new(inp))?, None)));
+                // `-lfoo` picks the first `libfoo.a` on the search path
+                let by_name = lcmd.libs.iter().filter_map(|lib| {
+                    let file_name = format!("lib{}.a", lib);
+                    let archive = lcmd
+                        .lib_dirs
+                        .iter()
+                        .find_map(|dir| archives.get(&dir.join(&file_name)))?;
+                    Some((*archive, Some(lib.as_str())))
+                });
+                by_path
+                    .chain(by_name)
+                    .filter(|&(archive, _)| archive != idx)
+                    .collect()
+            })
+            .collect();
+
+        let mut consumers: HashMap<usize, usize> = HashMap::new();
+        for &(archive, _) in linked_archives.iter().flatten() {
+            *consumers.entry(archive).or_default() += 1;
+        }
+        let folded = consumers
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|(archive, _)| archive)
+            .collect();
+
+        Self {
+            logged,
+            objects,
+            linked_archives,
+            folded,
+        }
+    }
+
+    /// Collect the compile commands of the objects that link command `idx`
+    /// links, descending into the archives folded into it.
+    fn collect_inputs(&self, idx: usize, visited: &mut HashSet<usize>, res: &mut Vec<usize>) {
+        if !visited.insert(idx) {
+            return;
+        }
+        let lcmd = &self.logged[idx];
+        res.extend(
+            lcmd.inputs
+                .iter()
+                .filter_map(|inp| self.objects.get(Path::new(inp))),
+        );
+        for &(archive, _) in &self.linked_archives[idx] {
+            if self.folded.contains(&archive) {
+                self.collect_inputs(archive, visited, res);
+            } else {
+                warn!(
+                    "{} links {}, which is translated as a separate crate",
+                    lcmd.output.as_deref().unwrap_or("a.out"),
+                    self.logged[archive].output.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    /// The `-l` names of the archives folded into link command `idx`; these
+    /// are no longer native libraries once their objects are translated.
+    fn folded_libs(&self, idx: usize) -> impl Iterator<Item = &'a str> + '_ {
+        self.linked_archives[idx]
+            .iter()
+            .filter(|(archive, _)| self.folded.contains(archive))
+            .filter_map(|&(_, lib)| lib)
+    }
+}
+
+/// For each link command, the link commands whose result includes its
+/// objects: itself first, followed by the ones that link it as a library,
+/// directly or through other libraries. Also returns, for each link command,
+/// the `-l` libraries it links that are not built by any of `lcmds`.
+fn link_chains(lcmds: &[LinkCmd]) -> (Vec<Vec<usize>>, Vec<Vec<&str>>) {
+    let libraries: HashMap<&Path, usize> = lcmds
+        .iter()
+        .enumerate()
+        .filter(|(_, lcmd)| lcmd.r#type.is_library())
+        .filter_map(|(idx, lcmd)| Some((Path::new(lcmd.output.as_ref()?), idx)))
+        .collect();
+    let library = |path: &Path| libraries.get(path).copied();
+    let library_by_name = |lcmd: &LinkCmd, lib: &str| {
+        lcmd.lib_dirs.iter().find_map(|dir| {
+            ["a", "so"]
+                .into_iter()
+                .find_map(|ext| library(&dir.join(format!("lib{}.{}", lib, ext))))
+        })
+    };
+
+    // The link commands linking each library, by path or by `-l` name
+    let mut consumers = vec![vec![]; lcmds.len()];
+    let mut external_libs = vec![vec![]; lcmds.len()];
+    for (idx, lcmd) in lcmds.iter().enumerate() {
+        let by_path = lcmd.inputs.iter().map(|input| library(Path::new(input)));
+        let by_name = lcmd.libs.iter().map(|lib| {
+            let built = library_by_name(lcmd, lib);
+            if built.is_none() {
+                external_libs[idx].push(lib.as_str());
+            }
+            built
+        });
+        for lib in by_path.chain(by_name).flatten() {
+            if lib != idx && !consumers[lib].contains(&idx) {
+                consumers[lib].push(idx);
+            }
+        }
+    }
+
+    let chains = (0..lcmds.len())
+        .map(|idx| {
+            let mut chain = vec![];
+            let mut visited = HashSet::new();
+            let mut queue = vec![idx];
+            while let Some(current) = queue.pop() {
+                if !visited.insert(current) {
+                    continue;
+                }
+                chain.push(current);
+                queue.extend(consumers[current].iter().rev());
+            }
+            chain
+        })
+        .collect();
+    (chains, external_libs)
+}
+
+/// For each link command, the outputs of the link commands whose result
+/// includes its objects: its own output first, followed by the targets that
+/// link it as a library, directly or through other libraries.
+pub fn link_targets(lcmds: &[LinkCmd]) -> Vec<Vec<String>> {
+    let (chains, _) = link_chains(lcmds);
+    chains
+        .iter()
+        .map(|chain| {
+            chain
+                .iter()
+                .filter_map(|&idx| lcmds[idx].output.clone())
+                .collect()
+        })
+        .collect()
+}
+
+/// For each link command, the libraries outside of the build that end up
+/// linked with its objects, i.e., the ones linked by any of its
+/// `link_targets`.
+pub fn link_libs(lcmds: &[LinkCmd]) -> Vec<Vec<String>> {
+    let (chains, external_libs) = link_chains(lcmds);
+    chains
+        .iter()
+        .map(|chain| {
+            let mut libs: Vec<String> = vec![];
+            for lib in chain.iter().flat_map(|&idx| &external_libs[idx]) {
+                if !libs.iter().any(|l| l == lib) {
+                    libs.push(lib.to_string());
+                }
+            }
+            libs
+        })
+        .collect()
+}
+
 /// some build scripts repeatedly compile the same input file with different
 /// command line flags thus creating multiple outputs. We remove any duplicates
 /// in the order we see them and warn the user.
-fn filter_duplicate_cmds(v: Vec<Rc<CompileCmd>>) -> Vec<Rc<CompileCmd>> {
+///
+/// With `keep_configurations`, only commands that repeat the exact same flags
+/// are removed, so every configuration gets translated. Configurations that
+/// don't name distinct outputs are named after their object files so they end
+/// up in separate modules.
+fn filter_duplicate_cmds(v: Vec<Rc<CompileCmd>>, keep_configurations: bool) -> Vec<Rc<CompileCmd>> {
     let mut seen = HashSet::new();
+    let mut seen_configurations = HashSet::new();
     let mut cmds = vec![];
 
-    for cmd in v {
+    for mut cmd in v {
         let absf: PathBuf = cmd.abs_file();
         let absof: Option<PathBuf> = cmd.abs_output_file();
+        if keep_configurations && seen_configurations.insert((absf.clone(), cmd.args())) {
+            if seen.contains(&(absf.clone(), absof.clone())) {
+                let mut n = 1;
+                let renamed = loop {
+                    let renamed = cmd.with_configuration_output(n);
+                    if !seen.contains(&(absf.clone(), renamed.abs_output_file())) {
+                        break renamed;
+                    }
+                    n += 1;
+                };
+                warn!(
+                    "Translating another configuration of {} as {}",
+                    absf.display(),
+                    renamed.abs_output_file().unwrap().display()
+                );
+                cmd = Rc::new(renamed);
+            }
+            seen.insert((absf, cmd.abs_output_file()));
+            cmds.push(cmd);
+            continue;
+        }
         if seen.contains(&(absf.clone(), absof.clone())) {
             if let Some(absof) = absof {
                 warn!(
@@ -196,22 +685,31 @@ fn filter_duplicate_cmds(v: Vec<Rc<CompileCmd>>) -> Vec<Rc<CompileCmd>> {
 
 /// Read `compile_commands` file, optionally ignore any entries not matching
 /// `filter`, and filter out any .S files since they're likely assembly files.
+/// With `hybrid`, the entries not matching `filter` are moved to the
+/// `native_inputs` of their link command instead, and link commands left
+/// without inputs to translate are dropped. `keep_configurations` is passed
+/// on to `filter_duplicate_cmds`. Link steps are additionally read from
+/// `link_log`, a build log or link database.
 pub fn get_compile_commands(
     compile_commands: &Path,
     filter: &Option<Regex>,
+    hybrid: bool,
+    keep_configurations: bool,
+    link_log: Option<&Path>,
 ) -> Result<Vec<LinkCmd>, Error> {
     let f = std::io::BufReader::new(File::open(compile_commands)?); // open read-only
 
     // Read the JSON contents of the file as an instance of `Value`
     let v: Vec<Rc<CompileCmd>> = serde_json::from_reader(f)?;
 
-    // apply the filter argument, if any
-    let v = if let &Some(ref re) = filter {
-        v.into_iter()
+    // apply the filter argument, if any; hybrid builds compile the files it
+    // excludes as C, so they are set aside once the link commands are known
+    let v = match filter {
+        Some(re) if !hybrid => v
+            .into_iter()
             .filter(|c| re.is_match(c.file.to_str().unwrap()))
-            .collect::<Vec<Rc<CompileCmd>>>()
-    } else {
-        v
+            .collect::<Vec<Rc<CompileCmd>>>(),
+        _ => v,
     };
 
     // Filter out any assembly files
@@ -224,13 +722,332 @@ pub fn get_compile_commands(
         })
         .collect::<Vec<Rc<CompileCmd>>>();
 
-    let mut lcmds = build_link_commands(v)?;
+    let logged = match link_log {
+        Some(link_log) => build_log::read_link_commands(link_log)?,
+        None => vec![],
+    };
+    let mut lcmds = build_link_commands(v, logged)?;
 
     for lcmd in &mut lcmds {
         let inputs = std::mem::take(&mut lcmd.cmd_inputs);
-        let inputs = filter_duplicate_cmds(inputs);
-        lcmd.cmd_inputs = inputs;
+
+        let (inputs, native_inputs) = match filter {
+            Some(re) if hybrid => inputs
+                .into_iter()
+                .partition(|c| re.is_match(c.file.to_str().unwrap())),
+            _ => (inputs, vec![]),
+        };
+        lcmd.cmd_inputs = filter_duplicate_cmds(inputs, keep_configurations);
+        lcmd.native_inputs = filter_duplicate_cmds(native_inputs, false);
+    }
+    if hybrid {
+        lcmds.retain(|lcmd| !lcmd.cmd_inputs.is_empty());
     }
 
     Ok(lcmds)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn compile_cmd(cmd: serde_json::Value) -> Rc<CompileCmd> {
+        Rc::new(serde_json::from_value(cmd).unwrap())
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn args_from_command() {
+        let cmd = compile_cmd(json!({
+            "directory": "/src",
+            "file": "/src/a.c",
+            "command": "cc -DNAME=\"a b\" -c 'dir/a.c' -o a.o",
+        }));
+        assert_eq!(
+            cmd.args(),
+            strings(&["cc", "-DNAME=a b", "-c", "dir/a.c", "-o", "a.o"])
+        );
+    }
+
+    #[test]
+    fn args_prefer_arguments() {
+        let cmd = compile_cmd(json!({
+            "directory": "/src",
+            "file": "/src/a.c",
+            "command": "gcc -c a.c",
+            "arguments": ["cc", "-DX", "-c", "a.c"],
+        }));
+        assert_eq!(cmd.args(), strings(&["cc", "-DX", "-c", "a.c"]));
+    }
+
+    #[test]
+    fn flags() {
+        let args = strings(&[
+            "cc",
+            "-DA",
+            "-D",
+            "B=1",
+            "-UC",
+            "-std=gnu99",
+            "-I",
+            "inc",
+            "-I../common",
+            "-isystem",
+            "/opt/include",
+            "-iquote",
+            "quoted",
+            "-include",
+            "config.h",
+            "-O2",
+            "-c",
+            "a.c",
+        ]);
+        let flags = CompileFlags::parse(&args, Path::new("/src"));
+        assert_eq!(
+            flags,
+            CompileFlags {
+                defines: strings(&["A", "B=1"]),
+                undefines: strings(&["C"]),
+                include_dirs: vec![
+                    PathBuf::from("/src/inc"),
+                    PathBuf::from("/src/../common"),
+                    PathBuf::from("/opt/include"),
+                    PathBuf::from("/src/quoted"),
+                ],
+                includes: vec![PathBuf::from("/src/config.h")],
+                std: Some("gnu99".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn flags_by_whole_name() {
+        let args = strings(&[
+            "cc",
+            "-undef",
+            "-include-pch",
+            "pre.pch",
+            "-isysroot",
+            "/sdk",
+            "-Iinc",
+            "-c",
+            "a.c",
+        ]);
+        let flags = CompileFlags::parse(&args, Path::new("/src"));
+        assert_eq!(
+            flags,
+            CompileFlags {
+                include_dirs: vec![PathBuf::from("/src/inc")],
+                ..CompileFlags::default()
+            }
+        );
+    }
+
+    #[test]
+    fn flags_skip_compiler() {
+        let flags = CompileFlags::parse(&strings(&["-DCC", "-c", "a.c"]), Path::new("/"));
+        assert_eq!(flags, CompileFlags::default());
+    }
+
+    #[test]
+    fn native_flags() {
+        let cmd = compile_cmd(json!({
+            "directory": "/src/build",
+            "file": "../lib/a.c",
+            "arguments": [
+                "cc", "-DX=1", "-I", "inc", "-I../gen", "-include", "config.h",
+                "-std=gnu99", "-O2", "-MD", "-MF", "a.d", "-c", "../lib/a.c",
+                "-o", "a.o", "-Wall",
+            ],
+        }));
+        assert_eq!(
+            cmd.native_flags(),
+            vec![
+                "-DX=1",
+                "-I",
+                "/src/build/inc",
+                "-I/src/build/../gen",
+                "-include",
+                "/src/build/config.h",
+                "-std=gnu99",
+                "-O2",
+                "-Wall",
+            ]
+        );
+    }
+
+    #[test]
+    fn object_path() {
+        let object = |args: &[&str]| {
+            compile_cmd(json!({
+                "directory": "/src",
+                "file": "/src/a.c",
+                "arguments": args,
+            }))
+            .object_path()
+        };
+        assert_eq!(object(&["cc", "-c", "a.c"]), Path::new("/src/a.o"));
+        assert_eq!(
+            object(&["cc", "-c", "a.c", "-o", "obj/a.o"]),
+            Path::new("/src/obj/a.o")
+        );
+        assert_eq!(object(&["cc", "-c", "a.c", "-o../a.o"]), Path::new("/a.o"));
+    }
+
+    #[test]
+    fn shared_objects_in_every_target() {
+        let objects = ["main1", "main2", "util"]
+            .iter()
+            .map(|name| {
+                compile_cmd(json!({
+                    "directory": "/src",
+                    "file": format!("/src/{}.c", name),
+                    "arguments": ["cc", "-c", format!("{}.c", name)],
+                }))
+            })
+            .collect::<Vec<_>>();
+        let link = |output: &str, inputs: &[&str]| -> LinkCmd {
+            serde_json::from_value(json!({
+                "inputs": inputs,
+                "output": output,
+                "libs": [],
+                "lib_dirs": [],
+                "type": "exe",
+            }))
+            .unwrap()
+        };
+        let logged = vec![
+            link("/src/one", &["/src/main1.o", "/src/util.o"]),
+            link("/src/two", &["/src/main2.o", "/src/util.o"]),
+        ];
+        let mut seen_ccmds = HashSet::new();
+        let lcmds = attach_logged_link_commands(&objects, logged, &mut seen_ccmds);
+        let inputs = lcmds
+            .iter()
+            .map(|lcmd| {
+                lcmd.cmd_inputs
+                    .iter()
+                    .map(|cmd| cmd.file.to_str().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            vec![
+                vec!["/src/main1.c", "/src/util.c"],
+                vec!["/src/main2.c", "/src/util.c"],
+            ]
+        );
+        assert_eq!(seen_ccmds.len(), 3);
+    }
+
+    /// `ls`, `dir` and `vdir` built from one source with different flags
+    fn configurations(output: &[&str]) -> Vec<Rc<CompileCmd>> {
+        ["LS", "DIR", "VDIR"]
+            .iter()
+            .zip(output)
+            .map(|(name, output)| {
+                compile_cmd(json!({
+                    "directory": "/src",
+                    "file": "/src/ls.c",
+                    "arguments": ["cc", format!("-DNAME={}", name), "-c", "ls.c", "-o", output],
+                }))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duplicates_removed() {
+        let cmds = filter_duplicate_cmds(configurations(&["ls.o", "dir.o", "vdir.o"]), false);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].args()[1], "-DNAME=LS");
+    }
+
+    #[test]
+    fn configurations_named_after_output() {
+        let cmds = filter_duplicate_cmds(configurations(&["ls.o", "dir.o", "vdir.o"]), true);
+        let outputs = cmds
+            .iter()
+            .map(|cmd| cmd.abs_output_file())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            vec![
+                None,
+                Some(PathBuf::from("/src/dir.o")),
+                Some(PathBuf::from("/src/vdir.o")),
+            ]
+        );
+        // Link commands find the configurations by their objects
+        let objects = cmds.iter().map(|cmd| cmd.object_path()).collect::<Vec<_>>();
+        assert_eq!(
+            objects,
+            vec![
+                PathBuf::from("/src/ls.o"),
+                PathBuf::from("/src/dir.o"),
+                PathBuf::from("/src/vdir.o"),
+            ]
+        );
+    }
+
+    #[test]
+    fn configurations_sharing_output() {
+        let cmds = filter_duplicate_cmds(configurations(&["ls.o", "ls.o", "ls.o"]), true);
+        let outputs = cmds
+            .iter()
+            .map(|cmd| cmd.abs_output_file())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            vec![
+                None,
+                Some(PathBuf::from("/src/ls.o")),
+                Some(PathBuf::from("/src/ls-2.o")),
+            ]
+        );
+        assert!(cmds
+            .iter()
+            .all(|cmd| cmd.object_path() == Path::new("/src/ls.o")));
+    }
+
+    #[test]
+    fn same_configuration_removed() {
+        let mut cmds = configurations(&["ls.o", "dir.o"]);
+        cmds.push(Rc::clone(&cmds[0]));
+        let cmds = filter_duplicate_cmds(cmds, true);
+        assert_eq!(cmds.len(), 2);
+    }
+
+    #[test]
+    fn link_args() {
+        let lcmd = LinkCmd {
+            inputs: vec![],
+            output: None,
+            libs: strings(&["m", "foo", "bar", "z"]),
+            static_libs: strings(&["foo", "bar"]),
+            lib_dirs: vec![PathBuf::from("/build/lib")],
+            flags: strings(&["-pthread"]),
+            r#type: LinkType::Exe,
+            cmd_inputs: vec![],
+            native_inputs: vec![],
+            top_level: false,
+        };
+        assert_eq!(
+            lcmd.link_args(),
+            strings(&[
+                "-pthread",
+                "-L/build/lib",
+                "-lm",
+                "-Wl,-Bstatic",
+                "-lfoo",
+                "-lbar",
+                "-Wl,-Bdynamic",
+                "-lz",
+            ])
+        );
+    }
+}
@@ -169,6 +169,11 @@ impl TranslationError {
         msg.into()
     }
 
+    /// Source locations the error points at, innermost first
+    pub fn locations(&self) -> &[DisplaySrcSpan] {
+        &self.loc
+    }
+
     pub fn add_loc(mut self, loc: Option<DisplaySrcSpan>) -> Self {
         if let Some(loc) = loc {
             self.loc.push(loc);
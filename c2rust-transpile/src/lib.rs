@@ -6,19 +6,24 @@ mod diagnostics;
 pub mod build_files;
 pub mod c_ast;
 pub mod cfg;
-mod compile_cmds;
+pub mod compile_cmds;
 pub mod convert_type;
 pub mod renamer;
+pub mod report;
 pub mod rust_ast;
 pub mod translator;
 pub mod with_stmts;
 
-use std::collections::{binary_heap, HashSet};
+use std::collections::{binary_heap, HashMap, HashSet};
+use std::fmt::Write as _;
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread;
 
 use build_files::get_lib;
 use failure::Error;
@@ -27,23 +32,36 @@ use log::{info, warn};
 use regex::Regex;
 use serde_derive::Serialize;
 
+use crate::c_ast::iterators::{DFNodes, SomeId};
 use crate::c_ast::Printer;
 use crate::c_ast::*;
 pub use crate::diagnostics::Diagnostic;
 use c2rust_ast_exporter as ast_exporter;
+use c2rust_ast_exporter::cache::AstCache;
 
-use crate::build_files::{emit_build_files, get_build_dir, get_build_dir_raw, CrateConfig};
-use crate::compile_cmds::get_compile_commands;
+use crate::build_files::{
+    emit_build_files, emit_dispatcher, get_build_dir, get_build_dir_raw, CommonCrateConfig,
+    CrateConfig,
+};
+use crate::compile_cmds::{get_compile_commands, link_libs, link_targets, CompileCmd, LinkCmd};
 use crate::convert_type::RESERVED_NAMES;
+use crate::report::{DeclReport, TranslationReport, UnitReport, UnitStatus};
 pub use crate::translator::ReplaceMode;
 use std::prelude::v1::Vec;
 
 type PragmaVec = Vec<(&'static str, Vec<&'static str>)>;
 type PragmaSet = indexmap::IndexSet<(&'static str, &'static str)>;
 type CrateSet = indexmap::IndexSet<ExternCrate>;
-type TranspileResult = Result<(PathBuf, PragmaVec, CrateSet), ()>;
+/// The translated module of a unit, or the status of a unit that was not
+/// translated
+type TranspileResult = Result<(PathBuf, PragmaVec, CrateSet), UnitStatus>;
 
-use deps_builder::{build_dependency, DependencyGraph, DependencyInfo, DependencySymbol};
+use deps_builder::plan::FileMetrics;
+use deps_builder::projects::merge_dependencies;
+use deps_builder::{
+    build_dependency, DependencyGraph, DependencyInfo, DependencySymbol, EntrySymbols, Include,
+    MatchStrategy, SymbolKind, SymbolUses,
+};
 
 /// Configuration settings for the translation process
 #[derive(Debug, Clone)]
@@ -86,20 +104,71 @@ pub struct TranspilerConfig {
     pub disable_refactoring: bool,
     pub preserve_unused_functions: bool,
     pub log_level: log::LevelFilter,
+    /// Number of translation units to process concurrently;
+    /// `0` uses all available cores. The exporter runs Clang on one unit
+    /// at a time, so only cache lookups and the conversion of exported ASTs
+    /// run concurrently during export.
+    pub jobs: usize,
+    /// Directory in which exporter output is cached between runs
+    pub ast_cache_dir: Option<PathBuf>,
 
     // Options that control build files
     /// Emit `Cargo.toml` and `lib.rs`
     pub emit_build_files: bool,
     pub emit_binaries: bool,
+    /// Give every binary a crate of its own and move the modules that more
+    /// than one binary needs into a common library crate, instead of
+    /// prepending all the modules a binary needs to it
+    pub common_crate: bool,
     /// Names of translation units containing main functions that we should make
     /// into binaries
     pub binaries: Vec<String>,
     pub detect_binaries: bool,
+    /// Functions programs start at besides `main`, like the `ls_main` of the
+    /// `ls` applet of a multi-call binary. Binaries and applets without a
+    /// `main` get a Rust `main` calling their entry function.
+    pub entry_symbols: EntrySymbols,
+    /// Keep the applets in library modules and emit a single binary running
+    /// the one `argv[0]` names, instead of detecting a binary per applet
+    pub applet_dispatcher: bool,
+    /// Translate every configuration of a source file that is compiled more
+    /// than once with different flags, rather than only the first one
+    pub translate_all_configurations: bool,
+    /// Build log or link database to read link steps from, so that every
+    /// executable and library it links becomes a crate of its own
+    pub link_log: Option<PathBuf>,
+    /// Compile the files that are excluded by `filter` or fail to translate
+    /// as C from the generated `build.rs` instead of giving up on the crate
+    pub hybrid_build: bool,
     pub dependency_file: PathBuf,
-    pub fuzz_depends_level: usize,
+    /// Projects translated before whose crates the translated files may use
+    /// functions and variables of
+    pub extern_projects: Vec<ExternProject>,
+    /// File to write the JSON translation report to
+    pub report_file: PathBuf,
+    /// How the symbols a unit uses are matched to their definitions
+    pub match_strategy: MatchStrategy,
+    /// The level of fuzziness that used to select how symbols are matched;
+    /// overrides `match_strategy` when set
+    #[deprecated(note = "use `match_strategy` instead")]
+    pub fuzz_depends_level: Option<usize>,
 }
 
 impl TranspilerConfig {
+    /// How the symbols a unit uses are matched to their definitions, taking
+    /// the deprecated `fuzz_depends_level` into account. Levels above 3 have
+    /// no strategy and match by name.
+    #[allow(deprecated)]
+    pub fn match_strategy(&self) -> MatchStrategy {
+        match self.fuzz_depends_level {
+            Some(level) => MatchStrategy::from_level(level).unwrap_or_else(|e| {
+                warn!("{}; matching by name", e);
+                MatchStrategy::NameOnly
+            }),
+            None => self.match_strategy,
+        }
+    }
+
     fn binary_name_from_path(file: &Path) -> String {
         let file = Path::new(file.file_stem().unwrap());
         get_module_name(file, false, false, false).unwrap()
@@ -110,10 +179,11 @@ impl TranspilerConfig {
         let module_name = Self::binary_name_from_path(file);
         self.binaries.contains(&module_name)
             || (self.detect_binaries
-                && dependency_info
-                    .defined
-                    .iter()
-                    .any(|symbol| symbol.name.contains("main")))
+                && if self.applet_dispatcher {
+                    dependency_info.is_main()
+                } else {
+                    dependency_info.is_entry(&self.entry_symbols)
+                })
     }
 
     fn check_if_all_binaries_used(
@@ -138,12 +208,75 @@ impl TranspilerConfig {
         ok
     }
 
+    fn num_jobs(&self) -> usize {
+        match self.jobs {
+            0 => thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            jobs => jobs,
+        }
+    }
+
     fn crate_name(&self) -> String {
         self.output_dir
             .as_ref()
             .and_then(|x| x.file_name().map(|x| x.to_string_lossy().into_owned()))
             .unwrap_or_else(|| "c2rust_out".into())
     }
+
+    /// The crates of the other projects the files `nodes` of the merged
+    /// dependency graph transitively depend on
+    fn extern_crates(&self, dependency_graph: &DependencyGraph, nodes: Vec<usize>) -> Vec<String> {
+        let own_project = self.crate_name();
+        dependency_graph
+            .reachable_nodes(nodes)
+            .into_iter()
+            .filter_map(|i| dependency_graph.nodes[i].project.as_ref())
+            .filter(|&project| *project != own_project)
+            .cloned()
+            .sorted()
+            .dedup()
+            .collect()
+    }
+}
+
+/// A project translated into a crate of its own before, like a library the
+/// project being translated vendors. Its units join the dependency graph, so
+/// that a binary can use the library modules of both projects.
+#[derive(Debug, Clone)]
+pub struct ExternProject {
+    /// The output directory of the project's translation
+    pub crate_dir: PathBuf,
+    /// The dependency file written when translating the project
+    pub dependency_file: PathBuf,
+}
+
+impl ExternProject {
+    /// The name of the project's crate, which is named after its output
+    /// directory like every translated crate
+    pub fn crate_name(&self) -> String {
+        self.crate_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "c2rust_out".into())
+    }
+
+    fn read_dependencies(&self) -> Vec<DependencyInfo> {
+        let file = File::open(&self.dependency_file).unwrap_or_else(|e| {
+            panic!(
+                "Unable to open dependency file {}: {}",
+                self.dependency_file.display(),
+                e
+            )
+        });
+        serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+            panic!(
+                "Unable to parse dependency file {}: {}",
+                self.dependency_file.display(),
+                e
+            )
+        })
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -243,15 +376,20 @@ fn get_module_name(
 /// Main entry point to transpiler. Called from CLI tools with the result of
 /// clap::App::get_matches().
 pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]) {
-    let dependency_infos = export(tcfg.clone(), cc_db, extra_clang_args);
-    let dependency_graph = build_dependency(dependency_infos, tcfg.fuzz_depends_level);
-
     diagnostics::init(tcfg.enabled_warnings.clone(), tcfg.log_level);
 
-    let lcmds = get_compile_commands(cc_db, &tcfg.filter).unwrap_or_else(|_| {
+    let lcmds = get_compile_commands(
+        cc_db,
+        &tcfg.filter,
+        tcfg.hybrid_build,
+        tcfg.translate_all_configurations,
+        tcfg.link_log.as_deref(),
+    )
+    .unwrap_or_else(|e| {
         panic!(
-            "Could not parse compile commands from {}",
-            cc_db.to_string_lossy()
+            "Could not parse compile commands from {}: {}",
+            cc_db.to_string_lossy(),
+            e
         )
     });
 
@@ -260,11 +398,69 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
     let mut clang_args: Vec<&str> = clang_args.iter().map(AsRef::as_ref).collect();
     clang_args.extend_from_slice(extra_clang_args);
 
+    // Parse every translation unit exactly once; the typed ASTs are kept
+    // around so the translation below does not have to invoke clang again.
+    let exported_units = export_units(
+        &tcfg,
+        cc_db,
+        &lcmds,
+        &clang_args,
+        &get_build_dir_raw(&tcfg, cc_db),
+    );
+    write_dependency_file(&tcfg, &exported_units);
+
+    let mut typed_contexts = HashMap::new();
+    let mut dependency_infos = Vec::with_capacity(exported_units.len());
+    for unit in exported_units {
+        typed_contexts.insert(unit.key(), unit.typed_context);
+        dependency_infos.push(unit.dependency_info);
+    }
+    // Each unit is translated for the first link command including it and
+    // the translation reused for the others
+    let units = UnitTranslations::new(typed_contexts);
+    // Symbols the other projects define resolve to their units, which no
+    // crate of this project translates
+    if !tcfg.extern_projects.is_empty() {
+        let mut projects = vec![(tcfg.crate_name(), dependency_infos)];
+        projects.extend(
+            tcfg.extern_projects
+                .iter()
+                .map(|project| (project.crate_name(), project.read_dependencies())),
+        );
+        let (merged, shared) = merge_dependencies(projects);
+        dependency_infos = merged;
+        for unit in shared {
+            warn!(
+                "{} is compiled by {} and {}, keeping the unit of {}",
+                unit.input_path,
+                unit.owner,
+                unit.projects.join(", "),
+                unit.owner
+            );
+        }
+    }
+    let mut dependency_graph = build_dependency(dependency_infos, tcfg.match_strategy());
+    dependency_graph.entry_symbols = tcfg.entry_symbols.clone();
+    for duplicate in dependency_graph.duplicate_definitions() {
+        if duplicate.conflicting {
+            let files = duplicate
+                .nodes
+                .iter()
+                .map(|&i| dependency_graph.nodes[i].input_path.as_str())
+                .format(", ");
+            warn!(
+                "{} is defined in more than one file: {}",
+                duplicate.name, files
+            );
+        }
+    }
+
+    let report = TranslationReport::default();
+    let build_dir = get_build_dir(&tcfg, cc_db);
     let mut top_level_ccfg = None;
     let mut workspace_members = vec![];
     let mut num_transpiled_files = 0;
     let mut transpiled_modules = Vec::new();
-    let build_dir = get_build_dir(&tcfg, cc_db);
     for lcmd in &lcmds {
         let cmds = &lcmd.cmd_inputs;
         let lcmd_name = lcmd
@@ -307,72 +503,169 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             }
         }
 
-        let pre_results = cmds
+        // Files we don't translate are compiled as C in hybrid builds
+        let mut native_inputs = if tcfg.hybrid_build {
+            lcmd.native_inputs.clone()
+        } else {
+            vec![]
+        };
+        for cmd in &native_inputs {
+            report.add(UnitReport::new(
+                &cmd.abs_file(),
+                &cmd.abs_output_file(),
+                &lcmd_name,
+                UnitStatus::CompiledAsC,
+            ));
+        }
+
+        let (bin_units, lib_units): (Vec<_>, Vec<_>) = cmds
             .iter()
-            .filter(|cmd| {
-                !tcfg.is_binary(
-                    &(dependency_graph
-                        .nodes
-                        .iter()
-                        .find(|dep| dep.input_path == cmd.abs_file().to_str().unwrap())
-                        .unwrap()),
-                )
+            .filter_map(|cmd| {
+                let input_path = cmd.abs_file();
+                let output_path = cmd.abs_output_file();
+                let exported = units.contains(&unit_key(&input_path, &output_path));
+                if !exported && tcfg.hybrid_build {
+                    warn!("Compiling {} as C instead", input_path.display());
+                    report.add(UnitReport::new(
+                        &input_path,
+                        &output_path,
+                        &lcmd_name,
+                        UnitStatus::CompiledAsC,
+                    ));
+                    native_inputs.push(Rc::clone(cmd));
+                    return None;
+                }
+                Some((input_path, output_path, cmd))
             })
-            .map(|cmd| {
+            .partition(|(input_path, output_path, _)| {
+                find_dependency_info(&dependency_graph, input_path, output_path)
+                    .map_or(false, |dependency_info| tcfg.is_binary(dependency_info))
+            });
+
+        // The commands stay here, as they cannot be sent to worker threads
+        let (lib_cmds, lib_units): (Vec<_>, Vec<_>) = lib_units
+            .into_iter()
+            .map(|(input_path, output_path, cmd)| (Rc::clone(cmd), (input_path, output_path)))
+            .unzip();
+        let (bin_cmds, bin_units): (Vec<_>, Vec<_>) = bin_units
+            .into_iter()
+            .map(|(input_path, output_path, cmd)| (Rc::clone(cmd), (input_path, output_path)))
+            .unzip();
+
+        let pre_results = map_in_parallel(
+            tcfg.num_jobs(),
+            lib_units,
+            |(input_path, output_path), output| {
                 transpile_single(
                     &tcfg,
-                    cmd.abs_file(),
-                    cmd.abs_output_file(),
+                    input_path,
+                    output_path,
                     &ancestor_path,
                     &build_dir,
-                    cc_db,
-                    &clang_args,
+                    &units,
                     &dependency_graph,
+                    &lcmd_name,
+                    &report,
+                    output,
                     |_, _| "".to_string(),
                 )
-            })
-            .collect::<Vec<_>>();
-        let results = cmds
-            .iter()
-            .filter(|cmd| {
-                tcfg.is_binary(
-                    &(dependency_graph
-                        .nodes
+            },
+        );
+        // Like units the exporter failed on, units we failed to translate
+        // are compiled as C in hybrid builds
+        if tcfg.hybrid_build {
+            native_inputs.extend(failed_cmds(&lib_cmds, &pre_results));
+        }
+        // C code may call into any library module, so binaries linking C code
+        // need all of them rather than just the ones `main` depends on
+        let has_native_inputs = !native_inputs.is_empty();
+        let extern_crates = tcfg.extern_crates(
+            &dependency_graph,
+            cmds.iter()
+                .filter_map(|cmd| {
+                    let (input_path, output_path) =
+                        unit_key(&cmd.abs_file(), &cmd.abs_output_file());
+                    dependency_graph.get_node_index_with_input(&input_path, &output_path)
+                })
+                .collect(),
+        );
+        // The modules more than one binary needs go into a common crate
+        // rather than into each of them
+        let common_modules = if tcfg.common_crate && !has_native_inputs {
+            let binaries = bin_units
+                .iter()
+                .filter_map(|(input_path, output_path)| {
+                    dependency_graph.get_node_index_with_input(
+                        &input_path.to_str().unwrap().to_string(),
+                        &output_path
+                            .as_ref()
+                            .map(|path| path.to_str().unwrap().to_string()),
+                    )
+                })
+                .collect::<Vec<_>>();
+            match dependency_graph.partition_crates(&binaries) {
+                Ok(partition) => Some(
+                    partition
+                        .common
                         .iter()
-                        .find(|dep| dep.input_path == cmd.abs_file().to_str().unwrap())
-                        .unwrap()),
-                )
-            })
-            .map(|cmd| {
+                        .map(|&i| PathBuf::from(&dependency_graph.nodes[i].output_path))
+                        .collect::<HashSet<_>>(),
+                ),
+                Err(e) => {
+                    warn!("Not emitting a common crate: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let common_crate_name = common_modules
+            .as_ref()
+            .map(|_| CommonCrateConfig::crate_rust_name(&lcmd_name));
+        let bin_results = map_in_parallel(
+            tcfg.num_jobs(),
+            bin_units,
+            |(input_path, output_path), output| {
                 let mut modules = vec![];
                 let mut pragmas = PragmaSet::new();
                 let mut crates = CrateSet::new();
-                println!(
+                writeln!(
+                    output,
                     "Getting sub dependency graph for {:?}",
-                    (
-                        Some(cmd.abs_file().to_str().unwrap_or_default().to_string()),
-                        cmd.abs_output_file()
-                            .as_ref()
-                            .map(|path| path.to_str().unwrap_or_default().to_string()),
-                    )
+                    unit_key(&input_path, &output_path)
+                )
+                .unwrap();
+                let node = dependency_graph.get_node_index_with_input(
+                    &input_path.to_str().unwrap().to_string(),
+                    &output_path
+                        .as_ref()
+                        .map(|path| path.to_str().unwrap().to_string()),
                 );
-                let sub_dependency_graph = if let Some(idx) = dependency_graph
-                    .get_node_index_with_input(
-                        &cmd.abs_file().to_str().unwrap().to_string(),
-                        &cmd.abs_output_file()
-                            .as_ref()
-                            .map(|path| path.to_str().unwrap().to_string()),
-                    ) {
-                    println!("Extracting sub dependency graph for {:?}", idx);
+                let sub_dependency_graph = if let Some(idx) = node {
+                    writeln!(output, "Extracting sub dependency graph for {:?}", idx).unwrap();
                     dependency_graph.extract_sub_dependency(vec![idx])
                 } else {
                     DependencyGraph::new()
                 };
+                let bin_extern_crates = match node {
+                    Some(idx) if !has_native_inputs => {
+                        tcfg.extern_crates(&dependency_graph, vec![idx])
+                    }
+                    _ => extern_crates.clone(),
+                };
                 for res in &pre_results {
                     match res {
                         Ok((module, pragma_vec, crate_set)) => {
-                            if let Some(_) = sub_dependency_graph
-                                .get_node_index_with_output(&module.to_str().unwrap().to_string())
+                            let is_common = common_modules
+                                .as_ref()
+                                .map_or(false, |common_modules| common_modules.contains(module));
+                            if has_native_inputs
+                                || (!is_common
+                                    && sub_dependency_graph
+                                        .get_node_index_with_output(
+                                            &module.to_str().unwrap().to_string(),
+                                        )
+                                        .is_some())
                             {
                                 modules.push(module.clone());
                                 crates.extend(crate_set);
@@ -389,13 +682,15 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
                 }
                 transpile_single(
                     &tcfg,
-                    cmd.abs_file(),
-                    cmd.abs_output_file(),
+                    input_path,
+                    output_path,
                     &ancestor_path,
                     &build_dir,
-                    cc_db,
-                    &clang_args,
+                    &units,
                     &dependency_graph,
+                    &lcmd_name,
+                    &report,
+                    output,
                     |pragma_vec, crate_set| {
                         crates.extend(crate_set);
                         for (key, vals) in pragma_vec {
@@ -412,11 +707,34 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
                             pragmas,
                             &crates,
                             &dependency_graph,
+                            common_crate_name.as_deref(),
+                            &bin_extern_crates,
                         )
                     },
                 )
-            })
-            .collect::<Vec<_>>()
+            },
+        );
+        let dispatcher = if tcfg.applet_dispatcher && tcfg.emit_build_files {
+            transpile_dispatcher(
+                &tcfg,
+                &build_dir,
+                &lcmd_name,
+                &pre_results,
+                &dependency_graph,
+                has_native_inputs,
+                &extern_crates,
+            )
+        } else {
+            None
+        };
+        if tcfg.hybrid_build {
+            native_inputs.extend(failed_cmds(&bin_cmds, &bin_results));
+        }
+        let binaries = bin_results
+            .iter()
+            .filter_map(|res| res.as_ref().ok().map(|(module, _, _)| module.clone()))
+            .collect::<Vec<_>>();
+        let results = bin_results
             .into_iter()
             .chain(pre_results.into_iter())
             .collect::<Vec<_>>();
@@ -437,8 +755,10 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
                         }
                     }
                 }
-                Err(_) => {
-                    modules_skipped = true;
+                // Hybrid builds compile the units that failed as C, but
+                // not the ones skipped because their output exists
+                Err(status) => {
+                    modules_skipped |= !tcfg.hybrid_build || status == UnitStatus::SkippedExisting;
                 }
             }
         }
@@ -451,15 +771,42 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             if modules_skipped {
                 // If we skipped a file, we may not have collected all required pragmas
                 warn!("Can't emit build files after incremental transpiler run; skipped.");
+                report.write(&tcfg.report_file);
                 return;
             }
 
+            let common = common_modules.map(|common_modules| CommonCrateConfig {
+                crate_name: CommonCrateConfig::crate_name(&lcmd_name),
+                modules: modules
+                    .iter()
+                    .filter(|module| common_modules.contains(*module))
+                    .cloned()
+                    .collect(),
+                binaries,
+            });
+            if let Some(common) = &common {
+                let crate_dir = if lcmd.top_level {
+                    PathBuf::new()
+                } else {
+                    PathBuf::from(&lcmd_name)
+                };
+                workspace_members.extend(
+                    common
+                        .crate_dirs()
+                        .iter()
+                        .map(|dir| crate_dir.join(dir).to_str().unwrap().to_owned()),
+                );
+            }
             let ccfg = CrateConfig {
                 crate_name: lcmd_name.clone(),
                 modules,
                 pragmas,
                 crates,
                 link_cmd: lcmd,
+                native_inputs,
+                common,
+                dispatcher,
+                extern_crates,
             };
             if lcmd.top_level {
                 top_level_ccfg = Some(ccfg);
@@ -473,6 +820,8 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
         }
     }
 
+    report.write(&tcfg.report_file);
+
     if num_transpiled_files == 0 {
         warn!("No C files found in compile_commands.json; nothing to do.");
         return;
@@ -502,23 +851,104 @@ pub fn export(
 ) -> Vec<DependencyInfo> {
     diagnostics::init(tcfg.enabled_warnings.clone(), tcfg.log_level);
 
-    let lcmds = get_compile_commands(cc_db, &tcfg.filter).unwrap_or_else(|_| {
+    let lcmds = get_compile_commands(
+        cc_db,
+        &tcfg.filter,
+        tcfg.hybrid_build,
+        tcfg.translate_all_configurations,
+        tcfg.link_log.as_deref(),
+    )
+    .unwrap_or_else(|e| {
         panic!(
-            "Could not parse compile commands from {}",
-            cc_db.to_string_lossy()
+            "Could not parse compile commands from {}: {}",
+            cc_db.to_string_lossy(),
+            e
         )
     });
 
-    let mut dependency_infos = Vec::<DependencyInfo>::new();
-
     // Specify path to system include dir on macOS 10.14 and later. Disable the blocks extension.
     let clang_args: Vec<String> = get_extra_args_macos();
     let mut clang_args: Vec<&str> = clang_args.iter().map(AsRef::as_ref).collect();
     clang_args.extend_from_slice(extra_clang_args);
 
-    let mut num_transpiled_files = 0;
     let build_dir = get_build_dir_raw(&tcfg, cc_db);
-    for lcmd in &lcmds {
+    let exported_units = export_units(&tcfg, cc_db, &lcmds, &clang_args, &build_dir);
+    write_dependency_file(&tcfg, &exported_units);
+
+    exported_units
+        .into_iter()
+        .map(|unit| unit.dependency_info)
+        .collect()
+}
+
+/// Key identifying a translation unit by its input file and (optional)
+/// object file, matching `DependencyInfo::input_path` and
+/// `DependencyInfo::object_path`.
+type UnitKey = (String, Option<String>);
+
+fn unit_key(input_path: &Path, output_path: &Option<PathBuf>) -> UnitKey {
+    (
+        input_path.to_str().unwrap().to_string(),
+        output_path
+            .as_ref()
+            .map(|path| path.to_str().unwrap().to_string()),
+    )
+}
+
+/// Look up the dependency information of the unit compiled from `input_path`
+/// to `output_path`. Both are needed since the same source file may be
+/// compiled several times with different flags.
+fn find_dependency_info<'a>(
+    dependency_graph: &'a DependencyGraph,
+    input_path: &Path,
+    output_path: &Option<PathBuf>,
+) -> Option<&'a DependencyInfo> {
+    let (input_path, object_path) = unit_key(input_path, output_path);
+    dependency_graph
+        .get_node_index_with_input(&input_path, &object_path)
+        .map(|idx| &dependency_graph.nodes[idx])
+}
+
+/// A translation unit parsed by the AST exporter, together with the
+/// dependency information extracted from it.
+struct ExportedUnit {
+    dependency_info: DependencyInfo,
+    typed_context: TypedAstContext,
+}
+
+impl ExportedUnit {
+    fn key(&self) -> UnitKey {
+        (
+            self.dependency_info.input_path.clone(),
+            self.dependency_info.object_path.clone(),
+        )
+    }
+}
+
+/// Parse every translation unit of every link command once and extract its
+/// dependency information. A unit several link commands include is parsed
+/// for the first of them, and is linked into the targets of all of them.
+/// Units that fail to parse are skipped.
+fn export_units(
+    tcfg: &TranspilerConfig,
+    cc_db: &Path,
+    lcmds: &[LinkCmd],
+    clang_args: &[&str],
+    build_dir: &Path,
+) -> Vec<ExportedUnit> {
+    let ast_cache = tcfg.ast_cache_dir.as_ref().and_then(|dir| {
+        AstCache::new(dir)
+            .map_err(|e| warn!("Not caching exporter output in {}: {}", dir.display(), e))
+            .ok()
+    });
+
+    let all_link_targets = link_targets(lcmds);
+    let all_link_libs = link_libs(lcmds);
+    let mut ancestor_paths = Vec::with_capacity(lcmds.len());
+    let mut build_dirs = Vec::with_capacity(lcmds.len());
+    let mut inputs: Vec<UnitInput> = vec![];
+    let mut unit_index = HashMap::new();
+    for (idx, lcmd) in lcmds.iter().enumerate() {
         let cmds = &lcmd.cmd_inputs;
         let lcmd_name = lcmd
             .output
@@ -533,11 +963,11 @@ pub fn export(
                     .to_owned()
             })
             .unwrap_or_else(|| tcfg.crate_name());
-        let build_dir = if lcmd.top_level {
+        build_dirs.push(if lcmd.top_level {
             build_dir.to_path_buf()
         } else {
             build_dir.join(&lcmd_name)
-        };
+        });
 
         // Compute the common ancestor of all input files
         // FIXME: this is quadratic-time in the length of the ancestor path
@@ -559,38 +989,95 @@ pub fn export(
                     .unwrap_or_else(PathBuf::new);
             }
         }
-        let results = cmds
-            .iter()
-            .map(|cmd| {
-                export_single(
-                    &tcfg,
-                    cmd.abs_file(),
-                    cmd.abs_output_file(),
-                    &ancestor_path,
-                    &build_dir,
-                    cc_db,
-                    &clang_args,
-                )
-            })
-            .collect::<Vec<Result<DependencyInfo, ()>>>();
-
-        // add all dependencies from results to the dependency_infos
-        for res in &results {
-            match res {
-                Ok(_) => {
-                    num_transpiled_files += 1;
+        ancestor_paths.push(ancestor_path);
+
+        for cmd in cmds {
+            let input_path = cmd.abs_file();
+            let output_path = cmd.abs_output_file();
+            let key = unit_key(&input_path, &output_path);
+            let input = match unit_index.get(&key) {
+                Some(&i) => &mut inputs[i],
+                None => {
+                    unit_index.insert(key, inputs.len());
+                    inputs.push(UnitInput {
+                        input_path,
+                        output_path,
+                        compile_cmd: CompileCmd::clone(cmd),
+                        lcmd: idx,
+                        link_targets: vec![],
+                        direct_link_targets: vec![],
+                        link_libs: vec![],
+                    });
+                    inputs.last_mut().unwrap()
+                }
+            };
+            if let Some(target) = &lcmd.output {
+                if !input.direct_link_targets.contains(target) {
+                    input.direct_link_targets.push(target.clone());
+                }
+            }
+            for target in &all_link_targets[idx] {
+                if !input.link_targets.contains(target) {
+                    input.link_targets.push(target.clone());
+                }
+            }
+            for lib in &all_link_libs[idx] {
+                if !input.link_libs.contains(lib) {
+                    input.link_libs.push(lib.clone());
                 }
-                Err(_) => {}
             }
         }
-        dependency_infos.extend(results.into_iter().filter_map(|res| res.ok()));
     }
 
-    if num_transpiled_files == 0 {
+    map_in_parallel(tcfg.num_jobs(), inputs, |input, output| {
+        export_single(
+            tcfg,
+            input.input_path,
+            input.output_path,
+            &ancestor_paths[input.lcmd],
+            &build_dirs[input.lcmd],
+            cc_db,
+            clang_args,
+            &input.compile_cmd,
+            &input.link_targets,
+            &input.direct_link_targets,
+            &input.link_libs,
+            ast_cache.as_ref(),
+            output,
+        )
+    })
+    .into_iter()
+    .filter_map(Result::ok)
+    .collect()
+}
+
+/// A unit to export, with what the link commands including it link it into
+struct UnitInput {
+    input_path: PathBuf,
+    output_path: Option<PathBuf>,
+    compile_cmd: CompileCmd,
+    /// The first link command including the unit, which its output path is
+    /// relative to
+    lcmd: usize,
+    link_targets: Vec<String>,
+    /// The outputs of the link commands including the unit directly
+    direct_link_targets: Vec<String>,
+    link_libs: Vec<String>,
+}
+
+/// Write the dependency information of all exported units to
+/// `tcfg.dependency_file`.
+fn write_dependency_file(tcfg: &TranspilerConfig, exported_units: &[ExportedUnit]) {
+    if exported_units.is_empty() {
         warn!("No C files found in compile_commands.json; nothing to do.");
-        return dependency_infos;
+        return;
     }
 
+    let dependency_infos = exported_units
+        .iter()
+        .map(|unit| &unit.dependency_info)
+        .collect::<Vec<_>>();
+
     let mut dep_file = match File::create(&tcfg.dependency_file) {
         Ok(file) => file,
         Err(e) => panic!(
@@ -613,8 +1100,76 @@ pub fn export(
             e
         ),
     };
+}
 
-    dependency_infos
+/// The commands of the units that failed to export, given the results of
+/// translating the units of `cmds` in order. Units skipped because their
+/// output exists are not failures.
+fn failed_cmds<'a>(
+    cmds: &'a [Rc<CompileCmd>],
+    results: &'a [TranspileResult],
+) -> impl Iterator<Item = Rc<CompileCmd>> + 'a {
+    cmds.iter()
+        .zip(results)
+        .filter(|(_, res)| matches!(res, Err(UnitStatus::ExportFailed)))
+        .map(|(cmd, _)| Rc::clone(cmd))
+}
+
+/// Apply `f` to every item using up to `jobs` worker threads. Results are
+/// returned in the order of `items` regardless of the order in which they
+/// finish. The progress messages `f` writes to the buffer it gets with each
+/// item are printed in that order too, once all items are done, so callers
+/// observe the same output as a serial run.
+fn map_in_parallel<T, R, F>(jobs: usize, items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T, &mut String) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items
+            .into_iter()
+            .map(|item| {
+                let mut output = String::new();
+                let result = f(item, &mut output);
+                print!("{}", output);
+                result
+            })
+            .collect();
+    }
+
+    let num_items = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(
+        (0..num_items)
+            .map(|_| None)
+            .collect::<Vec<Option<(R, String)>>>(),
+    );
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(num_items) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().next();
+                let (idx, item) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let mut output = String::new();
+                let result = f(item, &mut output);
+                results.lock().unwrap()[idx] = Some((result, output));
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| {
+            let (result, output) = result.expect("worker thread did not produce a result");
+            print!("{}", output);
+            result
+        })
+        .collect()
 }
 
 /// Ensure that clang can locate the system headers on macOS 10.14+.
@@ -675,116 +1230,145 @@ fn reorganize_definitions(
     Ok(())
 }
 
+/// The translation of a unit, without the modules a binary gets prepended
+#[derive(Clone)]
+struct TranslatedUnit {
+    code: String,
+    pragmas: PragmaVec,
+    crates: CrateSet,
+    decl_reports: Vec<DeclReport>,
+}
+
+/// The exported units of all link commands. A unit is translated once, for
+/// the first link command including it, which takes its typed AST; the other
+/// link commands get a copy of that translation.
+struct UnitTranslations {
+    typed_contexts: Mutex<HashMap<UnitKey, TypedAstContext>>,
+    translations: Mutex<HashMap<UnitKey, TranslatedUnit>>,
+}
+
+impl UnitTranslations {
+    fn new(typed_contexts: HashMap<UnitKey, TypedAstContext>) -> Self {
+        Self {
+            typed_contexts: Mutex::new(typed_contexts),
+            translations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the unit was exported, so that it can be translated
+    fn contains(&self, key: &UnitKey) -> bool {
+        self.translations.lock().unwrap().contains_key(key)
+            || self.typed_contexts.lock().unwrap().contains_key(key)
+    }
+
+    /// The translation of an exported unit, made with `translate` from its
+    /// typed AST the first time.
+    fn translate(
+        &self,
+        key: UnitKey,
+        translate: impl FnOnce(TypedAstContext) -> TranslatedUnit,
+    ) -> TranslatedUnit {
+        if let Some(translation) = self.translations.lock().unwrap().get(&key) {
+            return translation.clone();
+        }
+        let typed_context = self
+            .typed_contexts
+            .lock()
+            .unwrap()
+            .remove(&key)
+            .expect("translating a unit that was not exported");
+        let translation = translate(typed_context);
+        self.translations
+            .lock()
+            .unwrap()
+            .insert(key, translation.clone());
+        translation
+    }
+}
+
 fn transpile_single(
     tcfg: &TranspilerConfig,
     input_path: PathBuf,
     output_path: Option<PathBuf>,
     ancestor_path: &Path,
     build_dir: &Path,
-    cc_db: &Path,
-    extra_clang_args: &[&str],
+    units: &UnitTranslations,
     dependency_graph: &DependencyGraph,
+    crate_name: &str,
+    report: &TranslationReport,
+    output: &mut String,
     get_prefix: impl FnOnce(&PragmaVec, &CrateSet) -> String,
 ) -> TranspileResult {
+    let unit_report = UnitReport::new(
+        &input_path,
+        &output_path,
+        crate_name,
+        UnitStatus::ExportFailed,
+    );
+    // Hybrid builds compile the units we fail on as C
+    let failed_status = |status| {
+        if tcfg.hybrid_build {
+            UnitStatus::CompiledAsC
+        } else {
+            status
+        }
+    };
+
+    // Units without a typed AST failed to parse during export; the
+    // exporter has already warned about them.
+    let key = unit_key(&input_path, &output_path);
+    if !units.contains(&key) {
+        report.add(UnitReport {
+            status: failed_status(UnitStatus::ExportFailed),
+            ..unit_report
+        });
+        return Err(UnitStatus::ExportFailed);
+    }
+
+    let is_binary = tcfg.is_binary(
+        find_dependency_info(dependency_graph, &input_path, &output_path)
+            .expect("missing dependency information for an exported unit"),
+    );
+
     let output_path = get_output_path(
         tcfg,
         input_path.clone(),
         output_path,
         ancestor_path,
         build_dir,
-        tcfg.is_binary(
-            &(dependency_graph
-                .nodes
-                .iter()
-                .find(|dep| dep.input_path == input_path.to_str().unwrap())
-                .unwrap()),
-        ),
+        is_binary,
     );
     if output_path.exists() && !tcfg.overwrite_existing {
         warn!("Skipping existing file {}", output_path.display());
-        return Err(());
-    }
-
-    let file = input_path.file_name().unwrap().to_str().unwrap();
-    if !input_path.exists() {
-        warn!(
-            "Input C file {} does not exist, skipping!",
-            input_path.display()
-        );
-        return Err(());
-    }
-
-    if tcfg.verbose {
-        println!("Additional Clang arguments: {}", extra_clang_args.join(" "));
+        report.add(UnitReport {
+            status: UnitStatus::SkippedExisting,
+            output_path: Some(output_path),
+            ..unit_report
+        });
+        return Err(UnitStatus::SkippedExisting);
     }
 
-    // Extract the untyped AST from the CBOR file
-    let untyped_context = match ast_exporter::get_untyped_ast(
-        input_path.as_path(),
-        cc_db,
-        extra_clang_args,
-        tcfg.debug_ast_exporter,
-    ) {
-        Err(e) => {
-            warn!(
-                "Error: {}. Skipping {}; is it well-formed C?",
-                e,
-                input_path.display()
-            );
-            return Err(());
-        }
-        Ok(cxt) => cxt,
-    };
-
-    println!("Transpiling {}", file);
-
-    if tcfg.dump_untyped_context {
-        println!("CBOR Clang AST");
-        println!("{:#?}", untyped_context);
-    }
-
-    // Convert this into a typed AST
-    let typed_context = {
-        let conv = ConversionContext::new(&untyped_context);
-        if conv.invalid_clang_ast && tcfg.fail_on_error {
-            panic!("Clang AST was invalid");
+    // Perform the translation, unless it was done for another link command
+    let TranslatedUnit {
+        code: mut translated_string,
+        pragmas,
+        crates,
+        decl_reports,
+    } = units.translate(key, |typed_context| {
+        let file = input_path.file_name().unwrap().to_str().unwrap();
+        writeln!(output, "Transpiling {}", file).unwrap();
+        let (code, pragmas, crates, decl_reports) =
+            translator::translate(typed_context, tcfg, &input_path, is_binary);
+        TranslatedUnit {
+            code,
+            pragmas,
+            crates,
+            decl_reports,
         }
-        conv.typed_context
-    };
-
-    if tcfg.dump_typed_context {
-        println!("Clang AST");
-        println!("{:#?}", typed_context);
-    }
-
-    if tcfg.pretty_typed_context {
-        println!("Pretty-printed Clang AST");
-        println!("{:#?}", Printer::new(io::stdout()).print(&typed_context));
-    }
-
-    // Perform the translation
-    let (mut translated_string, pragmas, crates) = translator::translate(
-        typed_context,
-        tcfg,
-        &input_path,
-        tcfg.is_binary(
-            &(dependency_graph
-                .nodes
-                .iter()
-                .find(|dep| dep.input_path == input_path.to_str().unwrap())
-                .unwrap()),
-        ),
-    );
+    });
+    report.add(unit_report.translated(&output_path, &pragmas, &crates, decl_reports));
 
-    if tcfg.emit_binaries
-        && tcfg.is_binary(
-            &(dependency_graph
-                .nodes
-                .iter()
-                .find(|dep| dep.input_path == input_path.to_str().unwrap())
-                .unwrap()),
-        )
-    {
+    if tcfg.emit_binaries && is_binary {
         translated_string = get_prefix(&pragmas, &crates) + &translated_string;
     }
 
@@ -809,6 +1393,93 @@ fn transpile_single(
     Ok((output_path, pragmas, crates))
 }
 
+/// Emit the binary running the applet `argv[0]` names, for the library
+/// modules of `results` that define the entry function of an applet. Like
+/// other binaries, it gets the modules it needs prepended, and links the
+/// crates of the other projects those depend on. Returns the path
+/// of the binary, or `None` if there are no applets or the file existed
+/// already.
+fn transpile_dispatcher(
+    tcfg: &TranspilerConfig,
+    build_dir: &Path,
+    crate_name: &str,
+    results: &[TranspileResult],
+    dependency_graph: &DependencyGraph,
+    all_modules: bool,
+    crate_extern_crates: &[String],
+) -> Option<PathBuf> {
+    let results = results
+        .iter()
+        .filter_map(|res| res.as_ref().ok())
+        .collect::<Vec<_>>();
+    let mut applets = vec![];
+    let mut applet_nodes = vec![];
+    for (module, _, _) in &results {
+        let node = match dependency_graph
+            .get_node_index_with_output(&module.to_str().unwrap().to_string())
+        {
+            Some(node) => node,
+            None => continue,
+        };
+        let info = &dependency_graph.nodes[node];
+        if info.is_main() {
+            continue;
+        }
+        // The translator generates a `main` for the first entry function
+        if let Some(applet) = info
+            .entry_functions(&tcfg.entry_symbols)
+            .next()
+            .and_then(|entry| tcfg.entry_symbols.applet_name(&entry.name))
+        {
+            applets.push((applet.to_string(), module.clone()));
+            applet_nodes.push(node);
+        }
+    }
+    if applets.is_empty() {
+        warn!("No applets found for the dispatcher of {}", crate_name);
+        return None;
+    }
+
+    let extern_crates = if all_modules {
+        crate_extern_crates.to_vec()
+    } else {
+        tcfg.extern_crates(dependency_graph, applet_nodes.clone())
+    };
+    let needed = dependency_graph
+        .reachable_nodes(applet_nodes)
+        .into_iter()
+        .map(|i| PathBuf::from(&dependency_graph.nodes[i].output_path))
+        .collect::<HashSet<_>>();
+    let mut modules = vec![];
+    let mut pragmas = PragmaSet::new();
+    let mut crates = CrateSet::new();
+    for (module, pragma_vec, crate_set) in results {
+        if all_modules || needed.contains(module) {
+            modules.push(module.clone());
+            crates.extend(crate_set);
+            for (key, vals) in pragma_vec {
+                for val in vals {
+                    pragmas.insert((key, val));
+                }
+            }
+        }
+    }
+    pragmas.sort();
+    crates.sort();
+
+    emit_dispatcher(
+        tcfg,
+        build_dir,
+        crate_name,
+        &applets,
+        modules,
+        pragmas,
+        &crates,
+        dependency_graph,
+        &extern_crates,
+    )
+}
+
 fn export_single(
     tcfg: &TranspilerConfig,
     input_path: PathBuf,
@@ -817,7 +1488,13 @@ fn export_single(
     build_dir: &Path,
     cc_db: &Path,
     extra_clang_args: &[&str],
-) -> Result<DependencyInfo, ()> {
+    compile_cmd: &CompileCmd,
+    link_targets: &[String],
+    direct_link_targets: &[String],
+    link_libs: &[String],
+    ast_cache: Option<&AstCache>,
+    output: &mut String,
+) -> Result<ExportedUnit, ()> {
     let raw_output_path = get_output_path_raw(
         tcfg,
         input_path.clone(),
@@ -835,17 +1512,38 @@ fn export_single(
         return Err(());
     }
 
+    let compile_args = compile_cmd.args();
+    let compile_flags = compile_cmd.flags();
+
     if tcfg.verbose {
-        println!("Additional Clang arguments: {}", extra_clang_args.join(" "));
+        writeln!(
+            output,
+            "Additional Clang arguments: {}",
+            extra_clang_args.join(" ")
+        )
+        .unwrap();
+        writeln!(output, "Compile arguments: {}", compile_args.join(" ")).unwrap();
+        writeln!(output, "Compile flags: {:?}", compile_flags).unwrap();
     }
 
     // Extract the untyped AST from the CBOR file
-    let untyped_context = match ast_exporter::get_untyped_ast(
-        input_path.as_path(),
-        cc_db,
-        extra_clang_args,
-        tcfg.debug_ast_exporter,
-    ) {
+    let untyped_ast = match ast_cache {
+        Some(ast_cache) => ast_cache.get_untyped_ast(
+            input_path.as_path(),
+            cc_db,
+            extra_clang_args,
+            compile_cmd.directory(),
+            &compile_args,
+            tcfg.debug_ast_exporter,
+        ),
+        None => ast_exporter::get_untyped_ast(
+            input_path.as_path(),
+            cc_db,
+            extra_clang_args,
+            tcfg.debug_ast_exporter,
+        ),
+    };
+    let untyped_context = match untyped_ast {
         Err(e) => {
             warn!(
                 "Error: {}. Skipping {}; is it well-formed C?",
@@ -857,11 +1555,11 @@ fn export_single(
         Ok(cxt) => cxt,
     };
 
-    println!("Exporting {}", file);
+    writeln!(output, "Exporting {}", file).unwrap();
 
     if tcfg.dump_untyped_context {
-        println!("CBOR Clang AST");
-        println!("{:#?}", untyped_context);
+        writeln!(output, "CBOR Clang AST").unwrap();
+        writeln!(output, "{:#?}", untyped_context).unwrap();
     }
 
     // Convert this into a typed AST
@@ -874,13 +1572,16 @@ fn export_single(
     };
 
     if tcfg.dump_typed_context {
-        println!("Clang AST");
-        println!("{:#?}", typed_context);
+        writeln!(output, "Clang AST").unwrap();
+        writeln!(output, "{:#?}", typed_context).unwrap();
     }
 
     if tcfg.pretty_typed_context {
-        println!("Pretty-printed Clang AST");
-        println!("{:#?}", Printer::new(io::stdout()).print(&typed_context));
+        let mut pretty = vec![];
+        let result = Printer::new(&mut pretty).print(&typed_context);
+        writeln!(output, "Pretty-printed Clang AST").unwrap();
+        output.push_str(&String::from_utf8_lossy(&pretty));
+        writeln!(output, "{:#?}", result).unwrap();
     }
 
     let mut export_context = typed_context.clone();
@@ -893,45 +1594,64 @@ fn export_single(
         object_path: output_path
             .clone()
             .map(|path| path.to_str().unwrap().to_string()),
+        compile_args,
+        defines: compile_flags.defines,
         undefined: vec![],
         defined: vec![],
+        uses: vec![],
+        link_targets: link_targets.to_vec(),
+        direct_link_targets: direct_link_targets.to_vec(),
+        link_libs: link_libs.to_vec(),
+        includes: vec![],
+        weak: vec![],
+        metrics: FileMetrics::default(),
+        project: None,
     };
 
+    let file_path = |id| export_context.get_file_path(id)?.to_str();
+    for &(file, header) in export_context.include_edges() {
+        if let (Some(file), Some(header)) = (file_path(file), file_path(header)) {
+            dependency_info.includes.push(Include {
+                file: file.to_string(),
+                header: header.to_string(),
+            });
+        }
+    }
+
+    let main_file = export_context.find_file_id(&input_path);
+    dependency_info.metrics = file_metrics(&export_context, main_file);
     for (_, decl) in export_context.iter_decls() {
-        match &decl.kind {
+        let decl_file_id = export_context.file_id(decl);
+        let decl_file = match decl_file_id.and_then(|id| export_context.get_file_path(id)) {
+            Some(decl_file) => decl_file.to_str().unwrap().to_string(),
+            // Builtin declarations don't belong to any file
+            None => continue,
+        };
+        let symbol = |name: &str, kind| DependencySymbol {
+            name: name.to_string(),
+            path: decl_file.clone(),
+            kind,
+        };
+
+        let (name, kind) = match &decl.kind {
             CDeclKind::Function {
                 is_global: true,
                 name,
                 body,
                 ..
             } => {
-                let decl_file = export_context
-                    .get_file_path(export_context.file_id(decl).unwrap())
-                    .unwrap();
-                // println!(
-                //     "Function: {}, is_global: {}, is_implicit: {}, is_extern: {}, body: {}",
-                //     name,
-                //     is_global,
-                //     is_implicit,
-                //     is_extern,
-                //     body.is_some()
-                // );
-
-                if !body.is_some() {
-                    println!("U {}", name);
-                    dependency_info.undefined.push(DependencySymbol {
-                        name: name.to_string(),
-                        path: decl_file.to_str().unwrap().to_string(),
-                    });
-                } else if body.is_some() {
-                    println!("T {}", name);
-                    dependency_info.defined.push(DependencySymbol {
-                        name: name.to_string(),
-                        path: decl_file.to_str().unwrap().to_string(),
-                    });
+                if body.is_none() {
+                    writeln!(output, "U {}", name).unwrap();
+                    dependency_info
+                        .undefined
+                        .push(symbol(name, SymbolKind::Function));
                 } else {
-                    assert!(false);
+                    writeln!(output, "T {}", name).unwrap();
+                    dependency_info
+                        .defined
+                        .push(symbol(name, SymbolKind::Function));
                 }
+                continue;
             }
             CDeclKind::Variable {
                 is_externally_visible: true,
@@ -939,29 +1659,94 @@ fn export_single(
                 ident,
                 ..
             } => {
-                let decl_file = export_context
-                    .get_file_path(export_context.file_id(decl).unwrap())
-                    .unwrap();
-
-                // println!(
-                //     "Variable: {:?}, is_defn: {}, is_externally_visible: {}",
-                //     name, is_defn, true
-                // );
                 if *is_defn {
-                    println!("b {}", ident);
-                    dependency_info.defined.push(DependencySymbol {
-                        name: ident.to_string(),
-                        path: decl_file.to_str().unwrap().to_string(),
-                    });
+                    writeln!(output, "b {}", ident).unwrap();
+                    dependency_info
+                        .defined
+                        .push(symbol(ident, SymbolKind::Variable));
                 } else {
-                    println!("U {}", ident);
-                    dependency_info.undefined.push(DependencySymbol {
-                        name: ident.to_string(),
-                        path: decl_file.to_str().unwrap().to_string(),
-                    });
+                    writeln!(output, "U {}", ident).unwrap();
+                    dependency_info
+                        .undefined
+                        .push(symbol(ident, SymbolKind::Variable));
                 }
+                continue;
             }
-            _ => {}
+            // Helpers like `static inline` functions defined in a header
+            CDeclKind::Function {
+                name,
+                body: Some(_),
+                ..
+            } => (name, SymbolKind::Function),
+            CDeclKind::Struct {
+                name: Some(name), ..
+            }
+            | CDeclKind::Union {
+                name: Some(name), ..
+            } => (name, SymbolKind::Record),
+            CDeclKind::Typedef {
+                name,
+                is_implicit: false,
+                ..
+            } => (name, SymbolKind::Typedef),
+            CDeclKind::Enum {
+                name: Some(name), ..
+            } => (name, SymbolKind::Enum),
+            CDeclKind::MacroObject { name, .. } | CDeclKind::MacroFunction { name, .. } => {
+                (name, SymbolKind::Macro)
+            }
+            _ => continue,
+        };
+
+        // Types and macros from headers are attributed to the header that
+        // defines them. Those of the unit itself count as defined by it, while
+        // its static functions are private to it.
+        let is_local = decl_file_id == main_file;
+        if is_local && kind == SymbolKind::Function {
+            continue;
+        }
+        let symbols = if is_local {
+            &mut dependency_info.defined
+        } else {
+            &mut dependency_info.undefined
+        };
+        let symbol = symbol(name, kind);
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+
+    // Uses come from the unpruned AST, which still has the definitions of
+    // the unit that nothing uses. Those are what dead code analysis looks
+    // for. Unused helpers of headers are left out, like in the translation.
+    let kept_decls = export_context
+        .c_decls_top
+        .iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    let top_decls = typed_context
+        .c_decls_top
+        .iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    for &decl_id in &typed_context.c_decls_top {
+        let decl = &typed_context[decl_id];
+        if typed_context.file_id(decl) != main_file && !kept_decls.contains(&decl_id) {
+            continue;
+        }
+        let kind = match decl.kind {
+            CDeclKind::Function { body: Some(_), .. } => SymbolKind::Function,
+            CDeclKind::Variable {
+                initializer: Some(_),
+                ..
+            } => SymbolKind::Variable,
+            _ => continue,
+        };
+        if let Some(symbol) = decl_symbol(&typed_context, decl_id, kind) {
+            dependency_info.uses.push(SymbolUses {
+                symbol,
+                uses: used_symbols(&typed_context, decl_id, &top_decls),
+            });
         }
     }
 
@@ -976,7 +1761,99 @@ fn export_single(
 
     dependency_info.output_path = output_path.to_str().unwrap().to_string();
 
-    Ok(dependency_info)
+    Ok(ExportedUnit {
+        dependency_info,
+        typed_context,
+    })
+}
+
+/// The dependency symbol for the function or variable `decl_id`, unless it is
+/// a builtin that does not belong to any file.
+fn decl_symbol(
+    ctx: &TypedAstContext,
+    decl_id: CDeclId,
+    kind: SymbolKind,
+) -> Option<DependencySymbol> {
+    let decl = &ctx[decl_id];
+    let path = ctx.get_file_path(ctx.file_id(decl)?)?;
+    Some(DependencySymbol {
+        name: decl.kind.get_name()?.to_string(),
+        path: path.to_str().unwrap().to_string(),
+        kind,
+    })
+}
+
+/// The functions and global variables referenced from the body or
+/// initializer of `decl_id`, in order of first use.
+fn used_symbols(
+    ctx: &TypedAstContext,
+    decl_id: CDeclId,
+    top_decls: &HashSet<CDeclId>,
+) -> Vec<DependencySymbol> {
+    let mut symbols = vec![];
+    for some_id in DFNodes::new(ctx, SomeId::Decl(decl_id)) {
+        let used_id = match some_id {
+            SomeId::Expr(expr_id) => match ctx[expr_id].kind {
+                CExprKind::DeclRef(_, used_id, _) => used_id,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let kind = match ctx[used_id].kind {
+            CDeclKind::Function { .. } => SymbolKind::Function,
+            // Locals, including static ones, are not top-level declarations
+            CDeclKind::Variable { .. } if top_decls.contains(&used_id) => SymbolKind::Variable,
+            _ => continue,
+        };
+        if let Some(symbol) = decl_symbol(ctx, used_id, kind) {
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+    }
+    symbols
+}
+
+/// Count the functions of `main_file` and the constructs in it that are hard
+/// to port, for planning the migration of the project.
+fn file_metrics(ctx: &TypedAstContext, main_file: Option<FileId>) -> FileMetrics {
+    let mut metrics = FileMetrics::default();
+    for (&decl_id, decl) in ctx.iter_decls() {
+        if main_file.is_none() || ctx.file_id(decl) != main_file {
+            continue;
+        }
+        match decl.kind {
+            CDeclKind::Function {
+                typ, body: Some(_), ..
+            } => {
+                metrics.functions += 1;
+                if let Some(loc) = decl.loc {
+                    metrics.lines += (loc.end_line + 1).saturating_sub(loc.begin_line) as usize;
+                }
+                if let CTypeKind::Function(_, _, true, _, _) = ctx.resolve_type(typ).kind {
+                    metrics.variadic_functions += 1;
+                }
+                for some_id in DFNodes::new(ctx, SomeId::Decl(decl_id)) {
+                    if let SomeId::Stmt(stmt_id) = some_id {
+                        match ctx[stmt_id].kind {
+                            CStmtKind::Goto(_) => metrics.gotos += 1,
+                            CStmtKind::Asm { .. } => metrics.asm += 1,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            CDeclKind::Union {
+                fields: Some(_), ..
+            } => metrics.unions += 1,
+            CDeclKind::Field {
+                bitfield_width: Some(_),
+                ..
+            } => metrics.bitfields += 1,
+            _ => {}
+        }
+    }
+    metrics
 }
 
 fn get_output_path(
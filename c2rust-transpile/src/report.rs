@@ -0,0 +1,259 @@
+//! Machine-readable summary of a translation run.
+//!
+//! For every translation unit the report records whether it was translated,
+//! where the translation was written, which declarations had to be skipped or
+//! replaced (and why), and which features and extern crates it pulled in.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use failure::Fail;
+use log::warn;
+use serde_derive::Serialize;
+
+use crate::c_ast::DisplaySrcSpan;
+use crate::diagnostics::TranslationError;
+use crate::{CrateSet, ExternCrateDetails, PragmaVec};
+
+/// What happened to a translation unit.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitStatus {
+    /// Every declaration was translated
+    Translated,
+    /// The unit was translated, but some declarations were skipped or replaced
+    Partial,
+    /// The exporter could not parse the unit, so it was not translated
+    ExportFailed,
+    /// The output file already existed and `overwrite_existing` was not set
+    SkippedExisting,
+    /// The unit is compiled as C by the generated `build.rs` (hybrid builds)
+    CompiledAsC,
+}
+
+/// What the translator did with a declaration it could not translate.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclOutcome {
+    /// The declaration was left out of the translation
+    Skipped,
+    /// The definition was replaced by an `extern` declaration
+    Replaced,
+}
+
+/// A declaration that could not be translated as is.
+#[derive(Serialize, Debug, Clone)]
+pub struct DeclReport {
+    pub name: Option<String>,
+    /// Source location as `file:line:column`
+    pub location: Option<String>,
+    pub outcome: DeclOutcome,
+    /// The `TranslationErrorKind` of the error
+    pub error_kind: String,
+    pub message: String,
+}
+
+impl DeclReport {
+    pub fn new(
+        name: Option<String>,
+        loc: Option<DisplaySrcSpan>,
+        outcome: DeclOutcome,
+        error: &TranslationError,
+    ) -> Self {
+        // Prefer the innermost location the error points at over the
+        // location of the declaration as a whole
+        let location = error.locations().first().cloned().or(loc);
+        let message = match error.cause() {
+            Some(cause) => cause.to_string(),
+            None => error.kind().to_string(),
+        };
+        Self {
+            name,
+            location: location.map(|loc| loc.to_string()),
+            outcome,
+            error_kind: format!("{:?}", error.kind()),
+            message,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UnitReport {
+    pub input_path: PathBuf,
+    pub object_path: Option<PathBuf>,
+    /// Name of the crate the unit belongs to
+    pub crate_name: String,
+    pub status: UnitStatus,
+    pub output_path: Option<PathBuf>,
+    pub declarations: Vec<DeclReport>,
+    pub features: Vec<&'static str>,
+    pub extern_crates: Vec<&'static str>,
+}
+
+impl UnitReport {
+    pub fn new(
+        input_path: &Path,
+        object_path: &Option<PathBuf>,
+        crate_name: &str,
+        status: UnitStatus,
+    ) -> Self {
+        Self {
+            input_path: input_path.to_path_buf(),
+            object_path: object_path.clone(),
+            crate_name: crate_name.to_owned(),
+            status,
+            output_path: None,
+            declarations: vec![],
+            features: vec![],
+            extern_crates: vec![],
+        }
+    }
+
+    /// Record the outcome of translating the unit into `output_path`.
+    pub fn translated(
+        mut self,
+        output_path: &Path,
+        pragmas: &PragmaVec,
+        crates: &CrateSet,
+        declarations: Vec<DeclReport>,
+    ) -> Self {
+        self.status = if declarations.is_empty() {
+            UnitStatus::Translated
+        } else {
+            UnitStatus::Partial
+        };
+        self.output_path = Some(output_path.to_path_buf());
+        self.declarations = declarations;
+        self.features = pragmas
+            .iter()
+            .filter(|(key, _)| *key == "feature")
+            .flat_map(|(_, vals)| vals.iter().copied())
+            .collect();
+        self.extern_crates = crates
+            .iter()
+            .map(|&extern_crate| ExternCrateDetails::from(extern_crate).name)
+            .collect();
+        self
+    }
+}
+
+/// Report of a whole run. Units may be added from several worker threads.
+#[derive(Serialize, Debug, Default)]
+pub struct TranslationReport {
+    units: Mutex<Vec<UnitReport>>,
+}
+
+impl TranslationReport {
+    pub fn add(&self, unit: UnitReport) {
+        self.units.lock().unwrap().push(unit);
+    }
+
+    /// Write the report to `path` as JSON, ordering units by crate and input
+    /// file so that the output does not depend on the number of jobs. Failing
+    /// to write the report only warns, as the translation itself succeeded.
+    pub fn write(&self, path: &Path) {
+        self.units.lock().unwrap().sort_by(|a, b| {
+            (&a.crate_name, &a.input_path, &a.object_path).cmp(&(
+                &b.crate_name,
+                &b.input_path,
+                &b.object_path,
+            ))
+        });
+
+        println!("Writing translation report to file {}", path.display());
+        let res = File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| e.to_string())
+            });
+        if let Err(e) = res {
+            warn!(
+                "Unable to write translation report to file {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report() -> TranslationReport {
+        let report = TranslationReport::default();
+        let unit = |input: &str, crate_name: &str| {
+            UnitReport::new(
+                Path::new(input),
+                &Some(Path::new(input).with_extension("o")),
+                crate_name,
+                UnitStatus::ExportFailed,
+            )
+        };
+        let declaration = DeclReport {
+            name: Some("f".to_owned()),
+            location: Some("/src/b.c:3:1".to_owned()),
+            outcome: DeclOutcome::Replaced,
+            error_kind: "Generic".to_owned(),
+            message: "unsupported".to_owned(),
+        };
+        report.add(unit("/src/b.c", "tool").translated(
+            Path::new("/out/src/b.rs"),
+            &vec![("feature", vec!["c_variadic"]), ("allow", vec!["unused"])],
+            &CrateSet::new(),
+            vec![declaration],
+        ));
+        report.add(unit("/src/a.c", "tool"));
+        report
+    }
+
+    #[test]
+    fn json_shape() {
+        let report = report();
+        let path = std::env::temp_dir().join(format!("report-{}.json", std::process::id()));
+        report.write(&path);
+        let json: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "units": [
+                    {
+                        "input_path": "/src/a.c",
+                        "object_path": "/src/a.o",
+                        "crate_name": "tool",
+                        "status": "export_failed",
+                        "output_path": null,
+                        "declarations": [],
+                        "features": [],
+                        "extern_crates": [],
+                    },
+                    {
+                        "input_path": "/src/b.c",
+                        "object_path": "/src/b.o",
+                        "crate_name": "tool",
+                        "status": "partial",
+                        "output_path": "/out/src/b.rs",
+                        "declarations": [{
+                            "name": "f",
+                            "location": "/src/b.c:3:1",
+                            "outcome": "replaced",
+                            "error_kind": "Generic",
+                            "message": "unsupported",
+                        }],
+                        "features": ["c_variadic"],
+                        "extern_crates": [],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn unwritable_path_does_not_panic() {
+        report().write(Path::new("/nonexistent-dir/report.json"));
+    }
+}
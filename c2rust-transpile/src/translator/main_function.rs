@@ -5,9 +5,40 @@
 
 use super::*;
 use failure::format_err;
+use log::warn;
 use proc_macro2::{TokenStream, TokenTree};
 
 impl<'c> Translation<'c> {
+    /// The function of a unit without a C `main` that gets a Rust `main`
+    /// instead: the first one the unit defines whose name matches
+    /// `TranspilerConfig::entry_symbols`, like the `ls_main` of an applet.
+    /// Only binaries and the applets of a dispatcher get one; in a library
+    /// unit, such a function is just a function.
+    pub fn entry_function(&self, is_binary: bool) -> Option<CDeclId> {
+        if !is_binary && !self.tcfg.applet_dispatcher {
+            return None;
+        }
+        let mut entries = self.ast_context.c_decls_top.iter().filter_map(|&decl_id| {
+            match self.ast_context.index(decl_id).kind {
+                CDeclKind::Function {
+                    is_global: true,
+                    body: Some(_),
+                    ref name,
+                    ..
+                } if self.tcfg.entry_symbols.matches(name) => Some((decl_id, name)),
+                _ => None,
+            }
+        });
+        let (entry_id, _) = entries.next()?;
+        for (_, name) in entries {
+            warn!(
+                "Not generating a main function for {}, the unit has one already",
+                name
+            );
+        }
+        Some(entry_id)
+    }
+
     pub fn convert_main(&self, main_id: CDeclId) -> TranslationResult<Box<Item>> {
         if let CDeclKind::Function {
             ref parameters,
@@ -255,3 +286,87 @@ impl<'c> Translation<'c> {
         }
     }
 }
+
+/// Translate the `main` of a multi-call binary, which runs the applet the
+/// file name of `argv[0]` names. Every applet is given as its name and the
+/// path of the module with the `main` generated for its entry function.
+pub fn translate_dispatcher(applets: &[(String, Vec<String>)]) -> String {
+    let mut stmts: Vec<Stmt> = vec![];
+    stmts.push(mk().local_stmt(Box::new(mk().local(
+        mk().ident_pat("arg0"),
+        None,
+        Some(mk().method_call_expr(
+            mk().method_call_expr(
+                mk().call_expr(mk().abs_path_expr(vec!["std", "env", "args"]), vec![]),
+                "next",
+                vec![],
+            ),
+            "unwrap_or_default",
+            vec![],
+        )),
+    ))));
+    stmts.push(mk().local_stmt(Box::new(mk().local(
+        mk().ident_pat("applet"),
+        None,
+        Some(mk().method_call_expr(
+            mk().method_call_expr(
+                mk().method_call_expr(
+                    mk().call_expr(
+                        mk().abs_path_expr(vec!["std", "path", "Path", "new"]),
+                        vec![mk().addr_of_expr(mk().ident_expr("arg0"))],
+                    ),
+                    "file_name",
+                    vec![],
+                ),
+                "and_then",
+                vec![mk().abs_path_expr(vec!["std", "ffi", "OsStr", "to_str"])],
+            ),
+            "unwrap_or_default",
+            vec![],
+        )),
+    ))));
+
+    let mut arms = applets
+        .iter()
+        .map(|(name, module_path)| {
+            let mut main_fn = module_path.clone();
+            main_fn.push("main".to_string());
+            mk().arm(
+                mk().lit_pat(mk().lit_expr(name.as_str())),
+                None,
+                mk().call_expr(mk().path_expr(main_fn), vec![]),
+            )
+        })
+        .collect::<Vec<_>>();
+    let not_found = mk().mac_expr(
+        mk().mac(
+            mk().path(vec!["eprintln"]),
+            vec![
+                TokenTree::Literal(proc_macro2::Literal::string("{}: applet not found")),
+                TokenTree::Punct(Punct::new(',', proc_macro2::Spacing::Alone)),
+                TokenTree::Ident(mk().ident("applet")),
+            ]
+            .into_iter()
+            .collect::<TokenStream>(),
+            MacroDelimiter::Paren(Default::default()),
+        ),
+    );
+    let exit = mk().call_expr(
+        mk().abs_path_expr(vec!["std", "process", "exit"]),
+        vec![mk().lit_expr(mk().int_lit(1, "i32"))],
+    );
+    arms.push(mk().arm(
+        mk().wild_pat(),
+        None,
+        mk().block_expr(mk().block(vec![mk().semi_stmt(not_found), mk().semi_stmt(exit)])),
+    ));
+    stmts.push(mk().expr_stmt(mk().match_expr(mk().ident_expr("applet"), arms)));
+
+    let decl = mk().fn_decl("main", vec![], None, ReturnType::Default);
+    let main_fn = mk().pub_().fn_item(decl, mk().block(stmts));
+    pprust::to_string(|| syn::File {
+        shebang: None,
+        attrs: vec![],
+        items: vec![*main_fn],
+    })
+}
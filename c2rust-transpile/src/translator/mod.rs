@@ -17,7 +17,8 @@ use syn::spanned::Spanned as _;
 use syn::*;
 use syn::{BinOp, UnOp}; // To override c_ast::{BinOp,UnOp} from glob import
 
-use crate::diagnostics::TranslationResult;
+use crate::diagnostics::{TranslationError, TranslationResult};
+use crate::report::{DeclOutcome, DeclReport};
 use crate::rust_ast::comment_store::CommentStore;
 use crate::rust_ast::item_store::ItemStore;
 use crate::rust_ast::set_span::SetSpan;
@@ -51,6 +52,7 @@ mod variadic;
 pub use crate::diagnostics::{TranslationError, TranslationErrorKind};
 use crate::CrateSet;
 use crate::PragmaVec;
+pub use main_function::translate_dispatcher;
 
 pub const INNER_SUFFIX: &str = "_Inner";
 pub const PADDING_SUFFIX: &str = "_PADDING";
@@ -248,6 +250,7 @@ pub struct Translation<'c> {
     pub features: RefCell<IndexSet<&'static str>>,
     sectioned_static_initializers: RefCell<Vec<Stmt>>,
     extern_crates: RefCell<CrateSet>,
+    decl_reports: RefCell<Vec<DeclReport>>,
 
     // Translation state and utilities
     type_converter: RefCell<TypeConverter>,
@@ -492,7 +495,7 @@ pub fn translate(
     tcfg: &TranspilerConfig,
     main_file: &PathBuf,
     is_binary: bool,
-) -> (String, PragmaVec, CrateSet) {
+) -> (String, PragmaVec, CrateSet, Vec<DeclReport>) {
     let mut t = Translation::new(ast_context, tcfg, main_file.as_path());
     let ctx = ExprContext {
         used: true,
@@ -645,6 +648,7 @@ pub fn translate(
                 }
                 match t.convert_decl(ctx, decl_id) {
                     Err(e) => {
+                        t.report_decl(decl_id, DeclOutcome::Skipped, &e);
                         let k = &t.ast_context.get_decl(&decl_id).map(|x| &x.kind);
                         let msg = format!("Skipping declaration {:?} due to error: {}", k, e);
                         translate_failure(t.tcfg, &msg);
@@ -719,6 +723,7 @@ pub fn translate(
                 }
                 match t.convert_decl(ctx, *top_id) {
                     Err(e) => {
+                        t.report_decl(*top_id, DeclOutcome::Skipped, &e);
                         let decl = &t.ast_context.get_decl(top_id);
                         let msg = match decl {
                             Some(decl) => {
@@ -764,11 +769,13 @@ pub fn translate(
             }
         }
 
-        // Add the main entry point
-        if let Some(main_id) = t.ast_context.c_main {
+        // Add the main entry point. Units without a C `main` get one if they
+        // define the entry function of an applet.
+        if let Some(main_id) = t.ast_context.c_main.or_else(|| t.entry_function(is_binary)) {
             match t.convert_main(main_id) {
                 Ok(item) => t.items.borrow_mut()[&t.main_file].add_item(item),
                 Err(e) => {
+                    t.report_decl(main_id, DeclOutcome::Skipped, &e);
                     let msg = format!("Failed to translate main: {}", e);
                     translate_failure(t.tcfg, &msg)
                 }
@@ -886,7 +893,8 @@ pub fn translate(
                 items: all_items.into_iter().map(|x| *x).collect(),
             }
         });
-        (translation, pragmas, crates)
+        let decl_reports = t.decl_reports.take();
+        (translation, pragmas, crates, decl_reports)
     }
 }
 
@@ -1245,6 +1253,7 @@ impl<'c> Translation<'c> {
             mod_names: RefCell::new(IndexMap::new()),
             main_file,
             extern_crates: RefCell::new(IndexSet::new()),
+            decl_reports: RefCell::new(Vec::new()),
             cur_file: RefCell::new(None),
         }
     }
@@ -1253,6 +1262,16 @@ impl<'c> Translation<'c> {
         self.extern_crates.borrow_mut().insert(extern_crate);
     }
 
+    /// Record that declaration `decl_id` could not be translated as is.
+    fn report_decl(&self, decl_id: CDeclId, outcome: DeclOutcome, error: &TranslationError) {
+        let decl = self.ast_context.get_decl(&decl_id);
+        let name = decl.and_then(|decl| decl.kind.get_name().cloned());
+        let loc = decl.and_then(|decl| self.ast_context.display_loc(&decl.loc));
+        self.decl_reports
+            .borrow_mut()
+            .push(DeclReport::new(name, loc, outcome, error));
+    }
+
     pub fn cur_file(&self) -> FileId {
         if let Some(cur_file) = *self.cur_file.borrow() {
             cur_file
@@ -1921,21 +1940,25 @@ impl<'c> Translation<'c> {
                 );
 
                 converted_function.or_else(|e| match self.tcfg.replace_unsupported_decls {
-                    ReplaceMode::Extern if body.is_none() => self.convert_function(
-                        ctx,
-                        span,
-                        is_global,
-                        false,
-                        is_main,
-                        is_variadic,
-                        is_extern,
-                        new_name,
-                        name,
-                        &args,
-                        ret,
-                        None,
-                        attrs,
-                    ),
+                    ReplaceMode::Extern if body.is_none() => {
+                        let replacement = self.convert_function(
+                            ctx,
+                            span,
+                            is_global,
+                            false,
+                            is_main,
+                            is_variadic,
+                            is_extern,
+                            new_name,
+                            name,
+                            &args,
+                            ret,
+                            None,
+                            attrs,
+                        )?;
+                        self.report_decl(decl_id, DeclOutcome::Replaced, &e);
+                        Ok(replacement)
+                    }
                     _ => Err(e),
                 })
             }
@@ -0,0 +1,35 @@
+#!/bin/sh
+# Regenerate src/libc_symbols.txt, the functions and variables the `libc`
+# crate binds on x86_64-unknown-linux-gnu, from the source of the crate.
+#
+# Usage: ./gen_libc_symbols.sh [<libc crate directory>]
+#
+# Without an argument, the newest libc crate in the Cargo registry is used;
+# fetch the version to generate the list for first, e.g. with
+# `cargo fetch` in a crate depending on it.
+
+set -eu
+
+here=$(cd "$(dirname "$0")" && pwd)
+crate=${1:-$(ls -d "${CARGO_HOME:-$HOME/.cargo}"/registry/src/*/libc-0.2.* | sort -V | tail -n 1)}
+version=$(sed -n 's/^version = "\(.*\)"$/\1/p' "$crate/Cargo.toml" | head -n 1)
+
+cd "$crate/src"
+{
+    cat <<EOF
+# Functions and variables the \`libc\` crate ($version) binds on
+# x86_64-unknown-linux-gnu, one per line. Generated by gen_libc_symbols.sh,
+# which collects the \`pub fn\` and \`pub static\` items of the modules the
+# crate compiles on that target. The glibc variables the crate leaves out
+# (\`stdin\`, \`stdout\`, \`stderr\`, \`optarg\` and friends) are added by the
+# script.
+EOF
+    {
+        cat unix/mod.rs unix/linux_like/mod.rs unix/linux_like/linux_l4re_shared.rs \
+            unix/linux_like/linux/mod.rs $(find unix/linux_like/linux/gnu \
+            new/common/posix new/common/linux_like new/glibc new/linux_uapi -name '*.rs') |
+            grep -oP '^\s*pub (safe |unsafe )?fn \K\w+|^\s*pub (safe |unsafe )?static (mut )?\K\w+'
+        printf '%s\n' optarg opterr optind optopt program_invocation_name \
+            program_invocation_short_name stderr stdin stdout
+    } | LC_ALL=C sort -u
+} > "$here/src/libc_symbols.txt"
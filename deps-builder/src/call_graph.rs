@@ -0,0 +1,225 @@
+//! Symbol-level graph of which functions and global variables use which,
+//! built from the `uses` recorded for every unit.
+
+use std::collections::HashMap;
+
+use crate::{DependencyInfo, DependencySymbol, EntrySymbols, SymbolKind};
+
+#[derive(Debug, Clone)]
+pub struct CallGraphNode {
+    pub symbol: DependencySymbol,
+    /// Index of the unit defining the symbol
+    pub unit: usize,
+    /// Whether other units can link against the symbol
+    pub is_global: bool,
+}
+
+#[derive(Debug)]
+pub struct CallGraph {
+    pub nodes: Vec<CallGraphNode>,
+    pub edges: Vec<Vec<usize>>,
+}
+
+impl CallGraph {
+    /// Build the graph of the functions and global variables defined by
+    /// `units`. A use resolves to a definition in the same unit if there is
+    /// one, and to every global definition with its name otherwise, so that
+    /// reachability stays conservative when several programs define the same
+    /// symbol. Uses of symbols no unit defines, like libc functions, are
+    /// dropped.
+    pub fn new(units: &[DependencyInfo]) -> Self {
+        let mut nodes = vec![];
+        let mut node_uses = vec![];
+        for (unit, info) in units.iter().enumerate() {
+            let is_global = |symbol: &DependencySymbol| {
+                info.defined
+                    .iter()
+                    .any(|s| s.name == symbol.name && s.kind == symbol.kind)
+            };
+            for symbol_uses in &info.uses {
+                nodes.push(CallGraphNode {
+                    symbol: symbol_uses.symbol.clone(),
+                    unit,
+                    is_global: is_global(&symbol_uses.symbol),
+                });
+                node_uses.push(&symbol_uses.uses[..]);
+            }
+            // Global variables without an initializer use nothing
+            for symbol in &info.defined {
+                if symbol.kind == SymbolKind::Variable
+                    && !info
+                        .uses
+                        .iter()
+                        .any(|s| s.symbol.name == symbol.name && s.symbol.kind == symbol.kind)
+                {
+                    nodes.push(CallGraphNode {
+                        symbol: symbol.clone(),
+                        unit,
+                        is_global: true,
+                    });
+                    node_uses.push(&[]);
+                }
+            }
+        }
+
+        let mut local = HashMap::new();
+        let mut global: HashMap<_, Vec<usize>> = HashMap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            let key = (node.symbol.name.as_str(), node.symbol.kind);
+            local.entry((node.unit, key)).or_insert(i);
+            if node.is_global {
+                global.entry(key).or_default().push(i);
+            }
+        }
+
+        let mut edges = vec![vec![]; nodes.len()];
+        for (i, (node, uses)) in nodes.iter().zip(node_uses).enumerate() {
+            for symbol in uses {
+                let key = (symbol.name.as_str(), symbol.kind);
+                let targets = match local.get(&(node.unit, key)) {
+                    Some(&j) => vec![j],
+                    None => global.get(&key).cloned().unwrap_or_default(),
+                };
+                for j in targets {
+                    if !edges[i].contains(&j) {
+                        edges[i].push(j);
+                    }
+                }
+            }
+        }
+
+        Self { nodes, edges }
+    }
+
+    /// Indices of the entry functions of all programs and applets.
+    pub fn entries(&self, entry_symbols: &EntrySymbols) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| {
+                node.is_global
+                    && node.symbol.kind == SymbolKind::Function
+                    && entry_symbols.matches(&node.symbol.name)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// For every node, whether it is reachable from one of `roots`.
+    pub fn reachable_from(&self, roots: &[usize]) -> Vec<bool> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut queue = roots.to_vec();
+
+        while let Some(current_node_index) = queue.pop() {
+            if visited[current_node_index] {
+                continue;
+            }
+
+            visited[current_node_index] = true;
+            queue.extend(&self.edges[current_node_index]);
+        }
+
+        visited
+    }
+
+    /// For every node, whether some entry function transitively uses it.
+    pub fn reachable_from_entries(&self, entry_symbols: &EntrySymbols) -> Vec<bool> {
+        self.reachable_from(&self.entries(entry_symbols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{symbol, unit};
+    use crate::SymbolUses;
+
+    /// Record that the function `name` of `info` uses the functions `used`
+    fn uses(info: &mut DependencyInfo, name: &str, used: &[(&str, SymbolKind)]) {
+        let path = info.input_path.clone();
+        info.uses.push(SymbolUses {
+            symbol: symbol(name, &path, SymbolKind::Function),
+            uses: used
+                .iter()
+                .map(|&(used, kind)| symbol(used, &path, kind))
+                .collect(),
+        });
+    }
+
+    fn units() -> Vec<DependencyInfo> {
+        use SymbolKind::{Function, Variable};
+
+        // `helper` is static in a.c and global in b.c
+        let mut a = unit("/src/a.c", &["main"], &["parse", "puts"]);
+        a.defined.push(symbol("counter", "/src/a.c", Variable));
+        uses(
+            &mut a,
+            "main",
+            &[
+                ("helper", Function),
+                ("parse", Function),
+                ("puts", Function),
+                ("counter", Variable),
+            ],
+        );
+        uses(&mut a, "helper", &[]);
+        let mut b = unit("/src/b.c", &["parse", "helper"], &[]);
+        uses(&mut b, "parse", &[("helper", Function)]);
+        uses(&mut b, "helper", &[]);
+        let mut c = unit("/src/c.c", &["parse", "unused"], &[]);
+        uses(&mut c, "parse", &[]);
+        uses(&mut c, "unused", &[("parse", Function)]);
+        vec![a, b, c]
+    }
+
+    #[test]
+    fn nodes_and_edges() {
+        let call_graph = CallGraph::new(&units());
+        let nodes = call_graph
+            .nodes
+            .iter()
+            .map(|node| (node.symbol.name.as_str(), node.unit, node.is_global))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                ("main", 0, true),
+                ("helper", 0, false),
+                ("counter", 0, true),
+                ("parse", 1, true),
+                ("helper", 1, true),
+                ("parse", 2, true),
+                ("unused", 2, true),
+            ]
+        );
+        // Local definitions win, global ones are all used, and `puts` is
+        // dropped
+        assert_eq!(
+            call_graph.edges,
+            vec![
+                vec![1, 3, 5, 2],
+                vec![],
+                vec![],
+                vec![4],
+                vec![],
+                vec![],
+                vec![5]
+            ]
+        );
+    }
+
+    #[test]
+    fn reachable_from_entries() {
+        let call_graph = CallGraph::new(&units());
+        let entry_symbols = EntrySymbols::default();
+        assert_eq!(call_graph.entries(&entry_symbols), vec![0]);
+        assert_eq!(
+            call_graph.reachable_from_entries(&entry_symbols),
+            vec![true, true, true, true, true, true, false]
+        );
+        assert_eq!(
+            call_graph.reachable_from(&[6]),
+            vec![false, false, false, false, false, true, true]
+        );
+    }
+}
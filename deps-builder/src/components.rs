@@ -0,0 +1,185 @@
+//! Strongly connected components of the file-level dependency graph.
+//!
+//! Files in the same component depend on each other in a cycle and thus have
+//! to be translated, tested and put into crates together. Condensing every
+//! component into a single node yields a DAG whose topological order is an
+//! order in which the files can be translated bottom-up.
+
+use crate::DependencyGraph;
+
+/// The DAG of the strongly connected components of a dependency graph.
+#[derive(Debug)]
+pub struct Condensation {
+    /// Nodes of the dependency graph in each component. Components are in
+    /// topological order: every component comes after the ones it depends on.
+    pub components: Vec<Vec<usize>>,
+    /// Index of the component of each node of the dependency graph
+    pub component_of: Vec<usize>,
+    pub edges: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    /// Strongly connected components using Tarjan's algorithm. A component is
+    /// listed only after all the components it depends on.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        const UNVISITED: usize = usize::MAX;
+
+        let num_nodes = self.nodes.len();
+        let mut index = vec![UNVISITED; num_nodes];
+        let mut lowlink = vec![0; num_nodes];
+        let mut on_stack = vec![false; num_nodes];
+        let mut stack = vec![];
+        let mut components = vec![];
+        let mut next_index = 0;
+
+        for start in 0..num_nodes {
+            if index[start] != UNVISITED {
+                continue;
+            }
+
+            // Explicit call stack of (node, index of the next edge to follow)
+            // so that long dependency chains cannot overflow the stack
+            let mut call_stack = vec![(start, 0)];
+            index[start] = next_index;
+            lowlink[start] = next_index;
+            next_index += 1;
+            stack.push(start);
+            on_stack[start] = true;
+
+            while let Some(&mut (node, ref mut next_edge)) = call_stack.last_mut() {
+                if let Some(&next) = self.edges[node].get(*next_edge) {
+                    *next_edge += 1;
+                    if index[next] == UNVISITED {
+                        index[next] = next_index;
+                        lowlink[next] = next_index;
+                        next_index += 1;
+                        stack.push(next);
+                        on_stack[next] = true;
+                        call_stack.push((next, 0));
+                    } else if on_stack[next] {
+                        lowlink[node] = lowlink[node].min(index[next]);
+                    }
+                    continue;
+                }
+
+                call_stack.pop();
+                if let Some(&(parent, _)) = call_stack.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[node]);
+                }
+                if lowlink[node] == index[node] {
+                    let mut component = vec![];
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    component.sort_unstable();
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    /// The groups of files that depend on each other in a cycle, i.e., the
+    /// components with more than one file and files that depend on themselves.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|component| match component[..] {
+                [node] => self.direct_depends_on(node, node),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Condense every strongly connected component into a single node.
+    pub fn condense(&self) -> Condensation {
+        let components = self.strongly_connected_components();
+        let mut component_of = vec![0; self.nodes.len()];
+        for (i, component) in components.iter().enumerate() {
+            for &node in component {
+                component_of[node] = i;
+            }
+        }
+
+        let mut edges = vec![vec![]; components.len()];
+        for (from, targets) in self.edges.iter().enumerate() {
+            let from = component_of[from];
+            for &to in targets {
+                let to = component_of[to];
+                if from != to && !edges[from].contains(&to) {
+                    edges[from].push(to);
+                }
+            }
+        }
+
+        Condensation {
+            components,
+            component_of,
+            edges,
+        }
+    }
+
+    /// Groups of files in an order in which they can be translated and
+    /// tested bottom-up: every group only depends on itself and on earlier
+    /// groups. Files in a cycle form a group of their own.
+    pub fn translation_order(&self) -> Vec<Vec<usize>> {
+        self.strongly_connected_components()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::plain_graph;
+
+    #[test]
+    fn cycle_with_tail() {
+        // 0 -> 1 -> 2 -> 0 is a cycle that 3 depends on, and 4 depends on 3
+        let graph = plain_graph(5, &[(0, 1), (1, 2), (2, 0), (3, 0), (4, 3)]);
+        assert_eq!(
+            graph.strongly_connected_components(),
+            vec![vec![0, 1, 2], vec![3], vec![4]]
+        );
+        assert_eq!(graph.cycles(), vec![vec![0, 1, 2]]);
+        assert_eq!(
+            graph.translation_order(),
+            vec![vec![0, 1, 2], vec![3], vec![4]]
+        );
+
+        let condensation = graph.condense();
+        assert_eq!(condensation.component_of, vec![0, 0, 0, 1, 2]);
+        assert_eq!(condensation.edges, vec![vec![], vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        // 0 depends on 1 and 2, which depend on each other, and 2 on 3
+        let graph = plain_graph(4, &[(0, 1), (0, 2), (1, 2), (2, 1), (2, 3)]);
+        assert_eq!(
+            graph.translation_order(),
+            vec![vec![3], vec![1, 2], vec![0]]
+        );
+    }
+
+    #[test]
+    fn self_dependency_is_a_cycle() {
+        let graph = plain_graph(2, &[(0, 0), (1, 0)]);
+        assert_eq!(graph.cycles(), vec![vec![0]]);
+    }
+
+    #[test]
+    fn long_chain() {
+        // Deep enough to overflow the stack of a recursive implementation
+        let n = 100_000;
+        let edges = (1..n).map(|i| (i, i - 1)).collect::<Vec<_>>();
+        let components = plain_graph(n, &edges).strongly_connected_components();
+        assert_eq!(components.len(), n);
+        assert_eq!(components[0], vec![0]);
+        assert_eq!(components[n - 1], vec![n - 1]);
+    }
+}
@@ -0,0 +1,388 @@
+//! Comparison of the dependency graphs of two runs, like before and after an
+//! upstream change to the C code or to the translation flags, which tells
+//! which translated crates have to be regenerated.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use serde::Serialize;
+
+use crate::matching::MatchStrategy;
+use crate::unresolved::SymbolSource;
+use crate::{DependencyGraph, DependencySymbol};
+
+/// A unit of either graph. Units of the two graphs are the same if they are
+/// compiled from the same source file to the same output file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct UnitId {
+    pub input_path: String,
+    pub output_path: String,
+}
+
+impl fmt::Display for UnitId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.input_path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EdgeChange {
+    pub from: UnitId,
+    pub to: UnitId,
+    /// The symbols `from` uses from `to` in the graph with the edge
+    pub symbols: Vec<DependencySymbol>,
+}
+
+/// An edge of both graphs that stands for different symbols in each.
+#[derive(Debug, Clone, Serialize)]
+pub struct EdgeSymbolsChange {
+    pub from: UnitId,
+    pub to: UnitId,
+    /// The symbols `from` uses from `to` only in the new graph
+    pub added: Vec<DependencySymbol>,
+    /// The symbols `from` uses from `to` only in the old graph
+    pub removed: Vec<DependencySymbol>,
+}
+
+/// A function or variable a unit uses that nothing in the new graph provides
+/// but something did in the old one, or that the unit did not use before.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedChange {
+    pub unit: UnitId,
+    pub symbol: DependencySymbol,
+    pub source: SymbolSource,
+}
+
+/// A binary of both graphs whose translation includes different units.
+#[derive(Debug, Clone, Serialize)]
+pub struct BinaryChange {
+    pub binary: UnitId,
+    pub added: Vec<UnitId>,
+    pub removed: Vec<UnitId>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GraphDiff {
+    pub added_nodes: Vec<UnitId>,
+    pub removed_nodes: Vec<UnitId>,
+    pub added_edges: Vec<EdgeChange>,
+    pub removed_edges: Vec<EdgeChange>,
+    pub changed_edges: Vec<EdgeSymbolsChange>,
+    /// Newly unresolved symbols, not counting the ones libc provides
+    pub unresolved: Vec<UnresolvedChange>,
+    pub changed_binaries: Vec<BinaryChange>,
+}
+
+/// The symbols behind every edge of a graph
+type EdgeSymbols = BTreeMap<(UnitId, UnitId), Vec<DependencySymbol>>;
+
+/// The edges of `edges` that `other` doesn't have.
+fn edge_changes(edges: &EdgeSymbols, other: &EdgeSymbols) -> Vec<EdgeChange> {
+    edges
+        .iter()
+        .filter(|(edge, _)| !other.contains_key(edge))
+        .map(|((from, to), symbols)| EdgeChange {
+            from: from.clone(),
+            to: to.clone(),
+            symbols: symbols.clone(),
+        })
+        .collect()
+}
+
+/// The edges of both `old` and `new` whose symbols differ.
+fn edge_symbols_changes(old: &EdgeSymbols, new: &EdgeSymbols) -> Vec<EdgeSymbolsChange> {
+    let difference = |symbols: &[DependencySymbol], other: &[DependencySymbol]| {
+        symbols
+            .iter()
+            .filter(|symbol| !other.contains(symbol))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    new.iter()
+        .filter_map(|(edge, new_symbols)| {
+            let old_symbols = old.get(edge)?;
+            let added = difference(new_symbols, old_symbols);
+            let removed = difference(old_symbols, new_symbols);
+            if added.is_empty() && removed.is_empty() {
+                return None;
+            }
+            let (from, to) = edge;
+            Some(EdgeSymbolsChange {
+                from: from.clone(),
+                to: to.clone(),
+                added,
+                removed,
+            })
+        })
+        .collect()
+}
+
+impl DependencyGraph {
+    pub fn unit_id(&self, node: usize) -> UnitId {
+        UnitId {
+            input_path: self.nodes[node].input_path.clone(),
+            output_path: self.nodes[node].output_path.clone(),
+        }
+    }
+
+    /// How `new` differs from this graph. The symbols behind the edges of
+    /// both graphs are matched with `strategy`, and the binaries of each
+    /// graph are the files its entry functions are in.
+    pub fn diff(&self, new: &DependencyGraph, strategy: MatchStrategy) -> GraphDiff {
+        let old = self;
+        let old_ids = (0..old.nodes.len())
+            .map(|i| old.unit_id(i))
+            .collect::<Vec<_>>();
+        let new_ids = (0..new.nodes.len())
+            .map(|i| new.unit_id(i))
+            .collect::<Vec<_>>();
+        let mut diff = GraphDiff::default();
+
+        let old_nodes = old_ids.iter().collect::<HashSet<_>>();
+        let new_nodes = new_ids.iter().collect::<HashSet<_>>();
+        diff.added_nodes = new_ids
+            .iter()
+            .filter(|id| !old_nodes.contains(id))
+            .cloned()
+            .collect();
+        diff.removed_nodes = old_ids
+            .iter()
+            .filter(|id| !new_nodes.contains(id))
+            .cloned()
+            .collect();
+        diff.added_nodes.sort();
+        diff.removed_nodes.sort();
+
+        let edges = |graph: &DependencyGraph, ids: &[UnitId]| {
+            graph
+                .edges
+                .iter()
+                .zip(graph.edge_symbols(strategy))
+                .enumerate()
+                .flat_map(|(from, (edges, symbols))| {
+                    edges
+                        .iter()
+                        .zip(symbols)
+                        .map(move |(&to, symbols)| (from, to, symbols))
+                })
+                .map(|(from, to, symbols)| ((ids[from].clone(), ids[to].clone()), symbols))
+                .collect::<EdgeSymbols>()
+        };
+        let old_edges = edges(old, &old_ids);
+        let new_edges = edges(new, &new_ids);
+        diff.added_edges = edge_changes(&new_edges, &old_edges);
+        diff.removed_edges = edge_changes(&old_edges, &new_edges);
+        diff.changed_edges = edge_symbols_changes(&old_edges, &new_edges);
+
+        let old_unresolved = old
+            .unresolved_symbols()
+            .into_iter()
+            .filter(|unresolved| unresolved.source != SymbolSource::Libc)
+            .map(|unresolved| {
+                (
+                    &old_ids[unresolved.node],
+                    unresolved.symbol.name,
+                    unresolved.symbol.kind,
+                )
+            })
+            .collect::<HashSet<_>>();
+        for unresolved in new.unresolved_symbols() {
+            let unit = &new_ids[unresolved.node];
+            let key = (unit, unresolved.symbol.name.clone(), unresolved.symbol.kind);
+            if unresolved.source != SymbolSource::Libc && !old_unresolved.contains(&key) {
+                diff.unresolved.push(UnresolvedChange {
+                    unit: unit.clone(),
+                    symbol: unresolved.symbol,
+                    source: unresolved.source,
+                });
+            }
+        }
+
+        let old_index = old_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id, i))
+            .collect::<HashMap<_, _>>();
+        for binary in new.entries() {
+            let old_binary = match old_index.get(&new_ids[binary]) {
+                Some(&i) if old.is_entry(i) => i,
+                _ => continue,
+            };
+            let units = |graph: &DependencyGraph, ids: &[UnitId], binary: usize| {
+                graph
+                    .reachable_nodes(vec![binary])
+                    .into_iter()
+                    .map(|i| ids[i].clone())
+                    .collect::<BTreeSet<_>>()
+            };
+            let old_units = units(old, &old_ids, old_binary);
+            let new_units = units(new, &new_ids, binary);
+            if old_units != new_units {
+                diff.changed_binaries.push(BinaryChange {
+                    binary: new_ids[binary].clone(),
+                    added: new_units.difference(&old_units).cloned().collect(),
+                    removed: old_units.difference(&new_units).cloned().collect(),
+                });
+            }
+        }
+
+        diff
+    }
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_edges.is_empty()
+            && self.unresolved.is_empty()
+            && self.changed_binaries.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// One line per change, with files given by their input path.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (sign, nodes) in [('+', &self.added_nodes), ('-', &self.removed_nodes)] {
+            for node in nodes {
+                writeln!(out, "{} file {}", sign, node).unwrap();
+            }
+        }
+        for (sign, edges) in [('+', &self.added_edges), ('-', &self.removed_edges)] {
+            for edge in edges {
+                let symbols = edge
+                    .symbols
+                    .iter()
+                    .map(|symbol| symbol.name.as_str())
+                    .collect::<Vec<_>>();
+                writeln!(
+                    out,
+                    "{} edge {} -> {}: {}",
+                    sign,
+                    edge.from,
+                    edge.to,
+                    symbols.join(", ")
+                )
+                .unwrap();
+            }
+        }
+        for edge in &self.changed_edges {
+            let symbols = edge
+                .added
+                .iter()
+                .map(|symbol| format!("+{}", symbol.name))
+                .chain(
+                    edge.removed
+                        .iter()
+                        .map(|symbol| format!("-{}", symbol.name)),
+                )
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "~ edge {} -> {}: {}",
+                edge.from,
+                edge.to,
+                symbols.join(" ")
+            )
+            .unwrap();
+        }
+        for unresolved in &self.unresolved {
+            let source = match &unresolved.source {
+                SymbolSource::Library(libraries) => format!("from {}", libraries.join(" ")),
+                _ => "missing".to_string(),
+            };
+            writeln!(
+                out,
+                "unresolved {}: {} {:?} ({}, {})",
+                unresolved.unit,
+                unresolved.symbol.name,
+                unresolved.symbol.kind,
+                unresolved.symbol.path,
+                source
+            )
+            .unwrap();
+        }
+        for binary in &self.changed_binaries {
+            let units = binary
+                .added
+                .iter()
+                .map(|unit| format!("+{}", unit))
+                .chain(binary.removed.iter().map(|unit| format!("-{}", unit)))
+                .collect::<Vec<_>>();
+            writeln!(out, "binary {}: {}", binary.binary, units.join(" ")).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_dependency;
+    use crate::tests::unit;
+
+    fn old_graph() -> DependencyGraph {
+        build_dependency(
+            vec![
+                unit("/src/main.c", &["main"], &["parse"]),
+                unit("/src/parse.c", &["parse", "parse_all"], &["lex"]),
+                unit("/src/lex.c", &["lex"], &[]),
+            ],
+            MatchStrategy::NameOnly,
+        )
+    }
+
+    #[test]
+    fn same_graph() {
+        let diff = old_graph().diff(&old_graph(), MatchStrategy::NameOnly);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_text(), "");
+    }
+
+    #[test]
+    fn changes() {
+        // `parse.c` stops using `lex.c` and gets a new `token.c` instead, and
+        // `main` calls `parse_all` rather than `parse`
+        let new = build_dependency(
+            vec![
+                unit("/src/main.c", &["main"], &["parse_all"]),
+                unit("/src/parse.c", &["parse", "parse_all"], &["next_token"]),
+                unit("/src/lex.c", &["lex"], &[]),
+                unit("/src/token.c", &["next_token"], &[]),
+            ],
+            MatchStrategy::NameOnly,
+        );
+        let diff = old_graph().diff(&new, MatchStrategy::NameOnly);
+        assert_eq!(
+            diff.to_text(),
+            "+ file /src/token.c\n\
+             + edge /src/parse.c -> /src/token.c: next_token\n\
+             - edge /src/parse.c -> /src/lex.c: lex\n\
+             ~ edge /src/main.c -> /src/parse.c: +parse_all -parse\n\
+             binary /src/main.c: +/src/token.c -/src/lex.c\n"
+        );
+        assert!(diff.unresolved.is_empty());
+    }
+
+    #[test]
+    fn unresolved() {
+        let new = build_dependency(
+            vec![
+                unit("/src/main.c", &["main"], &["parse"]),
+                unit("/src/parse.c", &["parse", "parse_all"], &["lex"]),
+            ],
+            MatchStrategy::NameOnly,
+        );
+        let diff = old_graph().diff(&new, MatchStrategy::NameOnly);
+        assert_eq!(diff.removed_nodes.len(), 1);
+        assert_eq!(diff.unresolved.len(), 1);
+        assert_eq!(diff.unresolved[0].unit.input_path, "/src/parse.c");
+        assert_eq!(diff.unresolved[0].symbol.name, "lex");
+        assert_eq!(diff.unresolved[0].source, SymbolSource::Missing);
+    }
+}
@@ -0,0 +1,152 @@
+//! The functions programs start at. Besides `main`, multi-call binaries like
+//! BusyBox define one entry function per applet, such as `ls_main` and
+//! `cat_main`, and pick the applet to run from `argv[0]`.
+
+use crate::{DependencyGraph, DependencyInfo, DependencySymbol, SymbolKind};
+
+/// Patterns for the names of entry functions. A `*` in a pattern matches any
+/// run of characters, and the part of the name it matches is the name of the
+/// applet, so `*_main` makes `ls_main` the entry function of `ls`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySymbols {
+    patterns: Vec<String>,
+}
+
+impl Default for EntrySymbols {
+    fn default() -> Self {
+        Self {
+            patterns: vec!["main".to_string()],
+        }
+    }
+}
+
+impl EntrySymbols {
+    /// Entry functions named by `patterns`, or just `main` without any.
+    /// `main` stays an entry function unless some pattern matches it.
+    pub fn new(patterns: Vec<String>) -> Self {
+        if patterns.is_empty() {
+            return Self::default();
+        }
+        let mut entry_symbols = Self { patterns };
+        if !entry_symbols.matches("main") {
+            entry_symbols.patterns.push("main".to_string());
+        }
+        entry_symbols
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.applet_name(name).is_some()
+    }
+
+    /// The name of the applet `name` is the entry function of: what the
+    /// first matching pattern's `*` matches, or the whole name for patterns
+    /// without one.
+    pub fn applet_name<'a>(&self, name: &'a str) -> Option<&'a str> {
+        self.patterns
+            .iter()
+            .find_map(|pattern| match pattern.split_once('*') {
+                Some((prefix, suffix)) => name
+                    .strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_suffix(suffix))
+                    .filter(|applet| !applet.is_empty()),
+                None if pattern == name => Some(name),
+                None => None,
+            })
+    }
+}
+
+impl DependencyInfo {
+    /// The global functions of the unit that match `entry_symbols`.
+    pub fn entry_functions<'a>(
+        &'a self,
+        entry_symbols: &'a EntrySymbols,
+    ) -> impl Iterator<Item = &'a DependencySymbol> {
+        self.defined
+            .iter()
+            .filter(move |s| s.kind == SymbolKind::Function && entry_symbols.matches(&s.name))
+    }
+
+    pub fn is_entry(&self, entry_symbols: &EntrySymbols) -> bool {
+        self.entry_functions(entry_symbols).next().is_some()
+    }
+}
+
+impl DependencyGraph {
+    /// Whether a program starts in the file, i.e., it defines `main` or the
+    /// entry function of an applet.
+    pub fn is_entry(&self, node: usize) -> bool {
+        self.nodes[node].is_entry(&self.entry_symbols)
+    }
+
+    /// The files programs start in.
+    pub fn entries(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&i| self.is_entry(i))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{graph, symbol, unit};
+
+    fn applets() -> EntrySymbols {
+        EntrySymbols::new(vec!["*_main".to_string()])
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(EntrySymbols::new(vec![]), EntrySymbols::default());
+        assert_eq!(applets().patterns(), ["*_main", "main"]);
+        // `*` matches at least one character, so `*main` leaves out `main`
+        let suffix = EntrySymbols::new(vec!["*main".to_string()]);
+        assert_eq!(suffix.patterns(), ["*main", "main"]);
+        let explicit = EntrySymbols::new(vec!["main".to_string(), "*_main".to_string()]);
+        assert_eq!(explicit.patterns(), ["main", "*_main"]);
+    }
+
+    #[test]
+    fn applet_names() {
+        let entry_symbols = applets();
+        assert_eq!(entry_symbols.applet_name("ls_main"), Some("ls"));
+        assert_eq!(entry_symbols.applet_name("main"), Some("main"));
+        assert_eq!(entry_symbols.applet_name("_main"), None);
+        assert_eq!(entry_symbols.applet_name("ls_main_helper"), None);
+        assert!(!EntrySymbols::default().matches("ls_main"));
+    }
+
+    #[test]
+    fn entry_functions() {
+        let entry_symbols = applets();
+        let mut ls = unit("/src/ls.c", &["ls_main", "list"], &[]);
+        ls.defined
+            .push(symbol("cat_main", "/src/ls.c", SymbolKind::Variable));
+        let names = ls
+            .entry_functions(&entry_symbols)
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["ls_main"]);
+        assert!(!ls.is_main());
+        assert!(!unit("/src/list.c", &["list"], &[]).is_entry(&entry_symbols));
+    }
+
+    #[test]
+    fn entries() {
+        let mut graph = graph(
+            vec![
+                unit("/src/main.c", &["main"], &[]),
+                unit("/src/ls.c", &["ls_main"], &[]),
+                unit("/src/list.c", &["list"], &[]),
+            ],
+            &[],
+        );
+        assert_eq!(graph.entries(), vec![0]);
+        graph.entry_symbols = applets();
+        assert_eq!(graph.entries(), vec![0, 1]);
+    }
+}
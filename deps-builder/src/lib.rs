@@ -1,33 +1,86 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::option;
 use std::path::Path;
 
+pub mod call_graph;
+pub mod components;
+pub mod diff;
+pub mod entry;
+pub mod matching;
+pub mod objects;
+pub mod output;
+pub mod partition;
+pub mod plan;
+pub mod projects;
+pub mod query;
+pub mod unresolved;
+
+pub use entry::EntrySymbols;
+pub use matching::MatchStrategy;
+use matching::Resolver;
+use plan::FileMetrics;
+
+/// What a symbol names. Functions and variables are resolved by the linker;
+/// the other kinds are compiled into every unit that includes their header.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    #[default]
+    Function,
+    Variable,
+    /// A struct or union
+    Record,
+    Typedef,
+    Enum,
+    Macro,
+}
+
+impl SymbolKind {
+    pub fn is_linked(self) -> bool {
+        matches!(self, SymbolKind::Function | SymbolKind::Variable)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct DependencySymbol {
     pub name: String,
+    /// File the symbol is declared in; for types and macros from a header
+    /// this is the header
     pub path: String,
+    #[serde(default)]
+    pub kind: SymbolKind,
 }
 
-impl DependencySymbol {
-    pub fn depends_on(&self, other: &Self, fuzz_depends_level: usize) -> bool {
-        match fuzz_depends_level {
-            0 => self == other,
-            1 => {
-                self.name == other.name
-                    && Path::new(&self.path).parent() == Path::new(&other.path).parent()
-                    && Path::new(&self.path).file_stem() == Path::new(&other.path).file_stem()
-            }
-            2 => {
-                self.name == other.name
-                    && Path::new(&self.path).parent() == Path::new(&other.path).parent()
-            }
-            3 => self.name == other.name,
-            _ => true,
-        }
-    }
+/// A function or variable defined by more than one unit.
+#[derive(Debug, Clone)]
+pub struct DuplicateDefinition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The units defining the symbol
+    pub nodes: Vec<usize>,
+    /// Whether some target links more than one of the definitions, so that
+    /// the translated crate would define the symbol twice
+    pub conflicting: bool,
+}
+
+/// An `#include` of `header` in `file`, which is the unit's source file or
+/// another header.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Include {
+    pub file: String,
+    pub header: String,
+}
+
+/// The functions and global variables a function body or global variable
+/// initializer refers to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolUses {
+    pub symbol: DependencySymbol,
+    pub uses: Vec<DependencySymbol>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,8 +88,46 @@ pub struct DependencyInfo {
     pub input_path: String,
     pub output_path: String,
     pub object_path: Option<String>,
+    /// Arguments of the compile command this unit was built with
+    #[serde(default)]
+    pub compile_args: Vec<String>,
+    /// Macros defined with `-D` in the compile command
+    #[serde(default)]
+    pub defines: Vec<String>,
     pub undefined: Vec<DependencySymbol>,
     pub defined: Vec<DependencySymbol>,
+    /// What each function and initialized global variable of the unit uses,
+    /// including its static ones
+    #[serde(default)]
+    pub uses: Vec<SymbolUses>,
+    /// Outputs of the link commands whose result includes this unit: the one
+    /// it is linked into directly first, followed by the ones linking that as
+    /// a library
+    #[serde(default)]
+    pub link_targets: Vec<String>,
+    /// Outputs of the link commands the unit is linked into directly, which
+    /// are more than one for a unit several link commands share. Without
+    /// them, the first of `link_targets` is the one.
+    #[serde(default)]
+    pub direct_link_targets: Vec<String>,
+    /// Libraries outside of the build that any of the link targets link in,
+    /// as given with `-l`
+    #[serde(default)]
+    pub link_libs: Vec<String>,
+    /// The `#include` edges between the files making up the unit
+    #[serde(default)]
+    pub includes: Vec<Include>,
+    /// The symbols the unit defines or uses weakly. Only known for units
+    /// read from object files.
+    #[serde(default)]
+    pub weak: Vec<String>,
+    /// Constructs of the unit that make it harder to port
+    #[serde(default)]
+    pub metrics: FileMetrics,
+    /// The project the unit belongs to, when the dependency files of several
+    /// projects are merged
+    #[serde(default)]
+    pub project: Option<String>,
 }
 
 impl PartialEq for DependencyInfo {
@@ -46,8 +137,70 @@ impl PartialEq for DependencyInfo {
 }
 
 impl DependencyInfo {
+    /// Whether the unit defines `main` itself. See `is_entry` for the
+    /// entry functions of applets.
     pub fn is_main(&self) -> bool {
-        self.defined.iter().any(|s| s.name == "main")
+        self.defined
+            .iter()
+            .any(|s| s.name == "main" && s.kind == SymbolKind::Function)
+    }
+
+    /// The file name of the output file, prefixed with the project in a
+    /// merged graph, as in `tool:main.rs`.
+    pub fn label(&self) -> String {
+        let file_name = Path::new(&self.output_path).file_name().map_or_else(
+            || self.output_path.clone(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        match &self.project {
+            Some(project) => format!("{}:{}", project, file_name),
+            None => file_name,
+        }
+    }
+
+    /// The outputs of the link commands the unit is linked into directly.
+    pub fn direct_link_targets(&self) -> &[String] {
+        if self.direct_link_targets.is_empty() {
+            &self.link_targets[..self.link_targets.len().min(1)]
+        } else {
+            &self.direct_link_targets
+        }
+    }
+
+    /// How close `other` is to this unit from the linker's point of view: `0`
+    /// if both are linked into the same target directly, `1` if some target
+    /// includes both, and `2` otherwise. Without link information every unit
+    /// is as close as any other.
+    pub fn link_distance(&self, other: &Self) -> usize {
+        if self.link_targets.is_empty() || other.link_targets.is_empty() {
+            return 0;
+        }
+        let other_direct = other.direct_link_targets();
+        if self
+            .direct_link_targets()
+            .iter()
+            .any(|target| other_direct.contains(target))
+        {
+            0
+        } else if self
+            .link_targets
+            .iter()
+            .any(|target| other.link_targets.contains(target))
+        {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Whether this unit is compiled from the source file that goes with
+    /// `header`, i.e., one in the same directory with the same stem.
+    pub fn is_companion_of(&self, header: &str) -> bool {
+        let input_path = Path::new(&self.input_path);
+        let header = Path::new(header);
+        input_path != header
+            && input_path.parent() == header.parent()
+            && input_path.file_stem() == header.file_stem()
     }
 }
 
@@ -55,6 +208,12 @@ impl DependencyInfo {
 pub struct DependencyGraph {
     pub nodes: Vec<DependencyInfo>,
     pub edges: Vec<Vec<usize>>,
+    /// Which functions programs start at
+    pub entry_symbols: EntrySymbols,
+    /// Nodes by input path, for `get_node_index_with_input`
+    inputs: HashMap<String, Vec<usize>>,
+    /// First node with each output path, for `get_node_index_with_output`
+    outputs: HashMap<String, usize>,
 }
 
 impl DependencyGraph {
@@ -62,10 +221,21 @@ impl DependencyGraph {
         Self {
             nodes: Vec::new(),
             edges: Vec::new(),
+            entry_symbols: EntrySymbols::default(),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
         }
     }
 
     pub fn add_node(&mut self, node: DependencyInfo) {
+        let index = self.nodes.len();
+        self.inputs
+            .entry(node.input_path.clone())
+            .or_default()
+            .push(index);
+        self.outputs
+            .entry(node.output_path.clone())
+            .or_insert(index);
         self.nodes.push(node);
         self.edges.push(Vec::new());
     }
@@ -73,23 +243,77 @@ impl DependencyGraph {
     pub fn add_edge(&mut self, from: usize, to: usize) {
         assert!(from < self.nodes.len());
         assert!(to < self.nodes.len());
-        self.edges[from].push(to);
+        if !self.edges[from].contains(&to) {
+            self.edges[from].push(to);
+        }
     }
 
-    pub fn build_dependency_edges(&mut self, fuzz_depends_level: usize) {
+    /// Add an edge from every unit to the units providing the symbols it
+    /// uses, matched with `strategy`.
+    pub fn build_dependency_edges(&mut self, strategy: MatchStrategy) {
+        let dependencies = {
+            let resolver = Resolver::new(self, strategy);
+            (0..self.nodes.len())
+                .map(|i| resolver.dependencies(i))
+                .collect::<Vec<_>>()
+        };
+        // A set per node keeps this linear in the number of edges, where
+        // `add_edge` would scan the edges of the node every time
+        for (i, targets) in dependencies.into_iter().enumerate() {
+            let mut seen = self.edges[i].iter().copied().collect::<HashSet<_>>();
+            for j in targets {
+                if seen.insert(j) {
+                    self.edges[i].push(j);
+                }
+            }
+        }
+    }
+
+    /// Functions and variables other than `main` that more than one unit
+    /// defines, in the order of their first definition.
+    pub fn duplicate_definitions(&self) -> Vec<DuplicateDefinition> {
+        let mut definitions: HashMap<(&str, SymbolKind), Vec<usize>> = HashMap::new();
+        let mut order = vec![];
         for (i, node) in self.nodes.iter().enumerate() {
-            for symbol in &node.undefined {
-                self.nodes.iter().enumerate().for_each(|(j, n)| {
-                    if !n.is_main()
-                        && n.defined
-                            .iter()
-                            .any(|s| s.depends_on(symbol, fuzz_depends_level))
-                    {
-                        self.edges[i].push(j);
-                    }
+            for symbol in &node.defined {
+                // Weak definitions give way to any other one
+                if !symbol.kind.is_linked()
+                    || symbol.name == "main"
+                    || node.weak.contains(&symbol.name)
+                {
+                    continue;
+                }
+                let key = (symbol.name.as_str(), symbol.kind);
+                let nodes = definitions.entry(key).or_insert_with(|| {
+                    order.push(key);
+                    vec![]
                 });
+                if nodes.last() != Some(&i) {
+                    nodes.push(i);
+                }
             }
         }
+
+        order
+            .into_iter()
+            .filter_map(|key| {
+                let nodes = definitions.remove(&key)?;
+                if nodes.len() < 2 {
+                    return None;
+                }
+                let conflicting = nodes.iter().enumerate().any(|(n, &i)| {
+                    nodes[n + 1..]
+                        .iter()
+                        .any(|&j| self.nodes[i].link_distance(&self.nodes[j]) < 2)
+                });
+                Some(DuplicateDefinition {
+                    name: key.0.to_string(),
+                    kind: key.1,
+                    nodes,
+                    conflicting,
+                })
+            })
+            .collect()
     }
 
     pub fn get_node_index_with_input(
@@ -97,15 +321,15 @@ impl DependencyGraph {
         input_path: &String,
         object_path: &Option<String>,
     ) -> Option<usize> {
-        self.nodes
+        self.inputs
+            .get(input_path)?
             .iter()
-            .position(|node| node.input_path == *input_path && node.object_path == *object_path)
+            .copied()
+            .find(|&i| self.nodes[i].object_path == *object_path)
     }
 
     pub fn get_node_index_with_output(&self, output_path: &String) -> Option<usize> {
-        self.nodes
-            .iter()
-            .position(|node| node.output_path == *output_path)
+        self.outputs.get(output_path).copied()
     }
 
     pub fn direct_depends_on(&self, from: usize, to: usize) -> bool {
@@ -137,24 +361,32 @@ impl DependencyGraph {
 
     pub fn build_sub_graph(&self, nodes: &Vec<usize>) -> DependencyGraph {
         let mut sub_dependency_graph = DependencyGraph::new();
+        sub_dependency_graph.entry_symbols = self.entry_symbols.clone();
 
-        for &node_index in nodes {
+        // Index of each node in the subgraph
+        let mut sub_node_index = vec![None; self.nodes.len()];
+        for (i, &node_index) in nodes.iter().enumerate() {
+            sub_node_index[node_index].get_or_insert(i);
             sub_dependency_graph.add_node(self.nodes[node_index].clone());
         }
 
+        // The edges of a node are distinct, and so are their images
         for (i, &node_index) in nodes.iter().enumerate() {
-            for &next_node_index in &self.edges[node_index] {
-                if nodes.contains(&next_node_index) {
-                    sub_dependency_graph
-                        .add_edge(i, nodes.iter().position(|&x| x == next_node_index).unwrap());
-                }
-            }
+            sub_dependency_graph.edges[i] = self.edges[node_index]
+                .iter()
+                .filter_map(|&next_node_index| sub_node_index[next_node_index])
+                .collect();
         }
 
         sub_dependency_graph
     }
 
     pub fn extract_sub_dependency(&self, nodes: Vec<usize>) -> DependencyGraph {
+        self.build_sub_graph(&self.reachable_nodes(nodes))
+    }
+
+    /// `nodes` and all nodes they transitively depend on.
+    pub fn reachable_nodes(&self, nodes: Vec<usize>) -> Vec<usize> {
         let mut all_nodes = vec![];
 
         let mut visited = vec![false; self.nodes.len()];
@@ -174,7 +406,7 @@ impl DependencyGraph {
             }
         }
 
-        self.build_sub_graph(&all_nodes)
+        all_nodes
     }
 }
 
@@ -187,7 +419,7 @@ pub fn read_dependencies(dependency_file: &Path) -> Result<Vec<DependencyInfo>,
 
 pub fn build_dependency(
     dependency_infos: Vec<DependencyInfo>,
-    fuzz_depends_level: usize,
+    strategy: MatchStrategy,
 ) -> DependencyGraph {
     let mut dependency_graph = DependencyGraph::new();
 
@@ -195,7 +427,307 @@ pub fn build_dependency(
         dependency_graph.add_node(dependency);
     });
 
-    dependency_graph.build_dependency_edges(fuzz_depends_level);
+    dependency_graph.build_dependency_edges(strategy);
 
     dependency_graph
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn symbol(name: &str, path: &str, kind: SymbolKind) -> DependencySymbol {
+        DependencySymbol {
+            name: name.to_string(),
+            path: path.to_string(),
+            kind,
+        }
+    }
+
+    /// A unit compiled from `input` that defines and uses the functions
+    /// named in `defined` and `undefined`.
+    pub(crate) fn unit(input: &str, defined: &[&str], undefined: &[&str]) -> DependencyInfo {
+        let functions = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| symbol(name, input, SymbolKind::Function))
+                .collect()
+        };
+        let stem = Path::new(input).with_extension("");
+        DependencyInfo {
+            input_path: input.to_string(),
+            output_path: format!("{}.rs", stem.display()),
+            object_path: Some(format!("{}.o", stem.display())),
+            compile_args: vec![],
+            defines: vec![],
+            undefined: functions(undefined),
+            defined: functions(defined),
+            uses: vec![],
+            link_targets: vec![],
+            direct_link_targets: vec![],
+            link_libs: vec![],
+            includes: vec![],
+            weak: vec![],
+            metrics: FileMetrics::default(),
+            project: None,
+        }
+    }
+
+    /// A graph of `units` with the given edges rather than computed ones
+    pub(crate) fn graph(units: Vec<DependencyInfo>, edges: &[(usize, usize)]) -> DependencyGraph {
+        let mut graph = DependencyGraph::new();
+        for unit in units {
+            graph.add_node(unit);
+        }
+        for &(from, to) in edges {
+            graph.add_edge(from, to);
+        }
+        graph
+    }
+
+    /// A graph of `n` units without any symbols
+    pub(crate) fn plain_graph(n: usize, edges: &[(usize, usize)]) -> DependencyGraph {
+        let units = (0..n)
+            .map(|i| unit(&format!("/src/{}.c", i), &[], &[]))
+            .collect();
+        graph(units, edges)
+    }
+
+    #[test]
+    fn edges_from_symbols() {
+        let graph = build_dependency(
+            vec![
+                unit("/src/main.c", &["main"], &["parse", "puts"]),
+                unit("/src/parse.c", &["parse"], &["lex"]),
+                unit("/src/lex.c", &["lex"], &[]),
+            ],
+            MatchStrategy::NameOnly,
+        );
+        assert_eq!(graph.edges, vec![vec![1], vec![2], vec![]]);
+        assert!(graph.depends_on(0, 2));
+        assert!(!graph.depends_on(2, 0));
+        assert_eq!(graph.reachable_nodes(vec![1]), vec![1, 2]);
+    }
+
+    /// The edges of the original pairwise resolution, which compared every
+    /// symbol a unit uses with every symbol of every other unit
+    fn pairwise_edges(graph: &DependencyGraph, strategy: MatchStrategy) -> Vec<Vec<usize>> {
+        let nodes = &graph.nodes;
+        nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let mut edges = vec![];
+                for symbol in &node.undefined {
+                    let mut providers = (0..nodes.len())
+                        .filter(|&j| {
+                            !nodes[j].is_main()
+                                && nodes[j]
+                                    .defined
+                                    .iter()
+                                    .any(|s| s.depends_on(symbol, strategy))
+                        })
+                        .collect::<Vec<_>>();
+                    if providers.is_empty() {
+                        providers = (0..nodes.len())
+                            .filter(|&j| {
+                                i != j
+                                    && !nodes[j].is_main()
+                                    && nodes[j].is_companion_of(&symbol.path)
+                            })
+                            .collect();
+                    }
+                    edges.extend(providers);
+                }
+                edges.sort_unstable();
+                edges.dedup();
+                edges
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edges_match_pairwise_resolution() {
+        use SymbolKind::{Function, Macro, Record};
+
+        let with_symbols = |input: &str,
+                            defined: &[(&str, &str, SymbolKind)],
+                            undefined: &[(&str, &str, SymbolKind)]| {
+            let symbols = |symbols: &[(&str, &str, SymbolKind)]| {
+                symbols
+                    .iter()
+                    .map(|&(name, path, kind)| symbol(name, path, kind))
+                    .collect()
+            };
+            DependencyInfo {
+                defined: symbols(defined),
+                undefined: symbols(undefined),
+                ..unit(input, &[], &[])
+            }
+        };
+        let units = vec![
+            with_symbols(
+                "/src/main.c",
+                &[("main", "/src/main.c", Function)],
+                &[
+                    ("parse", "/src/parse.h", Function),
+                    ("lex", "/src/lex.h", Function),
+                    ("Token", "/src/lex.h", Record),
+                    ("helper", "/src/util/helper.h", Function),
+                    ("puts", "/usr/include/stdio.h", Function),
+                    ("VERSION", "/src/config.h", Macro),
+                ],
+            ),
+            with_symbols(
+                "/src/parse.c",
+                &[
+                    ("parse", "/src/parse.c", Function),
+                    ("Token", "/src/lex.h", Record),
+                ],
+                &[
+                    ("lex", "/src/lex.h", Function),
+                    ("Token", "/src/lex.h", Record),
+                ],
+            ),
+            with_symbols(
+                "/src/lex.c",
+                &[
+                    ("lex", "/src/lex.h", Function),
+                    ("Token", "/src/lex.h", Record),
+                ],
+                &[],
+            ),
+            with_symbols(
+                "/src/util/helper.c",
+                &[("helper", "/src/util/helper.c", Function)],
+                &[("lex", "/src/lex.c", Function)],
+            ),
+            with_symbols(
+                "/src/other/helper.c",
+                &[("helper", "/src/other/helper.c", Function)],
+                &[],
+            ),
+            with_symbols(
+                "/src/tool.c",
+                &[
+                    ("main", "/src/tool.c", Function),
+                    ("parse", "/src/parse.h", Function),
+                ],
+                &[("helper", "/src/other/helper.h", Function)],
+            ),
+            with_symbols("/src/config.c", &[], &[]),
+        ];
+
+        for strategy in [
+            MatchStrategy::Exact,
+            MatchStrategy::SameHeaderStem,
+            MatchStrategy::SameDirectory,
+            MatchStrategy::NameOnly,
+        ] {
+            let graph = build_dependency(units.clone(), strategy);
+            let mut edges = graph.edges.clone();
+            for edges in &mut edges {
+                // Edges are unique, in the order their first symbol resolved
+                let len = edges.len();
+                edges.sort_unstable();
+                edges.dedup();
+                assert_eq!(edges.len(), len);
+            }
+            assert_eq!(edges, pairwise_edges(&graph, strategy), "{}", strategy);
+        }
+    }
+
+    #[test]
+    fn node_lookup() {
+        let mut units = vec![
+            unit("/src/a.c", &[], &[]),
+            unit("/src/a.c", &[], &[]),
+            unit("/src/b.c", &[], &[]),
+        ];
+        units[1].object_path = Some("/src/a-2.o".to_string());
+        let graph = graph(units, &[]);
+        let input = |input: &str, object: Option<&str>| {
+            graph.get_node_index_with_input(&input.to_string(), &object.map(String::from))
+        };
+        assert_eq!(input("/src/a.c", Some("/src/a.o")), Some(0));
+        assert_eq!(input("/src/a.c", Some("/src/a-2.o")), Some(1));
+        assert_eq!(input("/src/a.c", None), None);
+        assert_eq!(input("/src/c.c", Some("/src/c.o")), None);
+        let output = |output: &str| graph.get_node_index_with_output(&output.to_string());
+        // Nodes sharing an output path resolve to the first one
+        assert_eq!(output("/src/a.rs"), Some(0));
+        assert_eq!(output("/src/b.rs"), Some(2));
+        assert_eq!(output("/src/c.rs"), None);
+    }
+
+    #[test]
+    fn shared_symbols_add_one_edge() {
+        let mut graph = graph(
+            vec![
+                unit("/src/main.c", &["main"], &["parse", "parse_all"]),
+                unit("/src/parse.c", &["parse", "parse_all"], &[]),
+            ],
+            &[(0, 1)],
+        );
+        graph.build_dependency_edges(MatchStrategy::NameOnly);
+        assert_eq!(graph.edges, vec![vec![1], vec![]]);
+    }
+
+    #[test]
+    fn sub_graph() {
+        let graph = plain_graph(4, &[(0, 1), (1, 2), (2, 3), (3, 1)]);
+        let sub_graph = graph.extract_sub_dependency(vec![1]);
+        let inputs = sub_graph
+            .nodes
+            .iter()
+            .map(|node| node.input_path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec!["/src/1.c", "/src/2.c", "/src/3.c"]);
+        assert_eq!(sub_graph.edges, vec![vec![1], vec![2], vec![0]]);
+    }
+
+    #[test]
+    fn link_distance() {
+        let targets = |targets: &[&str]| targets.iter().map(|t| t.to_string()).collect();
+        let linked = |input: &str, link_targets: &[&str], direct: &[&str]| DependencyInfo {
+            link_targets: targets(link_targets),
+            direct_link_targets: targets(direct),
+            ..unit(input, &[], &[])
+        };
+        // `shared.c` is linked into both programs, `lib.c` through a library
+        let shared = linked(
+            "/src/shared.c",
+            &["/b/one", "/b/two"],
+            &["/b/one", "/b/two"],
+        );
+        let two = linked("/src/two.c", &["/b/two"], &["/b/two"]);
+        let lib = linked("/src/lib.c", &["/b/lib.a", "/b/two"], &["/b/lib.a"]);
+        let other = linked("/src/other.c", &["/b/other"], &["/b/other"]);
+        assert_eq!(shared.link_distance(&two), 0);
+        assert_eq!(two.link_distance(&shared), 0);
+        assert_eq!(shared.link_distance(&lib), 1);
+        assert_eq!(shared.link_distance(&other), 2);
+        assert_eq!(shared.link_distance(&unit("/src/none.c", &[], &[])), 0);
+
+        // Without direct targets, the first target is the direct one
+        let old = linked("/src/old.c", &["/b/lib.a", "/b/two"], &[]);
+        assert_eq!(old.direct_link_targets(), ["/b/lib.a"]);
+        assert_eq!(old.link_distance(&lib), 0);
+        assert_eq!(old.link_distance(&two), 1);
+    }
+
+    #[test]
+    fn duplicate_definitions() {
+        let mut units = vec![
+            unit("/src/a.c", &["main", "helper"], &[]),
+            unit("/src/b.c", &["main", "helper"], &[]),
+        ];
+        units[0].link_targets = vec!["/build/a".to_string()];
+        units[1].link_targets = vec!["/build/b".to_string()];
+        let duplicates = graph(units, &[]).duplicate_definitions();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].name, "helper");
+        assert_eq!(duplicates[0].nodes, vec![0, 1]);
+        assert!(!duplicates[0].conflicting);
+    }
+}
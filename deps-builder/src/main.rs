@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dot_generator::*;
 use dot_structures::*;
 use graphviz_rust::printer::{DotPrinter, PrinterContext};
@@ -7,7 +7,19 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process;
 
-use deps_builder::{build_dependency, read_dependencies};
+use deps_builder::call_graph::CallGraph;
+use deps_builder::objects::{
+    cross_check, object_dependencies, read_object_files, MissingFrom, ObjectFile,
+};
+use deps_builder::output::ExportedGraph;
+use deps_builder::projects::merge_dependencies;
+use deps_builder::{
+    build_dependency, read_dependencies, DependencyGraph, DependencyInfo, EntrySymbols,
+    MatchStrategy,
+};
+
+/// Renders the dependency graph in one of the output formats
+type GraphFormat = fn(&ExportedGraph) -> String;
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -17,46 +29,223 @@ about = "Build C dependencies for C2Rust",
 long_about = None,
 trailing_var_arg = true)]
 struct Args {
-    /// Use fuzzing dependency checking
-    #[clap(long, default_value_t = 0)]
-    fuzz_depends_level: usize,
-    /// Path to a file to with the dependency information
-    #[clap(long, default_value = "./dependencies.json")]
-    dependency_file: PathBuf,
+    /// How to match the symbols a file uses to their definitions: exact,
+    /// same-header-stem, same-directory, include-graph, name-only or
+    /// link-scope. The levels 0 to 3 of --fuzz-depends-level are accepted as
+    /// well, with 3 meaning name-only.
+    #[clap(long, alias = "fuzz-depends-level", default_value = "exact")]
+    match_strategy: MatchStrategy,
+    /// Path to a file to with the dependency information. Given more than
+    /// once, the files of several projects are merged into one graph, with
+    /// every project named as in PROJECT=PATH or after the directory of its
+    /// file.
+    #[clap(
+        long,
+        default_value = "./dependencies.json",
+        multiple = true,
+        number_of_values = 1
+    )]
+    dependency_file: Vec<String>,
     /// Path to a file to write the dependency graph to
     #[clap(long, default_value = "./dependencies.dot")]
     dependency_dot: PathBuf,
+    /// Path to a file to write the dependency graph and the symbols behind
+    /// each edge to as JSON
+    #[clap(long)]
+    dependency_json: Option<PathBuf>,
+    /// Path to a file to write the dependency graph to as GraphML
+    #[clap(long)]
+    dependency_graphml: Option<PathBuf>,
+    /// Path to a file to write the dependency graph to as a Mermaid flowchart
+    #[clap(long)]
+    dependency_mermaid: Option<PathBuf>,
+    /// Path to a file to write an interactive HTML view of the dependency
+    /// graph to
+    #[clap(long)]
+    dependency_html: Option<PathBuf>,
     /// Emit Rust dependencies for the given binaries
     #[clap(long, multiple = true, number_of_values = 1)]
     bin: Vec<String>,
-    /// Emit Rust dependencies for all binaries (with main defined)
+    /// Emit Rust dependencies for all binaries (with an entry function
+    /// defined)
     #[clap(long)]
     bins: bool,
+    /// Treat the functions matching the pattern as entry functions besides
+    /// `main`, like `*_main` for the applets of a multi-call binary
+    #[clap(long, multiple = true, number_of_values = 1)]
+    entry_symbol: Vec<String>,
+    /// Path to a file to write the function-level call graph to
+    #[clap(long)]
+    call_graph_dot: Option<PathBuf>,
+    /// List the functions and global variables no entry function uses
+    #[clap(long)]
+    unreachable: bool,
+    /// List the groups of files that depend on each other in a cycle
+    #[clap(long)]
+    cycles: bool,
+    /// List the files in an order in which they can be translated bottom-up,
+    /// grouping files that depend on each other in a cycle
+    #[clap(long)]
+    translation_order: bool,
+    /// List the functions and variables defined in more than one file
+    #[clap(long)]
+    duplicates: bool,
+    /// Explain the symbols that resolve to more than one file
+    #[clap(long)]
+    ambiguous: bool,
+    /// List the libraries each binary needs besides libc, and the symbols
+    /// nothing provides
+    #[clap(long)]
+    unresolved: bool,
+    /// List the files shared between the binaries, which can go into a
+    /// common library crate, and the ones each binary needs for itself
+    #[clap(long)]
+    partition: bool,
+    /// List the projects of a merged graph in an order in which they can be
+    /// translated into the crates of a workspace, and the projects each of
+    /// them depends on
+    #[clap(long)]
+    workspace: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// Questions about single files, and the dependency information of object
+/// files. Files are given by their input or output path, their file name, or
+/// the name of the binary built from them.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the shortest chain of dependencies from one file to another and
+    /// the symbols behind each step
+    Why { from: String, to: String },
+    /// List the files and binaries that depend on a file
+    Rdeps { file: String },
+    /// List the binaries to verify again when the translation of a file
+    /// changes
+    Impact { file: String },
+    /// Write the dependency information of ELF object files and archives to
+    /// the dependency file instead of reading it
+    Objects {
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Compare the functions and variables in the dependency file to the
+    /// symbol tables of the object files. Without files, the object file
+    /// recorded for each unit is read.
+    CrossCheck { files: Vec<PathBuf> },
+    /// Compare the dependency files of two runs: the files, edges and
+    /// unresolved symbols that were added or removed, the edges that stand
+    /// for other symbols, and the binaries that now need other files
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Print the differences as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Recommend an order for porting the files, with an estimated
+    /// difficulty for each. With --bin or --bins, only the files those
+    /// binaries need are planned.
+    Plan,
 }
 
 fn main() {
     let args = Args::parse();
-    let fuzz_depends_level = args.fuzz_depends_level;
+    let match_strategy = args.match_strategy;
     let dependency_file = args.dependency_file;
     let dependency_dot = args.dependency_dot;
     let emit_binaries = args.bin;
     let emit_all_binaries = args.bins;
+    let entry_symbols = EntrySymbols::new(args.entry_symbol.clone());
 
-    // Read dependencies from the dependency file
-    let dependency_infos = read_dependencies(&dependency_file).unwrap_or_else(|e| {
-        eprintln!(
-            "Error reading dependencies from {}: {}",
-            dependency_file.display(),
-            e
+    if let Some(Command::Objects { files }) = &args.command {
+        let dependency_file = match &dependency_file[..] {
+            [dependency_file] => PathBuf::from(dependency_file),
+            _ => {
+                eprintln!("The object files can be written to only one dependency file");
+                process::exit(1);
+            }
+        };
+        let dependency_infos = object_dependencies(&read_objects(files));
+        write_file(
+            &dependency_file,
+            &serde_json::to_string(&dependency_infos).unwrap(),
         );
-        process::exit(1);
-    });
+        return;
+    }
+
+    if let Some(Command::Diff { old, new, json }) = &args.command {
+        let read_graph = |path: &Path| {
+            let mut graph = build_dependency(read_dependency_file(path), match_strategy);
+            graph.entry_symbols = entry_symbols.clone();
+            graph
+        };
+        let diff = read_graph(old).diff(&read_graph(new), match_strategy);
+        if *json {
+            println!("{}", diff.to_json());
+        } else {
+            print!("{}", diff.to_text());
+        }
+        return;
+    }
+
+    // Read dependencies from the dependency files, merging the projects
+    let dependency_infos = match &dependency_file[..] {
+        [dependency_file] if !dependency_file.contains('=') => {
+            read_dependency_file(Path::new(dependency_file))
+        }
+        dependency_files => {
+            let (dependency_infos, shared) = merge_dependencies(
+                dependency_files
+                    .iter()
+                    .map(|arg| {
+                        let (project, path) = project_file(arg);
+                        (project, read_dependency_file(&path))
+                    })
+                    .collect(),
+            );
+            for unit in shared {
+                eprintln!(
+                    "Warning: {} is compiled by {} and {}, keeping the unit of {}",
+                    unit.input_path,
+                    unit.owner,
+                    unit.projects.join(", "),
+                    unit.owner
+                );
+            }
+            dependency_infos
+        }
+    };
+
+    if let Some(Command::CrossCheck { files }) = &args.command {
+        cross_check_objects(&dependency_infos, files);
+        return;
+    }
+
+    if args.call_graph_dot.is_some() || args.unreachable {
+        let call_graph = CallGraph::new(&dependency_infos);
+        let reachable = call_graph.reachable_from_entries(&entry_symbols);
+
+        if let Some(call_graph_dot) = &args.call_graph_dot {
+            write_call_graph(&call_graph, &reachable, call_graph_dot);
+        }
+        if args.unreachable {
+            for (node, _) in call_graph
+                .nodes
+                .iter()
+                .zip(&reachable)
+                .filter(|(_, &reachable)| !reachable)
+            {
+                println!("{}: {}", node.symbol.path, node.symbol.name);
+            }
+        }
+    }
 
     let mut bin_nodes = Vec::new();
 
     if emit_all_binaries {
         for (i, node) in dependency_infos.iter().enumerate() {
-            if node.defined.iter().any(|s| s.name == "main") {
+            if node.is_entry(&entry_symbols) {
                 bin_nodes.push(i);
             }
         }
@@ -69,7 +258,7 @@ fn main() {
                     .to_str()
                     .unwrap()
                     == bin
-                    && node.defined.iter().any(|s| s.name == "main")
+                    && node.is_entry(&entry_symbols)
                 {
                     bin_nodes.push(i);
                 }
@@ -77,14 +266,183 @@ fn main() {
         }
     }
 
+    let mut dependency_graph = build_dependency(dependency_infos, match_strategy);
+    dependency_graph.entry_symbols = entry_symbols;
+
+    if let Some(command) = args
+        .command
+        .as_ref()
+        .filter(|command| !matches!(command, Command::Plan))
+    {
+        run_query(&dependency_graph, command, match_strategy);
+        return;
+    }
+
+    if args.ambiguous {
+        let file_name = |i: usize| dependency_graph.nodes[i].input_path.as_str();
+        for ambiguity in dependency_graph.ambiguous_symbols(match_strategy) {
+            let symbol = &ambiguity.symbol;
+            let providers = ambiguity
+                .providers
+                .iter()
+                .map(|&i| file_name(i))
+                .collect::<Vec<_>>();
+            println!(
+                "{}: {} {:?} declared in {} resolves to {}: {}",
+                file_name(ambiguity.node),
+                symbol.name,
+                symbol.kind,
+                symbol.path,
+                providers.join(" "),
+                ambiguity.reason
+            );
+            if ambiguity.header_fallback {
+                println!(
+                    "  none of them includes {}, so it was matched by name",
+                    symbol.path
+                );
+            }
+        }
+    }
+
+    if args.partition {
+        let binaries = if bin_nodes.is_empty() {
+            dependency_graph.entries()
+        } else {
+            bin_nodes.clone()
+        };
+        let file_names = |nodes: &[usize]| {
+            nodes
+                .iter()
+                .map(|&i| dependency_graph.nodes[i].input_path.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let partition = match dependency_graph.partition_crates(&binaries) {
+            Ok(partition) => partition,
+            Err(e) => {
+                eprintln!("Cannot partition the binaries: {}", e);
+                process::exit(1);
+            }
+        };
+        println!("common: {}", file_names(&partition.common));
+        for (binary, files) in &partition.binaries {
+            let name = Path::new(&dependency_graph.nodes[*binary].output_path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap();
+            println!("{}: {}", name, file_names(files));
+        }
+    }
+
+    if args.workspace {
+        for (i, member) in dependency_graph.workspace_plan().iter().enumerate() {
+            println!(
+                "{}: {} ({} files){}{}",
+                i + 1,
+                member.project,
+                member.files.len(),
+                if member.dependencies.is_empty() {
+                    ""
+                } else {
+                    " depends on "
+                },
+                member.dependencies.join(" ")
+            );
+        }
+    }
+
     let dependency_graph = if bin_nodes.is_empty() {
-        build_dependency(dependency_infos, fuzz_depends_level)
+        dependency_graph
     } else {
-        build_dependency(dependency_infos, fuzz_depends_level).extract_sub_dependency(bin_nodes)
+        dependency_graph.extract_sub_dependency(bin_nodes)
     };
 
     // println!("Dependency Graph: {:#?}", dependency_graph);
 
+    if let Some(Command::Plan) = &args.command {
+        print_plan(&dependency_graph);
+        return;
+    }
+
+    let condensation = dependency_graph.condense();
+    let in_cycle = |i: usize| {
+        condensation.components[condensation.component_of[i]].len() > 1
+            || dependency_graph.direct_depends_on(i, i)
+    };
+    let file_names = |group: &[usize]| {
+        group
+            .iter()
+            .map(|&i| dependency_graph.nodes[i].input_path.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    if args.cycles {
+        for (i, cycle) in dependency_graph.cycles().iter().enumerate() {
+            println!("cycle {}: {}", i + 1, file_names(cycle));
+        }
+    }
+
+    if args.duplicates {
+        for duplicate in dependency_graph.duplicate_definitions() {
+            println!(
+                "{} {:?} defined in {}{}",
+                duplicate.name,
+                duplicate.kind,
+                file_names(&duplicate.nodes),
+                if duplicate.conflicting {
+                    " (conflicting)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    if args.unresolved {
+        for (i, dependencies) in dependency_graph.binary_dependencies() {
+            let binary = Path::new(&dependency_graph.nodes[i].output_path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap();
+            println!("{}: {}", binary, dependencies.libraries.join(" "));
+            for symbol in &dependencies.missing {
+                println!(
+                    "  missing {} {:?} ({})",
+                    symbol.name, symbol.kind, symbol.path
+                );
+            }
+        }
+    }
+
+    if args.translation_order {
+        for (i, group) in condensation.components.iter().enumerate() {
+            println!("{}: {}", i + 1, file_names(group));
+        }
+    }
+
+    if args.dependency_json.is_some()
+        || args.dependency_graphml.is_some()
+        || args.dependency_mermaid.is_some()
+        || args.dependency_html.is_some()
+    {
+        let exported_graph = dependency_graph.export(match_strategy);
+        let outputs: [(_, GraphFormat); 4] = [
+            (&args.dependency_json, ExportedGraph::to_json),
+            (&args.dependency_graphml, ExportedGraph::to_graphml),
+            (&args.dependency_mermaid, ExportedGraph::to_mermaid),
+            (&args.dependency_html, ExportedGraph::to_html),
+        ];
+        for (path, format) in outputs {
+            if let Some(path) = path {
+                write_file(path, &format(&exported_graph));
+            }
+        }
+    }
+
     // Write the dependency graph to a dot file
     let mut dependency_dot_graph = Graph::DiGraph {
         id: Id::Plain(String::from("dependency_graph")),
@@ -92,21 +450,32 @@ fn main() {
         stmts: vec![],
     };
 
+    // Files programs start in are outlined in red, files and edges in a cycle are orange
     for (i, node) in dependency_graph.nodes.iter().enumerate() {
-        if let Some(_) = node.defined.iter().find(|s| s.name == "main") {
-            dependency_dot_graph.add_stmt(Stmt::Node(
-                node!(i;attr!("color", "red"), attr!("label", (format!("\"{}\"", (Path::new(&node.output_path).file_name().unwrap().to_str().unwrap()))))),
-            ));
-        } else {
-            dependency_dot_graph.add_stmt(Stmt::Node(
-                node!(i;attr!("label", (format!("\"{}\"", (Path::new(&node.output_path).file_name().unwrap().to_str().unwrap()))))),
-            ));
+        let label = format!("\"{}\"", node.label());
+        let mut attributes = vec![attr!("label", (label))];
+        if dependency_graph.is_entry(i) {
+            attributes.push(attr!("color", "red"));
+        }
+        if in_cycle(i) {
+            attributes.push(attr!("style", "filled"));
+            attributes.push(attr!("fillcolor", "orange"));
         }
+        dependency_dot_graph.add_stmt(Stmt::Node(Node::new(node_id!(i), attributes)));
     }
 
     for (i, edges) in dependency_graph.edges.iter().enumerate() {
-        for j in edges {
-            dependency_dot_graph.add_stmt(Stmt::Edge(edge!(node_id!(i) => node_id!(j))));
+        for &j in edges {
+            let attributes =
+                if condensation.component_of[i] == condensation.component_of[j] && in_cycle(i) {
+                    vec![attr!("color", "orange"), attr!("penwidth", "2")]
+                } else {
+                    vec![]
+                };
+            dependency_dot_graph.add_stmt(Stmt::Edge(Edge {
+                ty: EdgeTy::Pair(Vertex::N(node_id!(i)), Vertex::N(node_id!(j))),
+                attributes,
+            }));
         }
     }
 
@@ -137,3 +506,263 @@ fn main() {
         ),
     };
 }
+
+fn run_query(dependency_graph: &DependencyGraph, query: &Command, match_strategy: MatchStrategy) {
+    let file_name = |i: usize| dependency_graph.nodes[i].input_path.as_str();
+    let describe = |i: usize| {
+        if dependency_graph.is_entry(i) {
+            format!("{} (binary)", file_name(i))
+        } else {
+            file_name(i).to_string()
+        }
+    };
+
+    match query {
+        Command::Why { from, to } => {
+            let (from, to) = (
+                find_node(dependency_graph, from),
+                find_node(dependency_graph, to),
+            );
+            let path = match dependency_graph.shortest_path(from, to) {
+                Some(path) => path,
+                None => {
+                    println!("{} does not depend on {}", file_name(from), file_name(to));
+                    return;
+                }
+            };
+            let edge_symbols = dependency_graph.edge_symbols(match_strategy);
+            println!("{}", file_name(from));
+            for step in path.windows(2) {
+                let (i, j) = (step[0], step[1]);
+                let edge = dependency_graph.edges[i]
+                    .iter()
+                    .position(|&k| k == j)
+                    .unwrap();
+                let symbols = edge_symbols[i][edge]
+                    .iter()
+                    .map(|symbol| symbol.name.as_str())
+                    .collect::<Vec<_>>();
+                println!("  -> {}: {}", file_name(j), symbols.join(", "));
+            }
+        }
+        Command::Rdeps { file } => {
+            let node = find_node(dependency_graph, file);
+            for i in dependency_graph.reverse_dependencies(node) {
+                println!("{}", describe(i));
+            }
+        }
+        Command::Impact { file } => {
+            let node = find_node(dependency_graph, file);
+            for i in dependency_graph.impacted_binaries(node) {
+                println!("{}", file_name(i));
+            }
+        }
+        Command::Objects { .. }
+        | Command::CrossCheck { .. }
+        | Command::Diff { .. }
+        | Command::Plan => unreachable!(),
+    }
+}
+
+/// Print the steps of the migration plan for `dependency_graph`, with the
+/// metrics behind the difficulty of each file.
+fn print_plan(dependency_graph: &DependencyGraph) {
+    let plan = dependency_graph.migration_plan();
+    let total: usize = plan.iter().map(|step| step.difficulty).sum();
+    println!(
+        "{} files, total difficulty {}",
+        dependency_graph.nodes.len(),
+        total
+    );
+    for (i, step) in plan.iter().enumerate() {
+        println!(
+            "{}: difficulty {}, leverage {}",
+            i + 1,
+            step.difficulty,
+            step.leverage
+        );
+        for file in &step.files {
+            let node = &dependency_graph.nodes[file.node];
+            let metrics = &node.metrics;
+            let mut details = vec![
+                format!("{} functions", metrics.functions),
+                format!("{} lines", metrics.lines),
+            ];
+            for (count, construct) in [
+                (metrics.gotos, "goto"),
+                (metrics.asm, "asm"),
+                (metrics.variadic_functions, "variadic"),
+                (metrics.unions, "union"),
+                (metrics.bitfields, "bit-field"),
+            ] {
+                if count > 0 {
+                    details.push(format!("{} {}", count, construct));
+                }
+            }
+            details.push(format!("in {}", file.in_degree));
+            details.push(format!("out {}", file.out_degree));
+            println!(
+                "  {}: {} ({})",
+                node.input_path,
+                file.difficulty,
+                details.join(", ")
+            );
+        }
+    }
+}
+
+/// Read the dependency information in `path`, exiting if it can't be read.
+fn read_dependency_file(path: &Path) -> Vec<DependencyInfo> {
+    read_dependencies(path).unwrap_or_else(|e| {
+        eprintln!("Error reading dependencies from {}: {}", path.display(), e);
+        process::exit(1);
+    })
+}
+
+/// The project and path of a `--dependency-file`, which is named as in
+/// `PROJECT=PATH` or after the directory the file is in.
+fn project_file(arg: &str) -> (String, PathBuf) {
+    if let Some((project, path)) = arg.split_once('=') {
+        return (project.to_string(), PathBuf::from(path));
+    }
+    let path = PathBuf::from(arg);
+    let project = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.clone())
+        .parent()
+        .and_then(Path::file_name)
+        .or_else(|| path.file_stem())
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    (project, path)
+}
+
+/// Read the object files of `paths`, exiting if one of them can't be read.
+fn read_objects(paths: &[PathBuf]) -> Vec<ObjectFile> {
+    let mut objects = vec![];
+    for path in paths {
+        match read_object_files(path) {
+            Ok(files) => objects.extend(files),
+            Err(e) => {
+                eprintln!("Error reading object file {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    objects
+}
+
+/// Print the symbols on which the units of `dependency_infos` and the object
+/// files disagree. Without `paths`, the object files recorded for the units
+/// are read, skipping the ones that don't exist.
+fn cross_check_objects(dependency_infos: &[DependencyInfo], paths: &[PathBuf]) {
+    let objects = if paths.is_empty() {
+        let paths = dependency_infos
+            .iter()
+            .filter_map(|info| info.object_path.as_ref())
+            .map(PathBuf::from)
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+        read_objects(&paths)
+    } else {
+        read_objects(paths)
+    };
+
+    for difference in cross_check(dependency_infos, &object_dependencies(&objects)) {
+        let info = &dependency_infos[difference.unit];
+        let usage = if difference.defined {
+            "defined"
+        } else {
+            "used"
+        };
+        let missing_from = match difference.missing_from {
+            MissingFrom::Ast => "only in the object file",
+            MissingFrom::Object => "only in the AST",
+        };
+        println!(
+            "{}: {} {} {}",
+            info.input_path, difference.name, usage, missing_from
+        );
+    }
+}
+
+/// The node `name` refers to, exiting if there is no such node or more than
+/// one.
+fn find_node(dependency_graph: &DependencyGraph, name: &str) -> usize {
+    match dependency_graph.find_nodes(name)[..] {
+        [node] => node,
+        [] => {
+            eprintln!("No file named {} in the dependency graph", name);
+            process::exit(1);
+        }
+        ref nodes => {
+            eprintln!("{} names more than one file:", name);
+            for &i in nodes {
+                eprintln!("  {}", dependency_graph.nodes[i].input_path);
+            }
+            process::exit(1);
+        }
+    }
+}
+
+/// Write `call_graph` to `path` in DOT format, graying out the nodes that are
+/// not reachable from any entry function.
+fn write_call_graph(call_graph: &CallGraph, reachable: &[bool], path: &Path) {
+    let mut call_graph_dot = Graph::DiGraph {
+        id: Id::Plain(String::from("call_graph")),
+        strict: true,
+        stmts: vec![],
+    };
+
+    for (i, node) in call_graph.nodes.iter().enumerate() {
+        let file_name = Path::new(&node.symbol.path)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let label = format!("\"{}\\n{}\"", node.symbol.name, file_name);
+        let color = if reachable[i] { "black" } else { "gray" };
+        call_graph_dot.add_stmt(Stmt::Node(
+            node!(i;attr!("color", color), attr!("fontcolor", color), attr!("label", (label))),
+        ));
+    }
+
+    for (i, edges) in call_graph.edges.iter().enumerate() {
+        for j in edges {
+            call_graph_dot.add_stmt(Stmt::Edge(edge!(node_id!(i) => node_id!(j))));
+        }
+    }
+
+    let mut dot_file = File::create(path).unwrap_or_else(|e| {
+        eprintln!(
+            "Error creating call graph dot file {}: {}",
+            path.display(),
+            e
+        );
+        process::exit(1);
+    });
+
+    if let Err(e) = dot_file.write_all(
+        call_graph_dot
+            .print(&mut PrinterContext::default())
+            .as_bytes(),
+    ) {
+        panic!(
+            "Unable to write call graph to file {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+fn write_file(path: &Path, contents: &str) {
+    let mut file = File::create(path).unwrap_or_else(|e| {
+        eprintln!("Error creating file {}: {}", path.display(), e);
+        process::exit(1);
+    });
+
+    if let Err(e) = file.write_all(contents.as_bytes()) {
+        panic!("Unable to write to file {}: {}", path.display(), e);
+    }
+}
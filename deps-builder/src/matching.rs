@@ -0,0 +1,555 @@
+//! Matching the functions, variables and types a unit uses to the units that
+//! provide them.
+//!
+//! A declaration and its definition are recorded with the files they appear
+//! in, which rarely agree: `foo` is declared in `foo.h` and defined in
+//! `foo.c`. A `MatchStrategy` decides how much of the path has to agree, or
+//! whether the include graph of the defining unit is consulted instead.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DependencyGraph, DependencySymbol, SymbolKind};
+
+/// How an undefined symbol is matched to the definitions of other units. All
+/// strategies require the name and kind of the symbols to agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchStrategy {
+    /// The definition is in the file the symbol is declared in
+    #[default]
+    Exact,
+    /// The definition is in a file in the same directory with the same stem,
+    /// e.g. `foo` declared in `src/foo.h` and defined in `src/foo.c`
+    SameHeaderStem,
+    /// The definition is in a file in the same directory
+    SameDirectory,
+    /// The defining unit includes the header the symbol is declared in, or is
+    /// compiled from its companion source file. Falls back to `NameOnly` if
+    /// no definition qualifies.
+    IncludeGraph,
+    /// Any definition with the same name
+    NameOnly,
+    /// Any definition with the same name that is linked into a target the
+    /// using unit is linked into. Of several, the ones closest to the using
+    /// unit in the link commands win, like with the linker.
+    LinkScope,
+}
+
+impl MatchStrategy {
+    pub const ALL: [MatchStrategy; 6] = [
+        MatchStrategy::Exact,
+        MatchStrategy::SameHeaderStem,
+        MatchStrategy::SameDirectory,
+        MatchStrategy::IncludeGraph,
+        MatchStrategy::NameOnly,
+        MatchStrategy::LinkScope,
+    ];
+
+    /// The strategy a level of the former `fuzz_depends_level` option
+    /// selects. Levels above 3 matched every use to every definition,
+    /// whatever its name, which makes every file depend on every other one;
+    /// there is no strategy for them.
+    pub fn from_level(level: usize) -> Result<Self, String> {
+        match level {
+            0 => Ok(MatchStrategy::Exact),
+            1 => Ok(MatchStrategy::SameHeaderStem),
+            2 => Ok(MatchStrategy::SameDirectory),
+            3 => Ok(MatchStrategy::NameOnly),
+            _ => Err(format!(
+                "fuzz depends level {} is not supported anymore: levels above 3 \
+                 matched every use to every definition; use `{}` instead",
+                level,
+                MatchStrategy::NameOnly
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchStrategy::Exact => "exact",
+            MatchStrategy::SameHeaderStem => "same-header-stem",
+            MatchStrategy::SameDirectory => "same-directory",
+            MatchStrategy::IncludeGraph => "include-graph",
+            MatchStrategy::NameOnly => "name-only",
+            MatchStrategy::LinkScope => "link-scope",
+        }
+    }
+}
+
+impl fmt::Display for MatchStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for MatchStrategy {
+    type Err = String;
+
+    /// Parse a strategy name. The levels of the former `fuzz_depends_level`
+    /// option are accepted as well.
+    fn from_str(s: &str) -> Result<Self, String> {
+        if let Some(strategy) = MatchStrategy::ALL.iter().find(|st| st.name() == s) {
+            return Ok(*strategy);
+        }
+        if let Ok(level) = s.parse() {
+            return MatchStrategy::from_level(level);
+        }
+        let names = MatchStrategy::ALL.iter().map(|st| st.name());
+        Err(format!(
+            "unknown match strategy `{}`, expected one of: {}",
+            s,
+            names.collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+/// The parts of a symbol that have to agree for it to match another one.
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) struct SymbolKey<'a> {
+    name: &'a str,
+    kind: SymbolKind,
+    path: Option<&'a str>,
+    parent: Option<&'a Path>,
+    file_stem: Option<&'a OsStr>,
+}
+
+impl DependencySymbol {
+    /// Whether `self` may refer to the definition `other` under `strategy`.
+    /// The strategies that look at the defining unit rather than the symbol
+    /// only compare names and kinds here.
+    pub fn depends_on(&self, other: &Self, strategy: MatchStrategy) -> bool {
+        self.key(strategy) == other.key(strategy)
+    }
+
+    pub(crate) fn key(&self, strategy: MatchStrategy) -> SymbolKey<'_> {
+        let path = Path::new(&self.path);
+        let mut key = SymbolKey {
+            name: &self.name,
+            kind: self.kind,
+            path: None,
+            parent: None,
+            file_stem: None,
+        };
+        match strategy {
+            MatchStrategy::Exact => key.path = Some(&self.path),
+            MatchStrategy::SameHeaderStem => {
+                key.parent = path.parent();
+                key.file_stem = path.file_stem();
+            }
+            MatchStrategy::SameDirectory => key.parent = path.parent(),
+            MatchStrategy::IncludeGraph | MatchStrategy::NameOnly | MatchStrategy::LinkScope => {}
+        }
+        key
+    }
+}
+
+/// Why a symbol resolved to more than one unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbiguityReason {
+    /// Some of the units involved have no link information to tell the
+    /// definitions apart
+    NoLinkInfo,
+    /// The closest definitions are linked into the same target as the using
+    /// unit
+    SameTarget,
+    /// The closest definitions end up in a target the using unit ends up in,
+    /// but none of them is linked with it directly
+    SharedTarget,
+    /// None of the definitions is linked into a target with the using unit
+    NotLinked,
+}
+
+impl fmt::Display for AmbiguityReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AmbiguityReason::NoLinkInfo => "there is no link information to tell them apart",
+            AmbiguityReason::SameTarget => {
+                "the closest of them are linked into the same target as the user"
+            }
+            AmbiguityReason::SharedTarget => {
+                "the closest of them end up in a target with the user, none directly"
+            }
+            AmbiguityReason::NotLinked => "none of them is linked into a target with the user",
+        })
+    }
+}
+
+/// A symbol that resolved to more than one unit.
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    /// The unit using the symbol
+    pub node: usize,
+    pub symbol: DependencySymbol,
+    /// The units the symbol resolved to
+    pub providers: Vec<usize>,
+    /// Whether the include graph strategy fell back to matching by name
+    /// because no defining unit includes the header declaring the symbol
+    pub header_fallback: bool,
+    pub reason: AmbiguityReason,
+}
+
+/// The units providing a symbol, and how they were found.
+pub(crate) struct Resolution {
+    pub(crate) providers: Vec<usize>,
+    header_fallback: bool,
+    /// Link distance of the closest providers to the using unit
+    distance: usize,
+}
+
+/// Index of the units providing each symbol under a match strategy.
+pub(crate) struct Resolver<'a> {
+    graph: &'a DependencyGraph,
+    strategy: MatchStrategy,
+    providers: HashMap<SymbolKey<'a>, Vec<usize>>,
+    /// Units by the directory and stem of their source file
+    sources: HashMap<(Option<&'a Path>, Option<&'a OsStr>), Vec<usize>>,
+    /// Headers each unit includes, directly or through other headers
+    includes: Vec<HashSet<&'a str>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(graph: &'a DependencyGraph, strategy: MatchStrategy) -> Self {
+        let mut providers: HashMap<_, Vec<usize>> = HashMap::new();
+        let mut sources: HashMap<_, Vec<usize>> = HashMap::new();
+        let mut includes = vec![];
+        for (j, node) in graph.nodes.iter().enumerate() {
+            includes.push(node.includes.iter().map(|i| i.header.as_str()).collect());
+            // Programs don't provide symbols to other units
+            if node.is_main() {
+                continue;
+            }
+            for symbol in &node.defined {
+                let units = providers.entry(symbol.key(strategy)).or_default();
+                if units.last() != Some(&j) {
+                    units.push(j);
+                }
+            }
+            let input_path = Path::new(&node.input_path);
+            sources
+                .entry((input_path.parent(), input_path.file_stem()))
+                .or_default()
+                .push(j);
+        }
+
+        Self {
+            graph,
+            strategy,
+            providers,
+            sources,
+            includes,
+        }
+    }
+
+    /// Whether unit `j` implements the header `header`.
+    fn implements(&self, j: usize, header: &str) -> bool {
+        self.includes[j].contains(header) || self.graph.nodes[j].is_companion_of(header)
+    }
+
+    /// The units providing `symbol` to unit `i`. With `LinkScope`, only the
+    /// units defining the symbol closest to `i` in the link commands are
+    /// used, like the linker would.
+    pub(crate) fn resolve(&self, i: usize, symbol: &DependencySymbol) -> Resolution {
+        let node = &self.graph.nodes[i];
+        let distance = |j: usize| node.link_distance(&self.graph.nodes[j]);
+        let mut units = self
+            .providers
+            .get(&symbol.key(self.strategy))
+            .cloned()
+            .unwrap_or_default();
+
+        let mut header_fallback = false;
+        if self.strategy == MatchStrategy::IncludeGraph {
+            let implementing = units
+                .iter()
+                .copied()
+                .filter(|&j| {
+                    self.graph.nodes[j].defined.contains(symbol) || self.implements(j, &symbol.path)
+                })
+                .collect::<Vec<_>>();
+            header_fallback = implementing.is_empty() && !units.is_empty();
+            if !header_fallback {
+                units = implementing;
+            }
+        }
+        if self.strategy == MatchStrategy::LinkScope {
+            units.retain(|&j| distance(j) < 2);
+        }
+
+        let closest = units.iter().map(|&j| distance(j)).min().unwrap_or(0);
+        if self.strategy == MatchStrategy::LinkScope {
+            units.retain(|&j| distance(j) == closest);
+        }
+        Resolution {
+            providers: units,
+            header_fallback,
+            distance: closest,
+        }
+    }
+
+    /// The units unit `i` depends on, each with the symbols it provides to
+    /// `i`. A symbol that no unit defines, like a struct or a `static inline`
+    /// helper from a header, is attributed to the unit compiled from the
+    /// header's companion source file, if there is one.
+    pub(crate) fn dependency_symbols(&self, i: usize) -> Vec<(usize, Vec<&'a DependencySymbol>)> {
+        let mut dependencies: Vec<(usize, Vec<_>)> = vec![];
+        let mut index = HashMap::new();
+        let mut add = |j: usize, symbol| {
+            let k = *index.entry(j).or_insert_with(|| {
+                dependencies.push((j, vec![]));
+                dependencies.len() - 1
+            });
+            dependencies[k].1.push(symbol);
+        };
+
+        for symbol in &self.graph.nodes[i].undefined {
+            let resolution = self.resolve(i, symbol);
+            if !resolution.providers.is_empty() {
+                for j in resolution.providers {
+                    add(j, symbol);
+                }
+                continue;
+            }
+
+            let header = Path::new(&symbol.path);
+            let companions = self
+                .sources
+                .get(&(header.parent(), header.file_stem()))
+                .map_or(&[][..], Vec::as_slice);
+            for &j in companions {
+                if i != j && self.graph.nodes[j].is_companion_of(&symbol.path) {
+                    add(j, symbol);
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// The units unit `i` depends on.
+    pub(crate) fn dependencies(&self, i: usize) -> Vec<usize> {
+        self.dependency_symbols(i)
+            .into_iter()
+            .map(|(j, _)| j)
+            .collect()
+    }
+
+    /// Why `resolution` of a symbol used by unit `i` is ambiguous, if it is.
+    fn ambiguity(
+        &self,
+        i: usize,
+        symbol: &DependencySymbol,
+        resolution: Resolution,
+    ) -> Option<Ambiguity> {
+        if resolution.providers.len() < 2 {
+            return None;
+        }
+        let nodes = &self.graph.nodes;
+        let reason = match resolution.distance {
+            0 if nodes[i].link_targets.is_empty()
+                || resolution
+                    .providers
+                    .iter()
+                    .any(|&j| nodes[j].link_targets.is_empty()) =>
+            {
+                AmbiguityReason::NoLinkInfo
+            }
+            0 => AmbiguityReason::SameTarget,
+            1 => AmbiguityReason::SharedTarget,
+            _ => AmbiguityReason::NotLinked,
+        };
+        Some(Ambiguity {
+            node: i,
+            symbol: symbol.clone(),
+            providers: resolution.providers,
+            header_fallback: resolution.header_fallback,
+            reason,
+        })
+    }
+}
+
+impl DependencyGraph {
+    /// For every edge, the symbols behind it under `strategy`, in the same
+    /// order as `edges`.
+    pub fn edge_symbols(&self, strategy: MatchStrategy) -> Vec<Vec<Vec<DependencySymbol>>> {
+        let resolver = Resolver::new(self, strategy);
+        self.edges
+            .iter()
+            .enumerate()
+            .map(|(i, edges)| {
+                let mut symbols = resolver
+                    .dependency_symbols(i)
+                    .into_iter()
+                    .collect::<HashMap<_, _>>();
+                edges
+                    .iter()
+                    .map(|j| {
+                        symbols
+                            .remove(j)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .cloned()
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The symbols that resolve to more than one unit under `strategy`, once
+    /// per using unit.
+    pub fn ambiguous_symbols(&self, strategy: MatchStrategy) -> Vec<Ambiguity> {
+        let resolver = Resolver::new(self, strategy);
+        let mut ambiguities = vec![];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut seen = HashSet::new();
+            for symbol in &node.undefined {
+                if !seen.insert((symbol.name.as_str(), symbol.kind)) {
+                    continue;
+                }
+                let resolution = resolver.resolve(i, symbol);
+                ambiguities.extend(resolver.ambiguity(i, symbol, resolution));
+            }
+        }
+        ambiguities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{graph, symbol, unit};
+    use crate::{DependencyInfo, Include};
+
+    #[test]
+    fn parse_names() {
+        for strategy in MatchStrategy::ALL {
+            assert_eq!(strategy.name().parse(), Ok(strategy));
+        }
+        assert!("fuzzy".parse::<MatchStrategy>().is_err());
+        assert!("-1".parse::<MatchStrategy>().is_err());
+    }
+
+    #[test]
+    fn parse_levels() {
+        let level = |s: &str| s.parse::<MatchStrategy>().unwrap();
+        assert_eq!(level("0"), MatchStrategy::Exact);
+        assert_eq!(level("1"), MatchStrategy::SameHeaderStem);
+        assert_eq!(level("2"), MatchStrategy::SameDirectory);
+        assert_eq!(level("3"), MatchStrategy::NameOnly);
+        // Levels above 3 matched everything with everything
+        assert!("4".parse::<MatchStrategy>().is_err());
+        assert!("10".parse::<MatchStrategy>().is_err());
+    }
+
+    /// A unit defining `f` in the file `path`
+    fn provider(input: &str, path: &str) -> DependencyInfo {
+        let mut node = unit(input, &[], &[]);
+        node.defined = vec![symbol("f", path, SymbolKind::Function)];
+        node
+    }
+
+    /// `main.c` uses `f`, which is declared in `src/f.h` and defined by
+    /// units that agree with the declaration to a different degree
+    fn candidates() -> DependencyGraph {
+        let mut main = unit("/p/src/main.c", &["main"], &[]);
+        main.undefined = vec![
+            symbol("f", "/p/src/f.h", SymbolKind::Function),
+            symbol("S", "/p/src/f.h", SymbolKind::Record),
+        ];
+        let mut includes_header = provider("/p/other/f.c", "/p/other/f.c");
+        includes_header.includes = vec![Include {
+            file: "/p/other/f.c".to_string(),
+            header: "/p/src/f.h".to_string(),
+        }];
+        graph(
+            vec![
+                main,
+                // Companion source file of the header
+                provider("/p/src/f.c", "/p/src/f.c"),
+                provider("/p/src/g.c", "/p/src/g.c"),
+                includes_header,
+                // Defined in the header itself
+                provider("/p/lib/d.c", "/p/src/f.h"),
+                // Programs don't provide symbols to other units
+                unit("/p/src/f_test.c", &["main", "f"], &[]),
+            ],
+            &[],
+        )
+    }
+
+    fn providers(graph: &DependencyGraph, strategy: MatchStrategy) -> Vec<usize> {
+        let f = &graph.nodes[0].undefined[0];
+        Resolver::new(graph, strategy).resolve(0, f).providers
+    }
+
+    #[test]
+    fn strategies() {
+        let graph = candidates();
+        assert_eq!(providers(&graph, MatchStrategy::Exact), vec![4]);
+        assert_eq!(providers(&graph, MatchStrategy::SameHeaderStem), vec![1, 4]);
+        assert_eq!(
+            providers(&graph, MatchStrategy::SameDirectory),
+            vec![1, 2, 4]
+        );
+        assert_eq!(
+            providers(&graph, MatchStrategy::IncludeGraph),
+            vec![1, 3, 4]
+        );
+        assert_eq!(providers(&graph, MatchStrategy::NameOnly), vec![1, 2, 3, 4]);
+        assert_eq!(
+            providers(&graph, MatchStrategy::LinkScope),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn include_graph_falls_back_to_names() {
+        let graph = graph(
+            vec![
+                candidates().nodes.swap_remove(0),
+                provider("/p/other/f.c", "/p/other/f.c"),
+            ],
+            &[],
+        );
+        let f = &graph.nodes[0].undefined[0];
+        let resolution = Resolver::new(&graph, MatchStrategy::IncludeGraph).resolve(0, f);
+        assert_eq!(resolution.providers, vec![1]);
+        assert!(resolution.header_fallback);
+    }
+
+    #[test]
+    fn link_distance() {
+        let mut graph = candidates();
+        let targets = |targets: &[&str]| targets.iter().map(|t| t.to_string()).collect();
+        graph.nodes[0].link_targets = targets(&["/b/prog"]);
+        graph.nodes[1].link_targets = targets(&["/b/other"]);
+        graph.nodes[2].link_targets = targets(&["/b/libg.a", "/b/prog"]);
+        graph.nodes[3].link_targets = targets(&["/b/other"]);
+        graph.nodes[4].link_targets = targets(&["/b/other"]);
+        // With link scope the closest definition wins, like with the linker
+        assert_eq!(providers(&graph, MatchStrategy::LinkScope), vec![2]);
+        // The other strategies ignore the link commands
+        assert_eq!(providers(&graph, MatchStrategy::NameOnly), vec![1, 2, 3, 4]);
+        assert_eq!(providers(&graph, MatchStrategy::Exact), vec![4]);
+        graph.nodes[2].link_targets = targets(&["/b/libg.a"]);
+        assert!(providers(&graph, MatchStrategy::LinkScope).is_empty());
+    }
+
+    #[test]
+    fn companion_provides_types() {
+        let graph = candidates();
+        let resolver = Resolver::new(&graph, MatchStrategy::Exact);
+        let dependencies = resolver.dependency_symbols(0);
+        let names = dependencies
+            .iter()
+            .map(|(j, symbols)| (*j, symbols.iter().map(|s| s.name.as_str()).collect()))
+            .collect::<Vec<(usize, Vec<_>)>>();
+        assert_eq!(names, vec![(4, vec!["f"]), (1, vec!["S"])]);
+    }
+}
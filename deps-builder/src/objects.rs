@@ -0,0 +1,790 @@
+//! Dependency information from the symbol tables of compiled ELF objects and
+//! `ar` archives.
+//!
+//! The Clang AST misses symbols that macros, inline assembly or
+//! `#pragma weak` produce, while the object files show exactly what the
+//! linker sees. The dependency information built from them uses the same
+//! schema as the one exported from the AST, and the two can be compared with
+//! `cross_check`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::plan::FileMetrics;
+use crate::{DependencyInfo, DependencySymbol, SymbolKind};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const THIN_AR_MAGIC: &[u8] = b"!<thin>\n";
+
+const SHT_SYMTAB: u32 = 2;
+const SHN_UNDEF: u16 = 0;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_FILE: u8 = 4;
+const STT_COMMON: u8 = 5;
+const STT_TLS: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    Local,
+    Global,
+    Weak,
+}
+
+/// An entry of the symbol table of an object file.
+#[derive(Debug, Clone)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub binding: SymbolBinding,
+    /// `Function` or `Variable`, or `None` if the symbol table does not say,
+    /// as for undefined symbols
+    pub kind: Option<SymbolKind>,
+    pub defined: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectFile {
+    /// Path of the object file, or `archive(member)` for archive members
+    pub path: String,
+    /// The archive the object file is a member of
+    pub archive: Option<String>,
+    /// The source file the object was compiled from, as named by its
+    /// `STT_FILE` symbol
+    pub source: Option<String>,
+    pub symbols: Vec<ObjectSymbol>,
+}
+
+/// Read the object file at `path`, or all object files of the archive at
+/// `path`. Archive members that are not ELF objects are skipped.
+pub fn read_object_files(path: &Path) -> Result<Vec<ObjectFile>, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let path_str = path.to_str().ok_or("object file path is not UTF-8")?;
+    if data.starts_with(ELF_MAGIC) {
+        let (source, symbols) = read_symbols(&data)?;
+        Ok(vec![ObjectFile {
+            path: path_str.to_string(),
+            archive: None,
+            source,
+            symbols,
+        }])
+    } else if data.starts_with(AR_MAGIC) {
+        let mut objects = vec![];
+        for (member, contents) in archive_members(&data)? {
+            if !contents.starts_with(ELF_MAGIC) {
+                continue;
+            }
+            let (source, symbols) =
+                read_symbols(contents).map_err(|e| format!("{}({}): {}", path_str, member, e))?;
+            objects.push(ObjectFile {
+                path: format!("{}({})", path_str, member),
+                archive: Some(path_str.to_string()),
+                source,
+                symbols,
+            });
+        }
+        Ok(objects)
+    } else if data.starts_with(THIN_AR_MAGIC) {
+        Err("thin archives are not supported".into())
+    } else {
+        Err("not an ELF object file or archive".into())
+    }
+}
+
+/// The name and contents of a member of an `ar` archive
+type ArchiveMember<'a> = (String, &'a [u8]);
+
+/// The members of an `ar` archive, skipping the archive symbol table and the
+/// GNU long name table.
+fn archive_members(data: &[u8]) -> Result<Vec<ArchiveMember<'_>>, Box<dyn Error>> {
+    let mut members = vec![];
+    let mut long_names: &[u8] = &[];
+    let mut offset = AR_MAGIC.len();
+    while offset + 60 <= data.len() {
+        let header = &data[offset..offset + 60];
+        if &header[58..60] != b"`\n" {
+            return Err("malformed archive member header".into());
+        }
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range])
+                .trim_end()
+                .to_string()
+        };
+        let name = field(0..16);
+        let size: usize = field(48..58).parse()?;
+        let start = offset + 60;
+        let mut contents = start
+            .checked_add(size)
+            .and_then(|end| data.get(start..end))
+            .ok_or("truncated archive member")?;
+        let end = start + contents.len();
+        // Members start at even offsets
+        offset = end + (end & 1);
+
+        let name = if name == "/" || name == "/SYM64/" || name.starts_with("__.SYMDEF") {
+            continue;
+        } else if name == "//" {
+            long_names = contents;
+            continue;
+        } else if let Some(index) = name.strip_prefix('/') {
+            // GNU long name: offset into the long name table
+            let index: usize = index.parse()?;
+            let rest = long_names.get(index..).ok_or("bad long member name")?;
+            let len = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..len])
+                .trim_end_matches('/')
+                .to_string()
+        } else if let Some(len) = name.strip_prefix("#1/") {
+            // BSD long name: stored at the start of the contents
+            let len: usize = len.parse()?;
+            let name = contents.get(..len).ok_or("bad long member name")?;
+            let name = String::from_utf8_lossy(name)
+                .trim_end_matches('\0')
+                .to_string();
+            contents = &contents[len..];
+            name
+        } else {
+            name.trim_end_matches('/').to_string()
+        };
+        members.push((name, contents));
+    }
+    Ok(members)
+}
+
+/// Bounds-checked reads of the fields of an ELF file.
+struct ElfReader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> ElfReader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| "truncated ELF file".into())
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, Box<dyn Error>> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Box<dyn Error>> {
+        let bytes = self.bytes(offset, 2)?.try_into()?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Box<dyn Error>> {
+        let bytes = self.bytes(offset, 4)?.try_into()?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, Box<dyn Error>> {
+        let bytes = self.bytes(offset, 8)?.try_into()?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// A word-sized field: 8 bytes in ELF64, 4 bytes in ELF32
+    fn word(&self, offset: usize) -> Result<usize, Box<dyn Error>> {
+        Ok(if self.is_64 {
+            self.u64(offset)?.try_into()?
+        } else {
+            self.u32(offset)? as usize
+        })
+    }
+
+    fn str(&self, offset: usize) -> Result<&'a str, Box<dyn Error>> {
+        let rest = self.data.get(offset..).ok_or("truncated ELF file")?;
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        Ok(std::str::from_utf8(&rest[..len])?)
+    }
+}
+
+/// The source file and the symbols of the ELF object `data`.
+fn read_symbols(data: &[u8]) -> Result<(Option<String>, Vec<ObjectSymbol>), Box<dyn Error>> {
+    let mut elf = ElfReader {
+        data,
+        is_64: false,
+        big_endian: false,
+    };
+    elf.is_64 = match elf.u8(4)? {
+        1 => false,
+        2 => true,
+        _ => return Err("unknown ELF class".into()),
+    };
+    elf.big_endian = match elf.u8(5)? {
+        1 => false,
+        2 => true,
+        _ => return Err("unknown ELF data encoding".into()),
+    };
+
+    let (shoff, shentsize, mut shnum) = if elf.is_64 {
+        (elf.word(0x28)?, elf.u16(0x3a)?, elf.u16(0x3c)? as usize)
+    } else {
+        (elf.word(0x20)?, elf.u16(0x2e)?, elf.u16(0x30)? as usize)
+    };
+    if shoff == 0 {
+        return Ok((None, vec![]));
+    }
+    // The offset of a section header, which lies within the file
+    let section = |index: usize| -> Result<usize, Box<dyn Error>> {
+        let offset = index
+            .checked_mul(shentsize as usize)
+            .and_then(|offset| offset.checked_add(shoff))
+            .ok_or("bad section header offset")?;
+        elf.bytes(offset, shentsize as usize)?;
+        Ok(offset)
+    };
+    if shnum == 0 {
+        // More sections than fit the header: the count is in section 0
+        shnum = elf.word(section(0)? + if elf.is_64 { 32 } else { 20 })?;
+    }
+
+    let mut source = None;
+    let mut symbols = vec![];
+    for index in 0..shnum {
+        let header = section(index)?;
+        if elf.u32(header + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size, link, entsize) = if elf.is_64 {
+            (
+                elf.word(header + 24)?,
+                elf.word(header + 32)?,
+                elf.u32(header + 40)? as usize,
+                elf.word(header + 56)?,
+            )
+        } else {
+            (
+                elf.word(header + 16)?,
+                elf.word(header + 20)?,
+                elf.u32(header + 24)? as usize,
+                elf.word(header + 36)?,
+            )
+        };
+        let strtab = elf.word(section(link)? + if elf.is_64 { 24 } else { 16 })?;
+        if entsize == 0 {
+            return Err("symbol table without entry size".into());
+        }
+
+        // Entry 0 is reserved
+        for i in 1..size / entsize {
+            let entry = i
+                .checked_mul(entsize)
+                .and_then(|entry| entry.checked_add(offset))
+                .ok_or("bad symbol table offset")?;
+            let (name, info, shndx) = if elf.is_64 {
+                (elf.u32(entry)?, elf.u8(entry + 4)?, elf.u16(entry + 6)?)
+            } else {
+                (elf.u32(entry)?, elf.u8(entry + 12)?, elf.u16(entry + 14)?)
+            };
+            let name = strtab
+                .checked_add(name as usize)
+                .ok_or("bad symbol name offset")?;
+            let name = elf.str(name)?;
+            let (binding, ty) = (info >> 4, info & 0xf);
+            if ty == STT_FILE {
+                source.get_or_insert_with(|| name.to_string());
+                continue;
+            }
+            if name.is_empty() {
+                continue;
+            }
+            let binding = match binding {
+                STB_LOCAL => SymbolBinding::Local,
+                STB_GLOBAL => SymbolBinding::Global,
+                STB_WEAK => SymbolBinding::Weak,
+                _ => continue,
+            };
+            let kind = match ty {
+                STT_FUNC => Some(SymbolKind::Function),
+                STT_OBJECT | STT_COMMON | STT_TLS => Some(SymbolKind::Variable),
+                STT_NOTYPE => None,
+                // Sections and other bookkeeping symbols
+                _ => continue,
+            };
+            symbols.push(ObjectSymbol {
+                name: name.to_string(),
+                binding,
+                kind,
+                defined: shndx != SHN_UNDEF,
+            });
+        }
+    }
+    Ok((source, symbols))
+}
+
+/// Dependency information for `objects`, one unit per object file. The
+/// functions and variables other objects can link against count as defined,
+/// and undefined symbols take the kind and path of their definition if
+/// exactly one of `objects` defines them, so that the `exact` match strategy
+/// works like the linker. Members of the same archive are linked into it.
+/// Units are named after their source file, which `STT_FILE` gives without
+/// its directory, or after the object file if several objects name the same
+/// source, like `a/util.c` and `b/util.c` both do.
+pub fn object_dependencies(objects: &[ObjectFile]) -> Vec<DependencyInfo> {
+    let mut sources: HashMap<&str, usize> = HashMap::new();
+    for source in objects.iter().filter_map(|object| object.source.as_deref()) {
+        *sources.entry(source).or_default() += 1;
+    }
+    let source_path = |object: &ObjectFile| match &object.source {
+        Some(source) if sources[source.as_str()] == 1 => source.clone(),
+        _ => object.path.clone(),
+    };
+
+    let mut definitions: HashMap<&str, Vec<DependencySymbol>> = HashMap::new();
+    for object in objects {
+        for symbol in &object.symbols {
+            if symbol.defined && symbol.binding != SymbolBinding::Local {
+                definitions
+                    .entry(&symbol.name)
+                    .or_default()
+                    .push(DependencySymbol {
+                        name: symbol.name.clone(),
+                        path: source_path(object),
+                        kind: symbol.kind.unwrap_or(SymbolKind::Function),
+                    });
+            }
+        }
+    }
+
+    objects
+        .iter()
+        .map(|object| {
+            let input_path = source_path(object);
+            let mut info = DependencyInfo {
+                output_path: Path::new(&input_path)
+                    .with_extension("rs")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+                input_path,
+                object_path: Some(object.path.clone()),
+                compile_args: vec![],
+                defines: vec![],
+                undefined: vec![],
+                defined: vec![],
+                uses: vec![],
+                link_targets: object.archive.iter().cloned().collect(),
+                direct_link_targets: object.archive.iter().cloned().collect(),
+                link_libs: vec![],
+                includes: vec![],
+                weak: vec![],
+                metrics: FileMetrics::default(),
+                project: None,
+            };
+            for symbol in &object.symbols {
+                if symbol.binding == SymbolBinding::Local {
+                    continue;
+                }
+                if symbol.binding == SymbolBinding::Weak && !info.weak.contains(&symbol.name) {
+                    info.weak.push(symbol.name.clone());
+                }
+                if symbol.defined {
+                    info.defined.push(DependencySymbol {
+                        name: symbol.name.clone(),
+                        path: info.input_path.clone(),
+                        kind: symbol.kind.unwrap_or(SymbolKind::Function),
+                    });
+                } else {
+                    let undefined = match definitions.get(symbol.name.as_str()).map(Vec::as_slice) {
+                        Some([definition]) => definition.clone(),
+                        _ => DependencySymbol {
+                            name: symbol.name.clone(),
+                            path: String::new(),
+                            kind: symbol.kind.unwrap_or(SymbolKind::Function),
+                        },
+                    };
+                    info.undefined.push(undefined);
+                }
+            }
+            info
+        })
+        .collect()
+}
+
+/// Which side of a cross-check is missing a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingFrom {
+    /// The object file has the symbol but the AST does not, e.g., because a
+    /// macro or inline assembly produced it
+    Ast,
+    /// The AST has the symbol but the object file does not, e.g., because
+    /// the compiler inlined or dropped it
+    Object,
+}
+
+/// A function or variable on which the AST and an object file disagree.
+#[derive(Debug, Clone)]
+pub struct SymbolDifference {
+    /// The unit in the AST-based dependency information
+    pub unit: usize,
+    pub name: String,
+    /// Whether the symbol is defined rather than used by the unit
+    pub defined: bool,
+    pub missing_from: MissingFrom,
+}
+
+/// Compare the functions and variables that the units of `ast` define and
+/// use to those of the units of `objects` with the same object path. Units
+/// without a counterpart are skipped. Symbols are compared by name since
+/// object files don't know the kind of undefined symbols.
+pub fn cross_check(ast: &[DependencyInfo], objects: &[DependencyInfo]) -> Vec<SymbolDifference> {
+    let objects_by_path = objects
+        .iter()
+        .filter_map(|object| Some((object.object_path.as_deref()?, object)))
+        .collect::<HashMap<_, _>>();
+
+    let mut differences = vec![];
+    for (unit, info) in ast.iter().enumerate() {
+        let object = match info
+            .object_path
+            .as_deref()
+            .and_then(|path| objects_by_path.get(path))
+        {
+            Some(object) => object,
+            None => continue,
+        };
+
+        for defined in [true, false] {
+            let names = |info: &'_ DependencyInfo| {
+                let symbols = if defined {
+                    &info.defined
+                } else {
+                    &info.undefined
+                };
+                let mut names = symbols
+                    .iter()
+                    .filter(|symbol| symbol.kind.is_linked())
+                    .map(|symbol| symbol.name.clone())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names.dedup();
+                names
+            };
+            let (ast_names, object_names) = (names(info), names(object));
+            for (names, others, missing_from) in [
+                (&object_names, &ast_names, MissingFrom::Ast),
+                (&ast_names, &object_names, MissingFrom::Object),
+            ] {
+                for name in names {
+                    if others.binary_search(name).is_err() {
+                        differences.push(SymbolDifference {
+                            unit,
+                            name: name.clone(),
+                            defined,
+                            missing_from,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHT_STRTAB: u32 = 3;
+    const STT_SECTION: u8 = 3;
+
+    /// A symbol table entry: name, binding, type and section index
+    type Entry<'a> = (&'a str, u8, u8, u16);
+
+    /// A little-endian ELF64 object whose only sections are a symbol table
+    /// with `entries` and its string table.
+    fn elf64(entries: &[Entry]) -> Vec<u8> {
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 24];
+        for &(name, binding, ty, shndx) in entries {
+            let mut entry = [0; 24];
+            entry[..4].copy_from_slice(&(strtab.len() as u32).to_le_bytes());
+            entry[4] = binding << 4 | ty;
+            entry[6..8].copy_from_slice(&shndx.to_le_bytes());
+            symtab.extend_from_slice(&entry);
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+
+        let strtab_offset = 64;
+        let symtab_offset = strtab_offset + strtab.len();
+        let shoff = symtab_offset + symtab.len();
+        let mut data = vec![0; 64];
+        data[..4].copy_from_slice(ELF_MAGIC);
+        data[4] = 2;
+        data[5] = 1;
+        data[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&strtab);
+        data.extend_from_slice(&symtab);
+
+        let section = |ty: u32, offset: usize, size: usize, link: u32, entsize: u64| {
+            let mut header = [0; 64];
+            header[4..8].copy_from_slice(&ty.to_le_bytes());
+            header[24..32].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(size as u64).to_le_bytes());
+            header[40..44].copy_from_slice(&link.to_le_bytes());
+            header[56..64].copy_from_slice(&entsize.to_le_bytes());
+            header
+        };
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(&section(SHT_SYMTAB, symtab_offset, symtab.len(), 2, 24));
+        data.extend_from_slice(&section(SHT_STRTAB, strtab_offset, strtab.len(), 0, 0));
+        data
+    }
+
+    fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = AR_MAGIC.to_vec();
+        for (name, contents) in members {
+            data.extend_from_slice(
+                format!("{:<16}{:<32}{:<10}`\n", name, "", contents.len()).as_bytes(),
+            );
+            data.extend_from_slice(contents);
+            if data.len() % 2 == 1 {
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+
+    /// `a.c` defines `f`, calls `g` and has a weak default for `h`; `b.c`
+    /// defines the variable `g`
+    fn objects() -> (Vec<u8>, Vec<u8>) {
+        let a = elf64(&[
+            ("a.c", STB_LOCAL, STT_FILE, 0xfff1),
+            (".text", STB_LOCAL, STT_SECTION, 1),
+            ("helper", STB_LOCAL, STT_FUNC, 1),
+            ("f", STB_GLOBAL, STT_FUNC, 1),
+            ("g", STB_GLOBAL, STT_NOTYPE, SHN_UNDEF),
+            ("h", STB_WEAK, STT_FUNC, 1),
+        ]);
+        let b = elf64(&[
+            ("b.c", STB_LOCAL, STT_FILE, 0xfff1),
+            ("g", STB_GLOBAL, STT_OBJECT, 2),
+        ]);
+        (a, b)
+    }
+
+    #[test]
+    fn symbols() {
+        let (a, _) = objects();
+        let (source, symbols) = read_symbols(&a).unwrap();
+        assert_eq!(source.as_deref(), Some("a.c"));
+        let symbols = symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.binding, s.kind, s.defined))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                (
+                    "helper",
+                    SymbolBinding::Local,
+                    Some(SymbolKind::Function),
+                    true
+                ),
+                ("f", SymbolBinding::Global, Some(SymbolKind::Function), true),
+                ("g", SymbolBinding::Global, None, false),
+                ("h", SymbolBinding::Weak, Some(SymbolKind::Function), true),
+            ]
+        );
+        assert!(read_symbols(&a[..100]).is_err());
+    }
+
+    #[test]
+    fn archive_member_names() {
+        let data = archive(&[
+            ("/", b"\0\0\0\0"),
+            ("//", b"a_rather_long_member_name.o/\n"),
+            ("/0", b"long"),
+            ("short.o/", b"short"),
+            ("#1/8", b"bsd.o\0\0\0bsd"),
+        ]);
+        let members = archive_members(&data).unwrap();
+        assert_eq!(
+            members,
+            vec![
+                ("a_rather_long_member_name.o".to_string(), &b"long"[..]),
+                ("short.o".to_string(), &b"short"[..]),
+                ("bsd.o".to_string(), &b"bsd"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_archive() {
+        let (a, b) = objects();
+        let data = archive(&[("a.o/", &a), ("README/", b"text"), ("b.o/", &b)]);
+        let path = std::env::temp_dir().join(format!("deps-builder-test-{}.a", std::process::id()));
+        fs::write(&path, data).unwrap();
+        let objects = read_object_files(&path);
+        fs::remove_file(&path).unwrap();
+
+        let objects = objects.unwrap();
+        let path = path.to_str().unwrap();
+        let paths = objects.iter().map(|o| o.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![format!("{}(a.o)", path), format!("{}(b.o)", path)]
+        );
+        assert!(objects.iter().all(|o| o.archive.as_deref() == Some(path)));
+    }
+
+    #[test]
+    fn dependencies() {
+        let (a, b) = objects();
+        let object = |path: &str, data: &[u8]| {
+            let (source, symbols) = read_symbols(data).unwrap();
+            ObjectFile {
+                path: path.to_string(),
+                archive: None,
+                source,
+                symbols,
+            }
+        };
+        let infos = object_dependencies(&[object("/b/a.o", &a), object("/b/b.o", &b)]);
+
+        assert_eq!(infos[0].input_path, "a.c");
+        assert_eq!(infos[0].output_path, "a.rs");
+        assert_eq!(infos[0].object_path.as_deref(), Some("/b/a.o"));
+        let names = |symbols: &[DependencySymbol]| {
+            symbols.iter().map(|s| s.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&infos[0].defined), vec!["f", "h"]);
+        assert_eq!(infos[0].weak, vec!["h"]);
+        // The use of `g` takes the kind and path of its only definition
+        assert_eq!(
+            infos[0].undefined,
+            vec![DependencySymbol {
+                name: "g".to_string(),
+                path: "b.c".to_string(),
+                kind: SymbolKind::Variable,
+            }]
+        );
+    }
+
+    #[test]
+    fn ambiguous_sources() {
+        let util = elf64(&[
+            ("util.c", STB_LOCAL, STT_FILE, 0xfff1),
+            ("g", STB_GLOBAL, STT_OBJECT, 2),
+        ]);
+        let (a, _) = objects();
+        let object = |path: &str, data: &[u8]| {
+            let (source, symbols) = read_symbols(data).unwrap();
+            ObjectFile {
+                path: path.to_string(),
+                archive: None,
+                source,
+                symbols,
+            }
+        };
+        let infos = object_dependencies(&[
+            object("/b/a.o", &a),
+            object("/b/x/util.o", &util),
+            object("/b/y/util.o", &util),
+        ]);
+        let inputs = infos
+            .iter()
+            .map(|info| info.input_path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec!["a.c", "/b/x/util.o", "/b/y/util.o"]);
+        assert_eq!(infos[1].output_path, "/b/x/util.rs");
+        assert_eq!(infos[1].defined[0].path, "/b/x/util.o");
+    }
+
+    #[test]
+    fn corrupt_offsets() {
+        // A member larger than the archive
+        let mut data = archive(&[("a.o/", b"ab")]);
+        let size = AR_MAGIC.len() + 48;
+        data[size..size + 10].copy_from_slice(b"9999999999");
+        assert!(archive_members(&data).is_err());
+
+        // Section headers and symbols past the end of the address space
+        let (a, _) = objects();
+        let mut data = a.clone();
+        data[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_symbols(&data).is_err());
+        let mut data = a.clone();
+        data[0x3c..0x3e].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(read_symbols(&data).is_err());
+        let shoff = u64::from_le_bytes(a[0x28..0x30].try_into().unwrap()) as usize;
+        let symtab = shoff + 64;
+        let mut data = a;
+        data[symtab + 24..symtab + 32].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(read_symbols(&data).is_err());
+    }
+
+    #[test]
+    fn cross_check_differences() {
+        let symbols = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| DependencySymbol {
+                    name: name.to_string(),
+                    path: "a.c".to_string(),
+                    kind: SymbolKind::Function,
+                })
+                .collect()
+        };
+        let unit = |defined: &[&str], undefined: &[&str], object_path: &str| DependencyInfo {
+            input_path: "a.c".to_string(),
+            output_path: "a.rs".to_string(),
+            object_path: Some(object_path.to_string()),
+            compile_args: vec![],
+            defines: vec![],
+            undefined: symbols(undefined),
+            defined: symbols(defined),
+            uses: vec![],
+            link_targets: vec![],
+            direct_link_targets: vec![],
+            link_libs: vec![],
+            includes: vec![],
+            weak: vec![],
+            metrics: FileMetrics::default(),
+            project: None,
+        };
+        let ast = [
+            unit(&["f", "inlined"], &["g"], "a.o"),
+            unit(&["x"], &[], "x.o"),
+        ];
+        let objects = [unit(&["f"], &["g", "__stack_chk_fail"], "a.o")];
+
+        let differences = cross_check(&ast, &objects)
+            .into_iter()
+            .map(|d| (d.unit, d.name, d.defined, d.missing_from))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                (0, "inlined".to_string(), true, MissingFrom::Object),
+                (0, "__stack_chk_fail".to_string(), false, MissingFrom::Ast),
+            ]
+        );
+    }
+}
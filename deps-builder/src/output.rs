@@ -0,0 +1,333 @@
+//! Output formats for the dependency graph besides DOT: JSON, GraphML,
+//! Mermaid and a self-contained HTML page.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::matching::MatchStrategy;
+use crate::{DependencyGraph, DependencySymbol};
+
+/// The page `to_html` fills in with the graph
+const HTML_TEMPLATE: &str = include_str!("graph.html");
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedNode {
+    pub id: usize,
+    /// File name of the output file, prefixed with the project in a merged
+    /// graph
+    pub label: String,
+    pub input_path: String,
+    pub output_path: String,
+    /// The project of the file in a merged graph
+    pub project: Option<String>,
+    /// Whether a program starts in the file
+    pub is_main: bool,
+    /// Whether the file depends on itself through other files
+    pub in_cycle: bool,
+    /// Index of the strongly connected component of the file
+    pub component: usize,
+    /// Length of the longest chain of dependencies below the file, so that
+    /// files only depend on files in lower layers or in their own cycle
+    pub layer: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedEdge {
+    pub from: usize,
+    pub to: usize,
+    /// The symbols `from` uses from `to`
+    pub symbols: Vec<DependencySymbol>,
+}
+
+/// A dependency graph together with the symbols behind its edges.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedGraph {
+    pub nodes: Vec<ExportedNode>,
+    pub edges: Vec<ExportedEdge>,
+}
+
+impl DependencyGraph {
+    /// Collect everything the output formats show, with the symbols behind
+    /// the edges matched with `strategy`.
+    pub fn export(&self, strategy: MatchStrategy) -> ExportedGraph {
+        let condensation = self.condense();
+        let mut component_layers = vec![0; condensation.components.len()];
+        // Components come after the ones they depend on
+        for (c, edges) in condensation.edges.iter().enumerate() {
+            component_layers[c] = edges
+                .iter()
+                .map(|&d| component_layers[d] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let component = condensation.component_of[i];
+                ExportedNode {
+                    id: i,
+                    label: node.label(),
+                    input_path: node.input_path.clone(),
+                    output_path: node.output_path.clone(),
+                    project: node.project.clone(),
+                    is_main: self.is_entry(i),
+                    in_cycle: condensation.components[component].len() > 1
+                        || self.direct_depends_on(i, i),
+                    component,
+                    layer: component_layers[component],
+                }
+            })
+            .collect();
+
+        let edges = self
+            .edges
+            .iter()
+            .zip(self.edge_symbols(strategy))
+            .enumerate()
+            .flat_map(|(from, (edges, symbols))| {
+                edges
+                    .iter()
+                    .zip(symbols)
+                    .map(move |(&to, symbols)| ExportedEdge { from, to, symbols })
+            })
+            .collect();
+
+        ExportedGraph { nodes, edges }
+    }
+}
+
+impl ExportedGraph {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// GraphML with the file names, paths and symbols as attributes.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        for (id, domain, ty) in [
+            ("label", "node", "string"),
+            ("input_path", "node", "string"),
+            ("output_path", "node", "string"),
+            ("is_main", "node", "boolean"),
+            ("in_cycle", "node", "boolean"),
+            ("layer", "node", "int"),
+            ("symbols", "edge", "string"),
+        ] {
+            writeln!(
+                out,
+                "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>",
+                id, domain, ty
+            )
+            .unwrap();
+        }
+        out.push_str("  <graph id=\"dependency_graph\" edgedefault=\"directed\">\n");
+
+        for node in &self.nodes {
+            writeln!(out, "    <node id=\"n{}\">", node.id).unwrap();
+            for (key, value) in [
+                ("label", xml_escape(&node.label)),
+                ("input_path", xml_escape(&node.input_path)),
+                ("output_path", xml_escape(&node.output_path)),
+                ("is_main", node.is_main.to_string()),
+                ("in_cycle", node.in_cycle.to_string()),
+                ("layer", node.layer.to_string()),
+            ] {
+                writeln!(out, "      <data key=\"{}\">{}</data>", key, value).unwrap();
+            }
+            out.push_str("    </node>\n");
+        }
+
+        for (i, edge) in self.edges.iter().enumerate() {
+            let symbols = edge
+                .symbols
+                .iter()
+                .map(|symbol| symbol.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(
+                out,
+                "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">",
+                i, edge.from, edge.to
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "      <data key=\"symbols\">{}</data>",
+                xml_escape(&symbols)
+            )
+            .unwrap();
+            out.push_str("    </edge>\n");
+        }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// A Mermaid flowchart. Like in the DOT output, main files are outlined
+    /// in red and files in a cycle are orange.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        for node in &self.nodes {
+            writeln!(out, "    n{}[\"{}\"]", node.id, mermaid_escape(&node.label)).unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(out, "    n{} --> n{}", edge.from, edge.to).unwrap();
+        }
+
+        out.push_str("    classDef main stroke:red,stroke-width:2px\n");
+        out.push_str("    classDef cycle fill:orange\n");
+        for (class, nodes) in [
+            (
+                "main",
+                self.nodes.iter().filter(|n| n.is_main).collect::<Vec<_>>(),
+            ),
+            ("cycle", self.nodes.iter().filter(|n| n.in_cycle).collect()),
+        ] {
+            if !nodes.is_empty() {
+                let ids = nodes
+                    .iter()
+                    .map(|node| format!("n{}", node.id))
+                    .collect::<Vec<_>>();
+                writeln!(out, "    class {} {}", ids.join(","), class).unwrap();
+            }
+        }
+        out
+    }
+
+    /// A page that draws the graph in layers, with dependencies below the
+    /// files using them. It can be zoomed and searched, and clicking a file
+    /// lists the symbols behind its edges. Nothing is loaded from elsewhere.
+    pub fn to_html(&self) -> String {
+        // `</script>` in a path would end the script early
+        let json = serde_json::to_string(self).unwrap().replace("</", "<\\/");
+        HTML_TEMPLATE.replace("/*GRAPH_DATA*/null", &json)
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_dependency;
+    use crate::tests::{symbol, unit};
+    use crate::SymbolKind;
+
+    /// `main.c` uses `parse.c`, which depends on `lex.c` and back
+    fn exported() -> ExportedGraph {
+        build_dependency(
+            vec![
+                unit("/src/main.c", &["main"], &["parse"]),
+                unit("/src/parse.c", &["parse"], &["lex"]),
+                unit("/src/lex.c", &["lex"], &["parse"]),
+            ],
+            MatchStrategy::NameOnly,
+        )
+        .export(MatchStrategy::NameOnly)
+    }
+
+    /// A graph whose label, paths and symbols need escaping
+    fn awkward() -> ExportedGraph {
+        let node = |id: usize, label: &str, path: &str| ExportedNode {
+            id,
+            label: label.to_owned(),
+            input_path: path.to_owned(),
+            output_path: path.to_owned(),
+            project: None,
+            is_main: id == 0,
+            in_cycle: false,
+            component: id,
+            layer: 1 - id,
+        };
+        ExportedGraph {
+            nodes: vec![
+                node(0, "say \"hi\".rs", "/src/<a&b>.c"),
+                node(1, "lib.rs", "/src/</script>.c"),
+            ],
+            edges: vec![ExportedEdge {
+                from: 0,
+                to: 1,
+                symbols: vec![symbol("operator<", "/src/x.h", SymbolKind::Function)],
+            }],
+        }
+    }
+
+    #[test]
+    fn export() {
+        let graph = exported();
+        let nodes = graph
+            .nodes
+            .iter()
+            .map(|n| (n.label.as_str(), n.is_main, n.in_cycle, n.layer))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                ("main.rs", true, false, 1),
+                ("parse.rs", false, true, 0),
+                ("lex.rs", false, true, 0),
+            ]
+        );
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| (e.from, e.to, e.symbols[0].name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![(0, 1, "parse"), (1, 2, "lex"), (2, 1, "parse")]);
+    }
+
+    #[test]
+    fn graphml_escaping() {
+        let graphml = awkward().to_graphml();
+        assert!(graphml.contains("<data key=\"label\">say &quot;hi&quot;.rs</data>"));
+        assert!(graphml.contains("<data key=\"input_path\">/src/&lt;a&amp;b&gt;.c</data>"));
+        assert!(graphml.contains("<data key=\"symbols\">operator&lt;</data>"));
+        assert!(graphml.contains("<edge id=\"e0\" source=\"n0\" target=\"n1\">"));
+        assert!(!graphml.contains("<a&b>"));
+    }
+
+    #[test]
+    fn mermaid_escaping() {
+        assert_eq!(
+            awkward().to_mermaid(),
+            "flowchart TD\n\
+             \x20   n0[\"say #quot;hi#quot;.rs\"]\n\
+             \x20   n1[\"lib.rs\"]\n\
+             \x20   n0 --> n1\n\
+             \x20   classDef main stroke:red,stroke-width:2px\n\
+             \x20   classDef cycle fill:orange\n\
+             \x20   class n0 main\n"
+        );
+    }
+
+    #[test]
+    fn html_embeds_data() {
+        let graph = awkward();
+        let html = graph.to_html();
+        assert!(!html.contains("/*GRAPH_DATA*/null"));
+        // The data is the only thing that changes, and it can't end the script
+        let json = serde_json::to_string(&graph).unwrap().replace("</", "<\\/");
+        assert_eq!(html, HTML_TEMPLATE.replace("/*GRAPH_DATA*/null", &json));
+        assert_eq!(
+            html.matches("</script>").count(),
+            HTML_TEMPLATE.matches("</script>").count()
+        );
+        let data: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(data["nodes"][1]["input_path"], "/src/</script>.c");
+    }
+}
@@ -0,0 +1,100 @@
+//! Splitting the files of several binaries into the ones they share, which
+//! can be compiled once into a common library crate, and the ones each binary
+//! needs for itself.
+
+use crate::DependencyGraph;
+
+#[derive(Debug, Clone, Default)]
+pub struct CratePartition {
+    /// Files that more than one of the binaries need, in node order. Whatever
+    /// a shared file depends on is shared as well, except for the binaries,
+    /// which `partition_crates` refuses to share, so the common crate never
+    /// needs the files of a binary crate.
+    pub common: Vec<usize>,
+    /// For each binary, the binary and the other files only it needs
+    pub binaries: Vec<(usize, Vec<usize>)>,
+}
+
+impl DependencyGraph {
+    /// Partition the files `binaries` transitively depend on. The binaries
+    /// themselves always stay in their own crates, so a binary that depends on
+    /// another one does not get the other's file. Fails if a shared file
+    /// depends on one of the binaries, as the common crate could not be built
+    /// without that binary's crate.
+    pub fn partition_crates(&self, binaries: &[usize]) -> Result<CratePartition, String> {
+        let mut users = vec![0; self.nodes.len()];
+        let reachable = binaries
+            .iter()
+            .map(|&binary| {
+                let nodes = self.reachable_nodes(vec![binary]);
+                for &i in &nodes {
+                    users[i] += 1;
+                }
+                nodes
+            })
+            .collect::<Vec<_>>();
+
+        let is_common = |i: usize| users[i] > 1 && !binaries.contains(&i);
+        let mut partition = CratePartition {
+            common: (0..self.nodes.len()).filter(|&i| is_common(i)).collect(),
+            binaries: vec![],
+        };
+        for &i in &partition.common {
+            if let Some(&binary) = self.edges[i].iter().find(|j| binaries.contains(j)) {
+                return Err(format!(
+                    "shared file {} depends on the binary {}",
+                    self.nodes[i].label(),
+                    self.nodes[binary].label()
+                ));
+            }
+        }
+        for (&binary, nodes) in binaries.iter().zip(reachable) {
+            let mut own = nodes
+                .into_iter()
+                .filter(|&i| i == binary || (!is_common(i) && !binaries.contains(&i)))
+                .collect::<Vec<_>>();
+            own.sort_unstable();
+            partition.binaries.push((binary, own));
+        }
+        Ok(partition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::plain_graph;
+
+    #[test]
+    fn shared_files() {
+        // Binaries 0 and 1 share 2, which depends on 3; 4 is only 1's
+        let graph = plain_graph(5, &[(0, 2), (1, 2), (1, 4), (2, 3)]);
+        let partition = graph.partition_crates(&[0, 1]).unwrap();
+        assert_eq!(partition.common, vec![2, 3]);
+        assert_eq!(partition.binaries, vec![(0, vec![0]), (1, vec![1, 4])]);
+    }
+
+    #[test]
+    fn binaries_stay_apart() {
+        // Binary 0 calls into binary 1, which neither shares nor owns 0
+        let graph = plain_graph(3, &[(0, 1), (1, 2)]);
+        let partition = graph.partition_crates(&[0, 1]).unwrap();
+        assert_eq!(partition.common, vec![2]);
+        assert_eq!(partition.binaries, vec![(0, vec![0]), (1, vec![1])]);
+    }
+
+    #[test]
+    fn shared_file_depending_on_binary() {
+        // 2 is shared by both binaries but needs binary 1
+        let graph = plain_graph(3, &[(0, 2), (1, 2), (2, 1)]);
+        let err = graph.partition_crates(&[0, 1]).unwrap_err();
+        assert_eq!(err, "shared file 2.rs depends on the binary 1.rs");
+    }
+
+    #[test]
+    fn single_binary() {
+        let graph = plain_graph(3, &[(0, 1)]);
+        let partition = graph.partition_crates(&[0]).unwrap();
+        assert!(partition.common.is_empty());
+        assert_eq!(partition.binaries, vec![(0, vec![0, 1])]);
+    }
+}
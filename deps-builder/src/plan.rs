@@ -0,0 +1,233 @@
+//! A plan for porting a project file by file: how hard each file looks to
+//! port, and an order in which every file comes after the ones it depends on
+//! while the files many others depend on come as early as possible.
+
+use serde::{Deserialize, Serialize};
+
+use crate::DependencyGraph;
+
+/// What the typed AST tells about the code of a file, not counting headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetrics {
+    /// Functions defined in the file, including static ones
+    pub functions: usize,
+    /// Lines spanned by those functions
+    pub lines: usize,
+    pub gotos: usize,
+    /// Inline assembly statements
+    pub asm: usize,
+    /// Variadic functions defined in the file
+    pub variadic_functions: usize,
+    /// Unions defined in the file
+    pub unions: usize,
+    /// Bit-fields of the structs and unions defined in the file
+    pub bitfields: usize,
+}
+
+impl FileMetrics {
+    /// A rough estimate of the effort to port the code of the file. Every
+    /// function and every 20 lines count one point, while the constructs that
+    /// translate to unidiomatic or unsafe Rust count more.
+    pub fn difficulty(&self) -> usize {
+        self.functions
+            + self.lines / 20
+            + 5 * self.gotos
+            + 10 * self.asm
+            + 5 * self.variadic_functions
+            + 3 * self.unions
+            + 2 * self.bitfields
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileEstimate {
+    pub node: usize,
+    /// Files that directly depend on this one
+    pub in_degree: usize,
+    /// Files this one directly depends on
+    pub out_degree: usize,
+    /// Files that transitively depend on this one
+    pub leverage: usize,
+    /// The difficulty of the code of the file, plus one point for each file
+    /// it depends on, whose interface it has to be ported against
+    pub difficulty: usize,
+}
+
+/// Files to port together, either a single file or files that depend on
+/// each other in a cycle.
+#[derive(Debug, Clone)]
+pub struct PlanStep {
+    pub files: Vec<FileEstimate>,
+    pub difficulty: usize,
+    /// Files outside of the step that transitively depend on it
+    pub leverage: usize,
+}
+
+impl DependencyGraph {
+    pub fn file_estimates(&self) -> Vec<FileEstimate> {
+        let mut in_degree = vec![0; self.nodes.len()];
+        for (from, edges) in self.edges.iter().enumerate() {
+            for &to in edges {
+                if to != from {
+                    in_degree[to] += 1;
+                }
+            }
+        }
+
+        (0..self.nodes.len())
+            .map(|i| {
+                let out_degree = self.edges[i].iter().filter(|&&j| j != i).count();
+                FileEstimate {
+                    node: i,
+                    in_degree: in_degree[i],
+                    out_degree,
+                    leverage: self
+                        .reverse_dependencies(i)
+                        .into_iter()
+                        .filter(|&j| j != i)
+                        .count(),
+                    difficulty: self.nodes[i].metrics.difficulty() + out_degree,
+                }
+            })
+            .collect()
+    }
+
+    /// An order for porting the files of the graph. A step is ready once all
+    /// the steps it depends on are done, and of the ready steps the one with
+    /// the most leverage for its difficulty comes first.
+    pub fn migration_plan(&self) -> Vec<PlanStep> {
+        let condensation = self.condense();
+        let mut estimates = self
+            .file_estimates()
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        let mut steps = condensation
+            .components
+            .iter()
+            .map(|component| {
+                let mut dependents = component
+                    .iter()
+                    .flat_map(|&i| self.reverse_dependencies(i))
+                    .filter(|i| !component.contains(i))
+                    .collect::<Vec<_>>();
+                dependents.sort_unstable();
+                dependents.dedup();
+                let files = component
+                    .iter()
+                    .map(|&i| estimates[i].take().unwrap())
+                    .collect::<Vec<_>>();
+                Some(PlanStep {
+                    difficulty: files.iter().map(|file| file.difficulty).sum(),
+                    leverage: dependents.len(),
+                    files,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut dependents = vec![vec![]; steps.len()];
+        let mut missing = vec![0; steps.len()];
+        for (c, edges) in condensation.edges.iter().enumerate() {
+            for &d in edges {
+                dependents[d].push(c);
+                missing[c] += 1;
+            }
+        }
+
+        let mut ready = (0..steps.len())
+            .filter(|&c| missing[c] == 0)
+            .collect::<Vec<_>>();
+        let mut plan = vec![];
+        while !ready.is_empty() {
+            // Compare leverage / difficulty without dividing, preferring the
+            // earlier step on ties to keep the order stable
+            let value = |c: usize| {
+                let step = steps[c].as_ref().unwrap();
+                (step.leverage + 1, step.difficulty + 1)
+            };
+            let best = (0..ready.len())
+                .reduce(|best, r| {
+                    let ((l1, d1), (l2, d2)) = (value(ready[best]), value(ready[r]));
+                    if l2 * d1 > l1 * d2 || (l2 * d1 == l1 * d2 && ready[r] < ready[best]) {
+                        r
+                    } else {
+                        best
+                    }
+                })
+                .unwrap();
+            let c = ready.remove(best);
+            for &dependent in &dependents[c] {
+                missing[dependent] -= 1;
+                if missing[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+            plan.push(steps[c].take().unwrap());
+        }
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::plain_graph;
+
+    /// `main` (0) uses `util` (1), `parse` (2) and `log` (4); `parse` uses
+    /// `util` and depends on `lex` (3) in a cycle
+    fn project() -> DependencyGraph {
+        plain_graph(5, &[(0, 1), (0, 2), (0, 4), (2, 3), (2, 1), (3, 2)])
+    }
+
+    fn order(graph: &DependencyGraph) -> Vec<Vec<usize>> {
+        graph
+            .migration_plan()
+            .iter()
+            .map(|step| step.files.iter().map(|file| file.node).collect())
+            .collect()
+    }
+
+    #[test]
+    fn difficulty() {
+        let metrics = FileMetrics {
+            functions: 3,
+            lines: 45,
+            gotos: 1,
+            asm: 1,
+            variadic_functions: 1,
+            unions: 1,
+            bitfields: 2,
+        };
+        assert_eq!(metrics.difficulty(), 3 + 2 + 5 + 10 + 5 + 3 + 4);
+        assert_eq!(FileMetrics::default().difficulty(), 0);
+    }
+
+    #[test]
+    fn estimates() {
+        let mut graph = project();
+        graph.nodes[2].metrics.functions = 4;
+        let parse = &graph.file_estimates()[2];
+        assert_eq!(parse.in_degree, 2);
+        assert_eq!(parse.out_degree, 2);
+        assert_eq!(parse.leverage, 2);
+        assert_eq!(parse.difficulty, 4 + 2);
+    }
+
+    #[test]
+    fn leverage_first() {
+        let graph = project();
+        assert_eq!(order(&graph), vec![vec![1], vec![4], vec![2, 3], vec![0]]);
+        let plan = graph.migration_plan();
+        assert_eq!(plan[0].leverage, 3);
+        assert_eq!(plan[2].difficulty, 3);
+        assert_eq!(plan[2].leverage, 1);
+    }
+
+    #[test]
+    fn hard_files_wait() {
+        let mut graph = project();
+        graph.nodes[1].metrics.gotos = 2;
+        assert_eq!(order(&graph), vec![vec![4], vec![1], vec![2, 3], vec![0]]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Dependency graphs spanning several projects, like a tool and a library it
+//! vendors, each built with a compile database of its own. The dependency
+//! files of the projects are merged into one graph, in which symbols resolve
+//! across projects like within one.
+
+use std::collections::HashMap;
+
+use crate::{DependencyGraph, DependencyInfo};
+
+/// A unit that more than one project compiles, with the same input and
+/// output path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedUnit {
+    pub input_path: String,
+    pub output_path: String,
+    /// The project the merged unit belongs to
+    pub owner: String,
+    /// The other projects compiling the unit, whose units are dropped
+    pub projects: Vec<String>,
+}
+
+/// Merge the dependency information of several projects, given with their
+/// names, setting the project of every unit. A unit that more than one
+/// project compiles belongs to the first of them; these are returned as
+/// well, since the other projects lose the unit.
+pub fn merge_dependencies(
+    projects: Vec<(String, Vec<DependencyInfo>)>,
+) -> (Vec<DependencyInfo>, Vec<SharedUnit>) {
+    let mut merged: Vec<DependencyInfo> = vec![];
+    let mut units: HashMap<_, usize> = HashMap::new();
+    let mut shared: Vec<SharedUnit> = vec![];
+    let mut shared_units = HashMap::new();
+    for (project, dependency_infos) in projects {
+        for mut dependency_info in dependency_infos {
+            let unit = (
+                dependency_info.input_path.clone(),
+                dependency_info.output_path.clone(),
+            );
+            let owner = match units.get(&unit) {
+                Some(&i) => merged[i].project.as_ref().unwrap(),
+                None => {
+                    units.insert(unit, merged.len());
+                    dependency_info.project = Some(project.clone());
+                    merged.push(dependency_info);
+                    continue;
+                }
+            };
+            if *owner == project {
+                continue;
+            }
+            let i = *shared_units
+                .entry(unit)
+                .or_insert_with_key(|(input_path, output_path)| {
+                    shared.push(SharedUnit {
+                        input_path: input_path.clone(),
+                        output_path: output_path.clone(),
+                        owner: owner.clone(),
+                        projects: vec![],
+                    });
+                    shared.len() - 1
+                });
+            if !shared[i].projects.contains(&project) {
+                shared[i].projects.push(project.clone());
+            }
+        }
+    }
+    (merged, shared)
+}
+
+/// A project of a merged graph, as a member of a workspace in which every
+/// project becomes a crate.
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    pub project: String,
+    pub files: Vec<usize>,
+    /// The other projects whose files the files of this one depend on
+    pub dependencies: Vec<String>,
+}
+
+impl DependencyGraph {
+    /// The projects of the graph in an order in which every project comes
+    /// after the ones it depends on. Projects depending on each other in a
+    /// cycle, which can't become separate crates, keep the order in which
+    /// their files come.
+    pub fn workspace_plan(&self) -> Vec<WorkspaceMember> {
+        let mut members: Vec<WorkspaceMember> = vec![];
+        let mut member_of = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let project = node.project.clone().unwrap_or_default();
+            member_of[i] = match members.iter().position(|m| m.project == project) {
+                Some(m) => m,
+                None => {
+                    members.push(WorkspaceMember {
+                        project,
+                        files: vec![],
+                        dependencies: vec![],
+                    });
+                    members.len() - 1
+                }
+            };
+            members[member_of[i]].files.push(i);
+        }
+
+        let mut dependencies = vec![vec![]; members.len()];
+        for (i, edges) in self.edges.iter().enumerate() {
+            for &j in edges {
+                let (m, n) = (member_of[i], member_of[j]);
+                if m != n && !dependencies[m].contains(&n) {
+                    dependencies[m].push(n);
+                }
+            }
+        }
+
+        let mut order = vec![];
+        let mut placed = vec![false; members.len()];
+        while order.len() < members.len() {
+            let next = (0..members.len())
+                .filter(|&m| !placed[m])
+                .find(|&m| dependencies[m].iter().all(|&n| placed[n]))
+                .unwrap_or_else(|| placed.iter().position(|&placed| !placed).unwrap());
+            placed[next] = true;
+            order.push(next);
+        }
+
+        let names = members
+            .iter()
+            .map(|member| member.project.clone())
+            .collect::<Vec<_>>();
+        let mut members = members.into_iter().map(Some).collect::<Vec<_>>();
+        order
+            .into_iter()
+            .map(|m| {
+                let mut member = members[m].take().unwrap();
+                member.dependencies = dependencies[m].iter().map(|&n| names[n].clone()).collect();
+                member
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{graph, unit};
+
+    fn project(name: &str, units: Vec<DependencyInfo>) -> (String, Vec<DependencyInfo>) {
+        (name.to_string(), units)
+    }
+
+    #[test]
+    fn merge() {
+        let (merged, shared) = merge_dependencies(vec![
+            project("tool", vec![unit("/src/main.c", &["main"], &[])]),
+            project("lib", vec![unit("/lib/parse.c", &["parse"], &[])]),
+        ]);
+        let projects = merged
+            .iter()
+            .map(|unit| unit.project.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(projects, vec![Some("tool"), Some("lib")]);
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn shared_units() {
+        let (merged, shared) = merge_dependencies(vec![
+            project("tool", vec![unit("/src/util.c", &["util"], &[])]),
+            project("lib", vec![unit("/src/util.c", &["util"], &[])]),
+            project("test", vec![unit("/src/util.c", &["util"], &[])]),
+        ]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].project.as_deref(), Some("tool"));
+        assert_eq!(
+            shared,
+            vec![SharedUnit {
+                input_path: "/src/util.c".to_string(),
+                output_path: "/src/util.rs".to_string(),
+                owner: "tool".to_string(),
+                projects: vec!["lib".to_string(), "test".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn unit_listed_twice() {
+        let (merged, shared) = merge_dependencies(vec![project(
+            "tool",
+            vec![
+                unit("/src/util.c", &["util"], &[]),
+                unit("/src/util.c", &["util"], &[]),
+            ],
+        )]);
+        assert_eq!(merged.len(), 1);
+        assert!(shared.is_empty());
+    }
+
+    fn projects_graph(projects: &[&str], edges: &[(usize, usize)]) -> DependencyGraph {
+        let units = projects
+            .iter()
+            .enumerate()
+            .map(|(i, project)| DependencyInfo {
+                project: Some(project.to_string()),
+                ..unit(&format!("/src/{}.c", i), &[], &[])
+            })
+            .collect();
+        graph(units, edges)
+    }
+
+    fn plan(graph: &DependencyGraph) -> Vec<(String, Vec<usize>, Vec<String>)> {
+        graph
+            .workspace_plan()
+            .into_iter()
+            .map(|member| (member.project, member.files, member.dependencies))
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_first() {
+        // The tool uses the library, which uses the support code
+        let graph = projects_graph(
+            &["tool", "lib", "tool", "support"],
+            &[(0, 1), (2, 1), (1, 3)],
+        );
+        let strings = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            plan(&graph),
+            vec![
+                ("support".to_string(), vec![3], vec![]),
+                ("lib".to_string(), vec![1], strings(&["support"])),
+                ("tool".to_string(), vec![0, 2], strings(&["lib"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycle_keeps_file_order() {
+        let graph = projects_graph(&["a", "b", "c"], &[(0, 1), (1, 0), (2, 0)]);
+        let order = plan(&graph)
+            .into_iter()
+            .map(|(project, _, _)| project)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Questions about single files of the dependency graph: why one file
+//! depends on another, which files depend on it, and which binaries have to
+//! be checked again when its translation changes.
+
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::DependencyGraph;
+
+impl DependencyGraph {
+    /// The nodes `name` refers to: the input or output path of a file, its
+    /// file name, or the name of a binary, i.e., the stem of its output file.
+    /// In a graph merged from several projects, the name can be prefixed with
+    /// the project, as in `tool:main.c`.
+    pub fn find_nodes(&self, name: &str) -> Vec<usize> {
+        let (project, name) = match name.split_once(':') {
+            Some((project, name))
+                if self
+                    .nodes
+                    .iter()
+                    .any(|node| node.project.as_deref() == Some(project)) =>
+            {
+                (Some(project), name)
+            }
+            _ => (None, name),
+        };
+        let candidates = (0..self.nodes.len())
+            .filter(|&i| project.is_none() || self.nodes[i].project.as_deref() == project)
+            .collect::<Vec<_>>();
+
+        let matches = |path: &str| {
+            let path = Path::new(path);
+            path == Path::new(name) || path.file_name() == Some(OsStr::new(name))
+        };
+        let exact = candidates
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let node = &self.nodes[i];
+                matches(&node.input_path) || matches(&node.output_path)
+            })
+            .collect::<Vec<_>>();
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        candidates
+            .into_iter()
+            .filter(|&i| {
+                Path::new(&self.nodes[i].output_path).file_stem() == Some(OsStr::new(name))
+            })
+            .collect()
+    }
+
+    /// The shortest chain of dependencies leading from `from` to `to`, both
+    /// included, or `None` if `from` does not depend on `to`.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        // Searching from the direct dependencies of `from` finds a path of at
+        // least one edge, so that a file depends on itself only in a cycle
+        let mut previous = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        let mut visit = |node_index: usize, previous_node_index: usize, queue: &mut VecDeque<_>| {
+            if previous[node_index].is_none() {
+                previous[node_index] = Some(previous_node_index);
+                queue.push_back(node_index);
+            }
+        };
+        for &next_node_index in &self.edges[from] {
+            visit(next_node_index, from, &mut queue);
+        }
+        while let Some(current_node_index) = queue.pop_front() {
+            if current_node_index == to {
+                break;
+            }
+            for &next_node_index in &self.edges[current_node_index] {
+                visit(next_node_index, current_node_index, &mut queue);
+            }
+        }
+
+        previous[to]?;
+        let mut path = vec![to];
+        let mut current_node_index = to;
+        while path.len() == 1 || current_node_index != from {
+            current_node_index = previous[current_node_index].unwrap();
+            path.push(current_node_index);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// All nodes that transitively depend on `node`, in breadth-first order.
+    /// `node` is only included if it is part of a cycle.
+    pub fn reverse_dependencies(&self, node: usize) -> Vec<usize> {
+        let mut dependents = vec![vec![]; self.nodes.len()];
+        for (from, edges) in self.edges.iter().enumerate() {
+            for &to in edges {
+                dependents[to].push(from);
+            }
+        }
+
+        let mut visited = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::from([node]);
+        let mut result = vec![];
+        while let Some(current_node_index) = queue.pop_front() {
+            for &next_node_index in &dependents[current_node_index] {
+                if !visited[next_node_index] {
+                    visited[next_node_index] = true;
+                    result.push(next_node_index);
+                    queue.push_back(next_node_index);
+                }
+            }
+        }
+        result
+    }
+
+    /// The binaries whose translation includes `node`, i.e., the files
+    /// programs start in that are `node` or depend on it. They have to be
+    /// verified again when the translation of `node` changes.
+    pub fn impacted_binaries(&self, node: usize) -> Vec<usize> {
+        let mut binaries = vec![];
+        if self.is_entry(node) {
+            binaries.push(node);
+        }
+        binaries.extend(
+            self.reverse_dependencies(node)
+                .into_iter()
+                .filter(|&i| i != node && self.is_entry(i)),
+        );
+        binaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{graph, plain_graph, unit};
+    use crate::DependencyGraph;
+
+    /// Two programs sharing `lex.c`
+    fn programs() -> DependencyGraph {
+        graph(
+            vec![
+                unit("/src/main.c", &["main"], &[]),
+                unit("/src/parse.c", &["parse"], &[]),
+                unit("/src/lex.c", &["lex"], &[]),
+                unit("/src/tools/dump.c", &["main"], &[]),
+            ],
+            &[(0, 1), (1, 2), (3, 2)],
+        )
+    }
+
+    #[test]
+    fn find_nodes() {
+        let mut graph = programs();
+        assert_eq!(graph.find_nodes("/src/lex.c"), vec![2]);
+        assert_eq!(graph.find_nodes("lex.c"), vec![2]);
+        assert_eq!(graph.find_nodes("lex.rs"), vec![2]);
+        assert_eq!(graph.find_nodes("dump"), vec![3]);
+        assert!(graph.find_nodes("missing.c").is_empty());
+
+        graph.nodes[0].project = Some("tool".to_string());
+        assert_eq!(graph.find_nodes("tool:main.c"), vec![0]);
+        // Not a project name, so the whole name is looked up
+        assert!(graph.find_nodes("src:main.c").is_empty());
+    }
+
+    #[test]
+    fn shortest_path() {
+        let graph = plain_graph(4, &[(0, 1), (1, 2), (2, 3), (0, 3), (3, 1)]);
+        assert_eq!(graph.shortest_path(0, 3), Some(vec![0, 3]));
+        assert_eq!(graph.shortest_path(0, 2), Some(vec![0, 1, 2]));
+        assert_eq!(graph.shortest_path(1, 0), None);
+        // A file only depends on itself through a cycle
+        assert_eq!(graph.shortest_path(1, 1), Some(vec![1, 2, 3, 1]));
+        assert_eq!(graph.shortest_path(0, 0), None);
+    }
+
+    #[test]
+    fn reverse_dependencies() {
+        let graph = programs();
+        assert_eq!(graph.reverse_dependencies(2), vec![1, 3, 0]);
+        assert!(graph.reverse_dependencies(0).is_empty());
+
+        let cycle = plain_graph(2, &[(0, 1), (1, 0)]);
+        assert_eq!(cycle.reverse_dependencies(0), vec![1, 0]);
+    }
+
+    #[test]
+    fn impacted_binaries() {
+        let graph = programs();
+        assert_eq!(graph.impacted_binaries(2), vec![3, 0]);
+        assert_eq!(graph.impacted_binaries(1), vec![0]);
+        assert_eq!(graph.impacted_binaries(3), vec![3]);
+    }
+}
@@ -0,0 +1,247 @@
+//! Classification of the functions and variables that units use but no unit
+//! defines: they either come from libc, from one of the libraries the units
+//! are linked with, or from nowhere at all.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{DependencyGraph, DependencySymbol};
+
+/// Functions and variables bound by the `libc` crate. Run
+/// `gen_libc_symbols.sh` to regenerate the list for another version.
+const LIBC_SYMBOLS: &str = include_str!("libc_symbols.txt");
+
+/// Libraries glibc splits its functions into. The `libc` crate binds some of
+/// their functions, so translations may need them even if no symbol is
+/// attributed to them.
+pub const LIBC_LIBRARIES: &[&str] = &["c", "m", "pthread", "dl", "rt", "util", "resolv"];
+
+fn libc_symbols() -> HashSet<&'static str> {
+    LIBC_SYMBOLS
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Where an unresolved symbol comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolSource {
+    /// libc, as bound by the `libc` crate
+    Libc,
+    /// One of the given libraries the unit is linked with. Which one is not
+    /// known without the libraries themselves.
+    Library(Vec<String>),
+    /// Neither libc nor any library the unit is linked with, including
+    /// symbols declared by the project itself that no unit defines
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnresolvedSymbol {
+    pub symbol: DependencySymbol,
+    /// The unit using the symbol
+    pub node: usize,
+    pub source: SymbolSource,
+}
+
+/// What a set of units needs from outside of the build.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExternalDependencies {
+    /// Libraries other than libc that may provide unresolved symbols
+    pub libraries: Vec<String>,
+    /// Symbols that nothing provides
+    pub missing: Vec<DependencySymbol>,
+}
+
+impl DependencyGraph {
+    /// The functions and variables used by each unit that no unit defines,
+    /// once per unit. Symbols not from libc are attributed to the libraries
+    /// the unit links with only if they are declared outside of the sources
+    /// of the project, like in the header of an installed library. The ones
+    /// the project declares itself are missing.
+    pub fn unresolved_symbols(&self) -> Vec<UnresolvedSymbol> {
+        self.unresolved_symbols_of(0..self.nodes.len())
+    }
+
+    /// The deepest directory containing the input files of all units of
+    /// each project.
+    fn source_roots(&self) -> HashMap<Option<&str>, PathBuf> {
+        let mut roots: HashMap<Option<&str>, PathBuf> = HashMap::new();
+        for node in &self.nodes {
+            let dir = Path::new(&node.input_path)
+                .parent()
+                .unwrap_or_else(|| Path::new(""));
+            roots
+                .entry(node.project.as_deref())
+                .and_modify(|root| {
+                    *root = root
+                        .ancestors()
+                        .find(|ancestor| dir.starts_with(ancestor))
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default();
+                })
+                .or_insert_with(|| dir.to_path_buf());
+        }
+        roots
+    }
+
+    fn unresolved_symbols_of(&self, nodes: impl Iterator<Item = usize>) -> Vec<UnresolvedSymbol> {
+        let defined = self
+            .nodes
+            .iter()
+            .flat_map(|node| &node.defined)
+            .filter(|symbol| symbol.kind.is_linked())
+            .map(|symbol| (symbol.name.as_str(), symbol.kind))
+            .collect::<HashSet<_>>();
+        let libc_symbols = libc_symbols();
+        let source_roots = self.source_roots();
+
+        let mut unresolved = vec![];
+        for i in nodes {
+            let node = &self.nodes[i];
+            let libraries = node
+                .link_libs
+                .iter()
+                .filter(|lib| *lib != "c")
+                .cloned()
+                .collect::<Vec<_>>();
+            let source_root = &source_roots[&node.project.as_deref()];
+            let mut seen = HashSet::new();
+            for symbol in &node.undefined {
+                let key = (symbol.name.as_str(), symbol.kind);
+                if !symbol.kind.is_linked() || defined.contains(&key) || !seen.insert(key) {
+                    continue;
+                }
+                let source = if libc_symbols.contains(symbol.name.as_str()) {
+                    SymbolSource::Libc
+                } else if !libraries.is_empty()
+                    && !symbol.path.is_empty()
+                    && !Path::new(&symbol.path).starts_with(source_root)
+                {
+                    SymbolSource::Library(libraries.clone())
+                } else {
+                    SymbolSource::Missing
+                };
+                unresolved.push(UnresolvedSymbol {
+                    symbol: symbol.clone(),
+                    node: i,
+                    source,
+                });
+            }
+        }
+        unresolved
+    }
+
+    /// The libraries and missing symbols the units `nodes` need, in the
+    /// order they are first used.
+    pub fn external_dependencies(&self, nodes: &[usize]) -> ExternalDependencies {
+        let mut dependencies = ExternalDependencies::default();
+        for unresolved in self.unresolved_symbols_of(nodes.iter().copied()) {
+            match unresolved.source {
+                SymbolSource::Libc => {}
+                SymbolSource::Library(libraries) => {
+                    for library in libraries {
+                        if !dependencies.libraries.contains(&library) {
+                            dependencies.libraries.push(library);
+                        }
+                    }
+                }
+                SymbolSource::Missing => {
+                    if !dependencies.missing.contains(&unresolved.symbol) {
+                        dependencies.missing.push(unresolved.symbol);
+                    }
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// For every unit a program starts in, what the binary built from it and the
+    /// units it depends on needs from outside of the build.
+    pub fn binary_dependencies(&self) -> Vec<(usize, ExternalDependencies)> {
+        self.entries()
+            .into_iter()
+            .map(|i| {
+                let nodes = self.reachable_nodes(vec![i]);
+                (i, self.external_dependencies(&nodes))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{graph, symbol, unit};
+    use crate::SymbolKind;
+
+    fn source(graph: &DependencyGraph, name: &str) -> SymbolSource {
+        graph
+            .unresolved_symbols()
+            .into_iter()
+            .find(|unresolved| unresolved.symbol.name == name)
+            .unwrap()
+            .source
+    }
+
+    /// `main.c` linked with `-lz`, using `printf` from libc, `deflate` from
+    /// zlib and `helper`, which the project declares but never defines
+    fn program(link_libs: &[&str]) -> DependencyGraph {
+        let mut main = unit("/proj/src/main.c", &["main"], &[]);
+        main.undefined = vec![
+            symbol("printf", "/usr/include/stdio.h", SymbolKind::Function),
+            symbol("deflate", "/usr/include/zlib.h", SymbolKind::Function),
+            symbol("helper", "/proj/include/helper.h", SymbolKind::Function),
+            symbol("parse", "/proj/src/parse.h", SymbolKind::Function),
+        ];
+        main.link_libs = link_libs.iter().map(|lib| lib.to_string()).collect();
+        let parse = unit("/proj/lib/parse.c", &["parse"], &[]);
+        graph(vec![main, parse], &[])
+    }
+
+    #[test]
+    fn classify() {
+        let graph = program(&["c", "z"]);
+        assert_eq!(source(&graph, "printf"), SymbolSource::Libc);
+        assert_eq!(
+            source(&graph, "deflate"),
+            SymbolSource::Library(vec!["z".to_string()])
+        );
+        assert_eq!(source(&graph, "helper"), SymbolSource::Missing);
+        // Defined by another unit, so not unresolved at all
+        assert!(graph
+            .unresolved_symbols()
+            .iter()
+            .all(|unresolved| unresolved.symbol.name != "parse"));
+    }
+
+    #[test]
+    fn without_libraries() {
+        let graph = program(&[]);
+        assert_eq!(source(&graph, "deflate"), SymbolSource::Missing);
+    }
+
+    #[test]
+    fn external_dependencies() {
+        let graph = program(&["z"]);
+        let dependencies = graph.external_dependencies(&[0, 1]);
+        assert_eq!(dependencies.libraries, vec!["z".to_string()]);
+        let missing = dependencies
+            .missing
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(missing, vec!["helper"]);
+    }
+
+    #[test]
+    fn libc_symbols_skip_comments() {
+        let symbols = libc_symbols();
+        assert!(symbols.contains("printf"));
+        assert!(symbols.contains("stdout"));
+        assert!(symbols.iter().all(|symbol| !symbol.starts_with('#')));
+    }
+}
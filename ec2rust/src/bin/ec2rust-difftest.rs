@@ -0,0 +1,12 @@
+use clap::Parser;
+use ec2rust::difftest::{run, Args};
+use std::process;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args = Args::parse();
+    if !run(args)? {
+        process::exit(1);
+    }
+    Ok(())
+}
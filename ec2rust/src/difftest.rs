@@ -0,0 +1,586 @@
+//! Differential testing of translated binaries against the original C executables.
+//!
+//! Test cases live in `TESTS/<binary>/<case>/`, where every case directory may contain
+//! - `args`: the command line arguments, quoted as in a shell
+//! - `stdin`: the data fed to standard input
+//! - `env`: `NAME=VALUE` lines added to the environment
+//! - `files/`: files copied into the working directory before the run
+//!
+//! Each case runs once with the C executable and once with the Rust binary, each in a
+//! fresh working directory. It passes if both runs produce the same stdout, stderr,
+//! exit status and working directory contents.
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use c2rust_transpile::compile_cmds::{get_compile_commands, CompileCmd};
+use deps_builder::{read_dependencies, DependencyInfo};
+use log::warn;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "ec2rust-difftest",
+    about = "Compare translated binaries against the original C executables",
+    long_about = None
+)]
+pub struct Args {
+    /// Translated Cargo workspace
+    #[clap(long, value_name = "DIR")]
+    workspace: PathBuf,
+
+    /// compile_commands.json of the original C build
+    #[clap(long, value_name = "FILE")]
+    compile_commands: PathBuf,
+
+    /// Directory of test cases, with a subdirectory per binary
+    #[clap(long, value_name = "DIR")]
+    tests: PathBuf,
+
+    /// Dependency information written during translation
+    #[clap(long, default_value = "./dependencies.json")]
+    dependency_file: PathBuf,
+
+    /// Build log or link database the translation inferred link steps from
+    #[clap(long, value_name = "FILE")]
+    link_log: Option<PathBuf>,
+
+    /// Compiler driver used to link the C executables
+    #[clap(long, default_value = "cc")]
+    cc: String,
+
+    /// Build the Rust binaries with the release profile
+    #[clap(long)]
+    release: bool,
+
+    /// Seconds after which a test run is killed
+    #[clap(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+/// Build both versions of every binary, run all test cases and print a report.
+/// Returns whether every case passed.
+pub fn run(args: Args) -> anyhow::Result<bool> {
+    let work_dir = env::temp_dir().join(format!("ec2rust-difftest-{}", process::id()));
+    fs::create_dir_all(&work_dir)?;
+    let res = run_in(&args, &work_dir);
+    if let Err(e) = fs::remove_dir_all(&work_dir) {
+        warn!("Could not remove {}: {}", work_dir.display(), e);
+    }
+    res
+}
+
+fn run_in(args: &Args, work_dir: &Path) -> anyhow::Result<bool> {
+    let c_binaries = build_c(args, &work_dir.join("c"))?;
+    let rust_bin_dir = build_rust(args)?;
+
+    let mut all_passed = true;
+    for entry in sorted_dir(&args.tests)? {
+        if !entry.is_dir() {
+            continue;
+        }
+        let binary = file_name(&entry);
+        let c_binary = match c_binaries.get(&binary) {
+            Some(c_binary) => c_binary,
+            None => {
+                println!(
+                    "{}: SKIPPED, no C executable with a main function named {}",
+                    binary, binary
+                );
+                continue;
+            }
+        };
+        let rust_binary = rust_bin_dir.join(&binary);
+        if !rust_binary.exists() {
+            println!(
+                "{}: SKIPPED, no Rust binary {}",
+                binary,
+                rust_binary.display()
+            );
+            continue;
+        }
+
+        let mut failures = vec![];
+        let mut num_cases = 0;
+        for case_dir in sorted_dir(&entry)? {
+            if !case_dir.is_dir() {
+                continue;
+            }
+            num_cases += 1;
+            let case = TestCase::read(&case_dir)?;
+            let run_dir = work_dir.join("run").join(&binary).join(&case.name);
+            let c_outcome = case.run(c_binary, &binary, &run_dir.join("c"), args.timeout)?;
+            let rust_outcome =
+                case.run(&rust_binary, &binary, &run_dir.join("rust"), args.timeout)?;
+            let differences = c_outcome.differences(&rust_outcome);
+            if !differences.is_empty() {
+                failures.push((case.name, differences));
+            }
+        }
+
+        all_passed &= failures.is_empty();
+        println!(
+            "{}: {}/{} passed",
+            binary,
+            num_cases - failures.len(),
+            num_cases
+        );
+        for (case, differences) in failures {
+            println!("  FAIL {}: {}", case, differences.join("; "));
+        }
+    }
+
+    Ok(all_passed)
+}
+
+/// Build the workspace and return the directory containing its binaries.
+fn build_rust(args: &Args) -> anyhow::Result<PathBuf> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["build", "--bins"]).current_dir(&args.workspace);
+    if args.release {
+        cmd.arg("--release");
+    }
+    let status = cmd.status().context("could not run cargo")?;
+    if !status.success() {
+        bail!("building {} failed: {}", args.workspace.display(), status);
+    }
+
+    // The binaries run in other directories, so make the path absolute
+    let workspace = args.workspace.canonicalize()?;
+    let target_dir = env::var_os("CARGO_TARGET_DIR")
+        .map(|dir| env::current_dir().unwrap_or_default().join(dir))
+        .unwrap_or_else(|| workspace.join("target"));
+    let profile = if args.release { "release" } else { "debug" };
+    Ok(target_dir.join(profile))
+}
+
+/// Rebuild the C executables in `out_dir`, one per translation unit with a
+/// `main` function, named like the Rust binary translated from that unit. As
+/// in the translated crate, each one links its `main` unit with all units of
+/// its link command that don't define `main`, using the options and libraries
+/// of the original link command.
+fn build_c(args: &Args, out_dir: &Path) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let dependencies = read_dependencies(&args.dependency_file)
+        .map_err(|e| anyhow!("reading {}: {}", args.dependency_file.display(), e))?;
+    let lcmds = get_compile_commands(
+        &args.compile_commands,
+        &None,
+        false,
+        false,
+        args.link_log.as_deref(),
+    )
+    .map_err(|e| anyhow!("reading {}: {}", args.compile_commands.display(), e))?;
+
+    let obj_dir = out_dir.join("obj");
+    fs::create_dir_all(&obj_dir)?;
+    let mut num_objects = 0;
+    let mut binaries = HashMap::new();
+    for lcmd in &lcmds {
+        let mut mains = vec![];
+        let mut objects = vec![];
+        for cmd in &lcmd.cmd_inputs {
+            num_objects += 1;
+            let object = obj_dir.join(format!("{}.o", num_objects));
+            compile(cmd, &object)?;
+            match find_dependency_info(&dependencies, cmd) {
+                Some(info) if info.is_main() => mains.push((binary_name(info), object)),
+                _ => objects.push(object),
+            }
+        }
+
+        for (name, main_object) in mains {
+            let binary = out_dir.join(&name);
+            let status = Command::new(&args.cc)
+                .arg("-o")
+                .arg(&binary)
+                .arg(&main_object)
+                .args(&objects)
+                .args(lcmd.link_args())
+                .status()
+                .with_context(|| format!("could not run {}", args.cc))?;
+            if !status.success() {
+                bail!("linking the C executable {} failed: {}", name, status);
+            }
+            binaries.insert(name, binary);
+        }
+    }
+    Ok(binaries)
+}
+
+/// Rerun the compile command `cmd` with its output redirected to `object`.
+fn compile(cmd: &CompileCmd, object: &Path) -> anyhow::Result<()> {
+    let args = cmd.args();
+    let (compiler, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("empty compile command for {}", cmd.file.display()))?;
+    let mut cmd_args = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                args.next();
+            }
+            "-c" => {}
+            _ if arg.starts_with("-o") => {}
+            _ => cmd_args.push(arg),
+        }
+    }
+
+    let status = Command::new(compiler)
+        .args(cmd_args)
+        .arg("-c")
+        .arg("-o")
+        .arg(object)
+        .current_dir(cmd.directory())
+        .status()
+        .with_context(|| format!("could not run {}", compiler))?;
+    if !status.success() {
+        bail!("compiling {} failed: {}", cmd.file.display(), status);
+    }
+    Ok(())
+}
+
+fn find_dependency_info<'a>(
+    dependencies: &'a [DependencyInfo],
+    cmd: &CompileCmd,
+) -> Option<&'a DependencyInfo> {
+    let input_path = cmd.abs_file();
+    let object_path = cmd.abs_output_file();
+    dependencies.iter().find(|info| {
+        Path::new(&info.input_path) == input_path
+            && info.object_path.as_ref().map(PathBuf::from) == object_path
+    })
+}
+
+/// The name of the Rust binary translated from the unit of `info`
+fn binary_name(info: &DependencyInfo) -> String {
+    file_name(Path::new(&info.output_path).with_extension("").as_path())
+}
+
+struct TestCase {
+    name: String,
+    args: Vec<String>,
+    stdin: Vec<u8>,
+    env: Vec<(String, String)>,
+    files: Option<PathBuf>,
+}
+
+impl TestCase {
+    fn read(dir: &Path) -> anyhow::Result<Self> {
+        let args = match fs::read_to_string(dir.join("args")) {
+            Ok(args) => shlex::split(&args)
+                .ok_or_else(|| anyhow!("could not split {}", dir.join("args").display()))?,
+            Err(_) => vec![],
+        };
+        let stdin = fs::read(dir.join("stdin")).unwrap_or_default();
+        let env = fs::read_to_string(dir.join("env"))
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (name, value) = line.split_once('=').ok_or_else(|| {
+                    anyhow!("{}: expected NAME=VALUE, got {}", dir.display(), line)
+                })?;
+                Ok((name.to_owned(), value.to_owned()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let files = Some(dir.join("files")).filter(|files| files.is_dir());
+
+        Ok(Self {
+            name: file_name(dir),
+            args,
+            stdin,
+            env,
+            files,
+        })
+    }
+
+    /// Run `binary` on this case in the fresh directory `run_dir`, using
+    /// `arg0` as its program name so both versions print the same name.
+    fn run(
+        &self,
+        binary: &Path,
+        arg0: &str,
+        run_dir: &Path,
+        timeout: u64,
+    ) -> anyhow::Result<Outcome> {
+        fs::create_dir_all(run_dir)?;
+        if let Some(files) = &self.files {
+            copy_dir(files, run_dir)?;
+        }
+
+        // The test runs in its own process group, so that processes it starts
+        // are killed with it on a timeout
+        let mut child = Command::new(binary)
+            .arg0(arg0)
+            .process_group(0)
+            .args(&self.args)
+            .envs(self.env.iter().cloned())
+            .current_dir(run_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("could not run {}", binary.display()))?;
+
+        // Feed and drain the pipes on separate threads so that a child filling
+        // one of them can't block us
+        let mut stdin = child.stdin.take().unwrap();
+        let input = self.stdin.clone();
+        let stdin_writer = thread::spawn(move || {
+            // The child may exit without reading all of its input
+            let _ = stdin.write_all(&input);
+        });
+        let stdout_reader = read_to_end(child.stdout.take().unwrap());
+        let stderr_reader = read_to_end(child.stderr.take().unwrap());
+
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                // SAFETY: the group's id is the child's pid, which stays
+                // reserved until we wait for the child
+                unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+                child.wait()?;
+                break None;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        stdin_writer.join().unwrap();
+
+        Ok(Outcome {
+            stdout: stdout_reader.join().unwrap(),
+            stderr: stderr_reader.join().unwrap(),
+            status,
+            files: read_dir_contents(run_dir)?,
+        })
+    }
+}
+
+/// What a test run produced
+struct Outcome {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    /// `None` if the run timed out
+    status: Option<ExitStatus>,
+    /// Contents of the working directory after the run, by relative path
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl Outcome {
+    /// Describe how `rust`, the outcome of the Rust binary, differs from this
+    /// outcome of the C executable.
+    fn differences(&self, rust: &Outcome) -> Vec<String> {
+        let mut res = vec![];
+        let describe = |status: &Option<ExitStatus>| match status {
+            Some(status) => status.to_string(),
+            None => "timed out".to_owned(),
+        };
+        if self.status != rust.status {
+            res.push(format!(
+                "C {}, Rust {}",
+                describe(&self.status),
+                describe(&rust.status)
+            ));
+        }
+        if self.stdout != rust.stdout {
+            res.push("stdout differs".to_owned());
+        }
+        if self.stderr != rust.stderr {
+            res.push("stderr differs".to_owned());
+        }
+        for (path, contents) in &self.files {
+            match rust.files.get(path) {
+                None => res.push(format!("{} only produced by C", path.display())),
+                Some(rust_contents) if rust_contents != contents => {
+                    res.push(format!("{} differs", path.display()))
+                }
+                Some(_) => {}
+            }
+        }
+        for path in rust.files.keys() {
+            if !self.files.contains_key(path) {
+                res.push(format!("{} only produced by Rust", path.display()));
+            }
+        }
+        res
+    }
+}
+
+fn read_to_end(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            fs::create_dir_all(&target)?;
+            copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read all files below `dir`, keyed by their path relative to `dir`
+fn read_dir_contents(dir: &Path) -> anyhow::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut res = BTreeMap::new();
+    let mut queue = vec![dir.to_path_buf()];
+    while let Some(current) = queue.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            let relative = path.strip_prefix(dir)?.to_path_buf();
+            if metadata.is_dir() {
+                queue.push(path);
+            } else if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&path)?;
+                res.insert(relative, target.to_string_lossy().into_owned().into_bytes());
+            } else {
+                res.insert(relative, fs::read(&path)?);
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn sorted_dir(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("could not read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ec2rust-difftest-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn outcome(code: i32, stdout: &str, files: &[(&str, &str)]) -> Outcome {
+        Outcome {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: vec![],
+            status: Some(ExitStatus::from_raw(code << 8)),
+            files: files
+                .iter()
+                .map(|(path, contents)| (PathBuf::from(path), contents.as_bytes().to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn differences() {
+        let c = outcome(0, "out", &[("a", "1"), ("b", "2")]);
+        assert!(c
+            .differences(&outcome(0, "out", &[("a", "1"), ("b", "2")]))
+            .is_empty());
+
+        let rust = outcome(1, "other", &[("a", "x"), ("c", "3")]);
+        assert_eq!(
+            c.differences(&rust),
+            vec![
+                "C exit status: 0, Rust exit status: 1",
+                "stdout differs",
+                "a differs",
+                "b only produced by C",
+                "c only produced by Rust",
+            ]
+        );
+
+        let timed_out = Outcome {
+            status: None,
+            ..outcome(0, "out", &[("a", "1"), ("b", "2")])
+        };
+        assert_eq!(
+            c.differences(&timed_out),
+            vec!["C exit status: 0, Rust timed out"]
+        );
+    }
+
+    #[test]
+    fn read_test_case() {
+        let dir = temp_dir("read").join("case");
+        fs::create_dir_all(dir.join("files")).unwrap();
+        fs::write(dir.join("args"), "-x 'two words'\n").unwrap();
+        fs::write(dir.join("stdin"), "input").unwrap();
+        fs::write(dir.join("env"), "A=1\n\nB=x=y\n").unwrap();
+
+        let case = TestCase::read(&dir).unwrap();
+        assert_eq!(case.name, "case");
+        assert_eq!(case.args, vec!["-x", "two words"]);
+        assert_eq!(case.stdin, b"input");
+        assert_eq!(
+            case.env,
+            vec![
+                ("A".to_owned(), "1".to_owned()),
+                ("B".to_owned(), "x=y".to_owned())
+            ]
+        );
+        assert_eq!(case.files, Some(dir.join("files")));
+
+        fs::write(dir.join("env"), "A\n").unwrap();
+        assert!(TestCase::read(&dir).is_err());
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn empty_test_case() {
+        let dir = temp_dir("empty");
+        let case = TestCase::read(&dir).unwrap();
+        assert!(case.args.is_empty());
+        assert!(case.stdin.is_empty());
+        assert!(case.env.is_empty());
+        assert_eq!(case.files, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timeout_kills_process_group() {
+        let dir = temp_dir("timeout");
+        let case = TestCase {
+            name: "sleep".to_owned(),
+            args: vec!["-c".to_owned(), "sleep 60 & wait".to_owned()],
+            stdin: vec![],
+            env: vec![],
+            files: None,
+        };
+        let start = Instant::now();
+        // The background `sleep` keeps the pipes open unless it is killed too
+        let outcome = case
+            .run(Path::new("/bin/sh"), "sh", &dir.join("run"), 1)
+            .unwrap();
+        assert_eq!(outcome.status, None);
+        assert!(start.elapsed() < Duration::from_secs(30));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
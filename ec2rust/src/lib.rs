@@ -3,7 +3,10 @@ use log::LevelFilter;
 use regex::Regex;
 use std::path::{Path, PathBuf};
 
-use c2rust_transpile::{Diagnostic, ReplaceMode, TranspilerConfig};
+use c2rust_transpile::{Diagnostic, ExternProject, ReplaceMode, TranspilerConfig};
+use deps_builder::{EntrySymbols, MatchStrategy};
+
+pub mod difftest;
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -104,6 +107,11 @@ pub struct Args {
     #[clap(long, requires = "output-dir")]
     emit_binaries: bool,
 
+    /// Give every binary a crate of its own and move the modules that several
+    /// binaries need into a common library crate (implies --emit-binaries)
+    #[clap(long, requires = "output-dir")]
+    common_crate: bool,
+
     /// Path to output directory. Rust sources will be emitted in DIR/src/ and build files will be emitted in DIR/.
     #[clap(short = 'o', long, value_name = "DIR")]
     output_dir: Option<PathBuf>,
@@ -124,6 +132,31 @@ pub struct Args {
     #[clap(long)]
     detect_binary: bool,
 
+    /// Treat functions matching PATTERN as entry functions besides main, like `*_main` for the
+    /// applets of a multi-call binary. Files defining one without a main get a Rust main calling it.
+    #[clap(long, value_name = "PATTERN", multiple = true, number_of_values = 1)]
+    entry_symbol: Vec<String>,
+
+    /// Emit a single binary running the applet argv[0] names instead of a binary per applet
+    /// (implies --emit-binaries)
+    #[clap(long, requires = "output-dir", conflicts_with = "common-crate")]
+    applet_dispatcher: bool,
+
+    /// Translate every configuration of a source file compiled several times with
+    /// different flags into its own module, named after the compile command's output
+    #[clap(long)]
+    all_configurations: bool,
+
+    /// Infer link steps from a build log (`make V=1` or `ninja -v` output) or a JSON
+    /// link database, creating a crate for every executable and library it links
+    #[clap(long, value_name = "FILE")]
+    link_log: Option<PathBuf>,
+
+    /// Compile files excluded by --filter or failing to translate as C from the
+    /// generated build.rs, producing a mixed C/Rust crate (implies -e/--emit-build-files)
+    #[clap(long)]
+    hybrid: bool,
+
     /// Emit files even if it causes existing files to be overwritten
     #[clap(long)]
     overwrite_existing: bool,
@@ -172,9 +205,37 @@ pub struct Args {
     #[clap(long, default_value = "./dependencies.json")]
     dependency_file: PathBuf,
 
-    /// Fuzz dependency checking level
-    #[clap(long, default_value_t = 0)]
-    fuzz_depends_level: usize,
+    /// Resolve symbols to the files of another project translated before into CRATE_DIR, with
+    /// the dependency file written then, and link its crate where they are used
+    #[clap(
+        long,
+        value_names = &["CRATE_DIR", "DEPENDENCY_FILE"],
+        multiple = true,
+        number_of_values = 2
+    )]
+    extern_project: Vec<PathBuf>,
+
+    /// Path to a file to write the JSON translation report to
+    #[clap(long, default_value = "./translation_report.json")]
+    report_file: PathBuf,
+
+    /// How to match the symbols a file uses to their definitions: exact,
+    /// same-header-stem, same-directory, include-graph, name-only or
+    /// link-scope. The levels 0 to 3 of --fuzz-depends-level are accepted as
+    /// well, with 3 meaning name-only.
+    #[clap(long, alias = "fuzz-depends-level", default_value = "exact")]
+    match_strategy: MatchStrategy,
+
+    /// Number of translation units to process concurrently (0 uses all available cores).
+    /// Clang parses one unit at a time, so this speeds up translation but
+    /// not the parsing of units missing from the --ast-cache-dir
+    #[clap(short = 'j', long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Cache Clang AST exporter output in DIR and reuse it while the source file,
+    /// its headers and its compile command are unchanged
+    #[clap(long, value_name = "DIR")]
+    ast_cache_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
@@ -186,6 +247,7 @@ enum InvalidCodes {
 
 pub fn process_args(args: Args) -> (TranspilerConfig, PathBuf, Vec<String>) {
     // Build a TranspilerConfig from the command line
+    #[allow(deprecated)]
     let mut tcfg = TranspilerConfig {
         dump_untyped_context: args.dump_untyped_clang_ast,
         dump_typed_context: args.dump_typed_clang_ast,
@@ -227,21 +289,43 @@ pub fn process_args(args: Args) -> (TranspilerConfig, PathBuf, Vec<String>) {
         reorganize_definitions: args.reorganize_definitions,
         emit_modules: args.emit_modules,
         emit_build_files: args.emit_build_files,
-        emit_binaries: args.emit_binaries,
+        emit_binaries: args.emit_binaries || args.common_crate || args.applet_dispatcher,
+        common_crate: args.common_crate,
         output_dir: args.output_dir,
         binaries: args.binary.unwrap_or_default(),
         detect_binaries: args.detect_binary,
+        entry_symbols: EntrySymbols::new(args.entry_symbol),
+        applet_dispatcher: args.applet_dispatcher,
+        translate_all_configurations: args.all_configurations,
+        link_log: args.link_log,
+        hybrid_build: args.hybrid,
         panic_on_translator_failure: args.invalid_code == InvalidCodes::Panic,
         replace_unsupported_decls: ReplaceMode::Extern,
         emit_no_std: args.emit_no_std,
         emit_no_lib: args.emit_no_lib,
         enabled_warnings: args.warn.into_iter().collect(),
         log_level: args.log_level,
+        jobs: args.jobs,
+        ast_cache_dir: args.ast_cache_dir,
         dependency_file: args.dependency_file,
-        fuzz_depends_level: args.fuzz_depends_level,
+        extern_projects: args
+            .extern_project
+            .chunks(2)
+            .map(|project| ExternProject {
+                crate_dir: project[0].canonicalize().unwrap_or_else(|_| {
+                    panic!("Could not find crate directory: {}", project[0].display())
+                }),
+                dependency_file: project[1].clone(),
+            })
+            .collect(),
+        report_file: args.report_file,
+        match_strategy: args.match_strategy,
+        // `--fuzz-depends-level` is an alias of `--match-strategy`
+        fuzz_depends_level: None,
     };
-    // binaries imply emit-build-files
-    if !tcfg.binaries.is_empty() || tcfg.detect_binaries || tcfg.emit_binaries {
+    // binaries and hybrid builds imply emit-build-files
+    if !tcfg.binaries.is_empty() || tcfg.detect_binaries || tcfg.emit_binaries || tcfg.hybrid_build
+    {
         tcfg.emit_build_files = true
     };
     // emit-build-files implies emit-modules